@@ -0,0 +1,6 @@
+ALTER TABLE "public"."invitations" ADD COLUMN "expires_at" timestamp(3);
+
+-- Pending invitations created before the expiration was introduced get a fresh week
+UPDATE "public"."invitations" SET "expires_at" = CURRENT_TIMESTAMP + interval '7 days';
+
+ALTER TABLE "public"."invitations" ALTER COLUMN "expires_at" SET NOT NULL;
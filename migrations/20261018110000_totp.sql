@@ -0,0 +1,30 @@
+ALTER TABLE "public"."users" ADD COLUMN "totp_secret" text;
+ALTER TABLE "public"."users" ADD COLUMN "totp_enabled_at" timestamp(3);
+
+CREATE TABLE "public"."totp_recovery_codes" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" uuid NOT NULL,
+    "encrypted_code" text NOT NULL,
+    "used_at" timestamp(3),
+    PRIMARY KEY ("id")
+);
+
+CREATE TABLE "public"."totp_challenges" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" uuid NOT NULL,
+    "code" text NOT NULL UNIQUE,
+    "login_challenge" text NOT NULL,
+    "expires_at" timestamp(3) NOT NULL,
+    "used_at" timestamp(3),
+    PRIMARY KEY ("id")
+);
+
+ALTER TABLE "public"."totp_recovery_codes" ADD FOREIGN KEY ("user_id") REFERENCES "public"."users"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+ALTER TABLE "public"."totp_challenges" ADD FOREIGN KEY ("user_id") REFERENCES "public"."users"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+SELECT manage_updated_at('totp_recovery_codes');
+SELECT manage_updated_at('totp_challenges');
@@ -0,0 +1,28 @@
+CREATE TABLE "public"."webauthn_credentials" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" uuid NOT NULL,
+    "credential_id" bytea NOT NULL UNIQUE,
+    "name" text NOT NULL,
+    "passkey" text NOT NULL,
+    "last_used_at" timestamp(3),
+    PRIMARY KEY ("id")
+);
+
+CREATE TABLE "public"."webauthn_challenges" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" uuid NOT NULL,
+    "state" text NOT NULL,
+    "login_challenge" text,
+    "expires_at" timestamp(3) NOT NULL,
+    PRIMARY KEY ("id")
+);
+
+ALTER TABLE "public"."webauthn_credentials" ADD FOREIGN KEY ("user_id") REFERENCES "public"."users"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+ALTER TABLE "public"."webauthn_challenges" ADD FOREIGN KEY ("user_id") REFERENCES "public"."users"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+SELECT manage_updated_at('webauthn_credentials');
+SELECT manage_updated_at('webauthn_challenges');
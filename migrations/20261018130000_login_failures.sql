@@ -0,0 +1,14 @@
+CREATE TABLE "public"."login_failures" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "kind" text NOT NULL,
+    "identifier" text NOT NULL,
+    "failed_attempts" integer NOT NULL DEFAULT 0,
+    "last_failed_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "locked_until" timestamp(3),
+    PRIMARY KEY ("id"),
+    UNIQUE ("kind", "identifier")
+);
+
+SELECT manage_updated_at('login_failures');
@@ -0,0 +1,4 @@
+ALTER TABLE "public"."idp_clients" ADD COLUMN "skip_consent" boolean NOT NULL DEFAULT false;
+
+-- Existing clients were never asked for consent, they stay trusted
+UPDATE "public"."idp_clients" SET "skip_consent" = true;
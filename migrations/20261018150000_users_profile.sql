@@ -0,0 +1,4 @@
+ALTER TABLE "public"."users" ADD COLUMN "name" text;
+ALTER TABLE "public"."users" ADD COLUMN "locale" text;
+ALTER TABLE "public"."users" ADD COLUMN "timezone" text;
+ALTER TABLE "public"."users" ADD COLUMN "metadata" jsonb NOT NULL DEFAULT '{}';
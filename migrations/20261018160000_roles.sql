@@ -0,0 +1,55 @@
+CREATE TABLE "public"."roles" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "name" text NOT NULL UNIQUE,
+    "description" text,
+    PRIMARY KEY ("id")
+);
+
+CREATE TABLE "public"."permissions" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "name" text NOT NULL UNIQUE,
+    PRIMARY KEY ("id")
+);
+
+CREATE TABLE "public"."role_permissions" (
+    "role_id" uuid NOT NULL,
+    "permission_id" uuid NOT NULL,
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY ("role_id", "permission_id")
+);
+
+-- A role assigned without client applies to all the clients
+CREATE TABLE "public"."user_roles" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" uuid NOT NULL,
+    "role_id" uuid NOT NULL,
+    "idp_client_id" text,
+    PRIMARY KEY ("id")
+);
+
+CREATE UNIQUE INDEX "user_roles_user_id_role_id_idp_client_id_key" ON "public"."user_roles" ("user_id", "role_id", COALESCE("idp_client_id", ''));
+
+ALTER TABLE "public"."role_permissions" ADD FOREIGN KEY ("role_id") REFERENCES "public"."roles"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE "public"."role_permissions" ADD FOREIGN KEY ("permission_id") REFERENCES "public"."permissions"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE "public"."user_roles" ADD FOREIGN KEY ("user_id") REFERENCES "public"."users"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE "public"."user_roles" ADD FOREIGN KEY ("role_id") REFERENCES "public"."roles"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE "public"."user_roles" ADD FOREIGN KEY ("idp_client_id") REFERENCES "public"."idp_clients"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- Names of the roles assigned to the user once the invitation is completed
+ALTER TABLE "public"."invitations" ADD COLUMN "roles" text[] NOT NULL DEFAULT '{}';
+
+SELECT manage_updated_at('roles');
+
+SELECT manage_updated_at('permissions');
+
+SELECT manage_updated_at('user_roles');
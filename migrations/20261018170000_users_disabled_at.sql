@@ -0,0 +1 @@
+ALTER TABLE "public"."users" ADD COLUMN "disabled_at" timestamp(3);
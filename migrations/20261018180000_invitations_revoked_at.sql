@@ -0,0 +1,6 @@
+ALTER TABLE "public"."invitations" ADD COLUMN "revoked_at" timestamp(3);
+
+-- An email can be invited again once its previous invitation has been used or revoked
+ALTER TABLE "public"."invitations" DROP CONSTRAINT "invitations_email_key";
+
+CREATE UNIQUE INDEX "invitations_pending_email_key" ON "public"."invitations"("email") WHERE "used_at" IS NULL AND "revoked_at" IS NULL;
@@ -0,0 +1,6 @@
+-- The urls the invitations and password resets of a client can redirect to, the `/` set
+-- until now isn't a valid redirect url: use `zagreus set-redirect-uris` to register them
+UPDATE "public"."idp_clients" SET "redirect_uris" = array_remove(COALESCE("redirect_uris", '{}'), '/');
+
+ALTER TABLE "public"."idp_clients" ALTER COLUMN "redirect_uris" SET DEFAULT '{}';
+ALTER TABLE "public"."idp_clients" ALTER COLUMN "redirect_uris" SET NOT NULL;
@@ -0,0 +1,29 @@
+CREATE TABLE "public"."rate_limit_buckets" (
+    "id" uuid DEFAULT uuid_generate_v4 (),
+    "created_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "key" text NOT NULL,
+    "tokens" double precision NOT NULL,
+    "allowed" boolean NOT NULL,
+    "refilled_at" timestamp(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY ("id"),
+    UNIQUE ("key")
+);
+
+SELECT manage_updated_at('rate_limit_buckets');
+
+-- Tokens of a bucket once refilled according to the time elapsed since its last refill
+CREATE OR REPLACE FUNCTION refilled_tokens(
+        _tokens double precision,
+        _refilled_at timestamp,
+        _capacity double precision,
+        _refill_per_second double precision
+    ) RETURNS double precision AS $$
+SELECT LEAST(
+        _capacity,
+        _tokens + EXTRACT(
+            EPOCH
+            FROM (CURRENT_TIMESTAMP::timestamp - _refilled_at)
+        )::double precision * _refill_per_second
+    );
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,7 @@
+-- Only the (hex encoded SHA-256) hashes of the codes are persisted from now on,
+-- the links already sent keep working as they are looked up by the hash of their code
+UPDATE "public"."invitations"
+SET "code" = encode(sha256(convert_to("code", 'UTF8')), 'hex');
+
+UPDATE "public"."password_resets"
+SET "code" = encode(sha256(convert_to("code", 'UTF8')), 'hex');
@@ -0,0 +1,6 @@
+ALTER TABLE "public"."password_resets" ADD COLUMN "expires_at" timestamp(3);
+
+-- Pending password resets created before the expiration was introduced get a fresh hour
+UPDATE "public"."password_resets" SET "expires_at" = CURRENT_TIMESTAMP + interval '1 hour';
+
+ALTER TABLE "public"."password_resets" ALTER COLUMN "expires_at" SET NOT NULL;
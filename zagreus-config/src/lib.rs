@@ -5,7 +5,7 @@
 use anyhow::Result;
 use itconfig::config;
 
-pub static CORS_ALLOWED_METHODS: &[&str] = &["GET", "POST", "PUT"];
+pub static CORS_ALLOWED_METHODS: &[&str] = &["GET", "POST", "PUT", "DELETE"];
 
 macro_rules! define_ownable_strings_static {
     (static $global:ident, fn $name:ident, $values:expr) => {
@@ -66,6 +66,110 @@ config! {
         PUBLIC_TOKEN_URL < ( HYDRA_PUBLIC_API_URL, "/oauth2/token" ),
     },
     #[allow(non_snake_case)]
+    ADMIN {
+        // Scope required by the client credentials tokens to use the admin api
+        SCOPE: String => "zagreus:admin",
+        // Permission required by the users to use the admin api
+        PERMISSION: String => "zagreus:admin",
+    },
+    #[allow(non_snake_case)]
+    CLAIMS {
+        // Json object of the extra claims added per granted scope: `{ "<scope>": { "<claim>": <value> } }`
+        CUSTOM: Option<String>,
+    },
+    #[allow(non_snake_case)]
+    CODE {
+        // Number of characters of the invitation and password reset codes (at least 16)
+        LENGTH: usize => 32,
+    },
+    #[allow(non_snake_case)]
+    INVITATION {
+        // Number of hours an invitation code stays valid (defaults to a week)
+        TTL_HOURS: i64 => 168,
+    },
+    #[allow(non_snake_case)]
+    PASSWORD_RESET {
+        // Number of minutes a password reset code stays valid (defaults to an hour)
+        TTL_MINUTES: i64 => 60,
+    },
+    #[allow(non_snake_case)]
+    PASSWORD {
+        // Lengths in characters
+        MIN_LENGTH: usize => 8,
+        MAX_LENGTH: usize => 128,
+        REQUIRE_LOWERCASE: bool => true,
+        REQUIRE_UPPERCASE: bool => true,
+        REQUIRE_DIGIT: bool => true,
+        REQUIRE_SYMBOL: bool => false,
+        // Estimated strength required, from 0 (anything goes) to 4
+        MIN_STRENGTH: u8 => 3,
+        // Comma separated words the passwords can't contain (case insensitive), the local part of the email is always forbidden
+        FORBIDDEN_SUBSTRINGS: Option<String>,
+        // File of breached passwords, one per line, in clear or as SHA-1 hashes (`<hash>:<count>` lines of Have I Been Pwned)
+        BREACHED_LIST_PATH: Option<String>,
+    },
+    #[allow(non_snake_case)]
+    CSRF {
+        // Key signing the csrf tokens
+        SECRET: String,
+        // Comma separated paths not protected against csrf (machine-to-machine endpoints)
+        EXEMPT_PATHS: Option<String>,
+    },
+    #[allow(non_snake_case)]
+    SECURITY_HEADERS {
+        // `{nonce}` is replaced by the nonce of each request, exposed to the templates as `csp_nonce`.
+        // AlpineJS evaluates its expressions, hence `'unsafe-eval'`
+        CONTENT_SECURITY_POLICY: String => "default-src 'self'; script-src 'self' 'nonce-{nonce}' 'unsafe-eval'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; object-src 'none'; base-uri 'self'; frame-ancestors 'none'",
+        FRAME_OPTIONS: String => "DENY",
+        // Only honoured by the browsers over https
+        STRICT_TRANSPORT_SECURITY: String => "max-age=31536000; includeSubDomains",
+        REFERRER_POLICY: String => "same-origin",
+        PERMISSIONS_POLICY: String => "camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+    },
+    #[allow(non_snake_case)]
+    RATE_LIMIT {
+        // Where the request counts are kept, `memory` or `postgres` (shared by all the instances)
+        BACKEND: String => "memory",
+        // Comma separated addresses (or networks, `10.0.0.0/8`) of the reverse proxies whose `X-Forwarded-For` header is trusted
+        TRUSTED_PROXIES: Option<String>,
+        // Requests allowed per minute and per ip address (per /64 network for IPv6) on each route, `0` disables the limit
+        LOGIN_PER_MINUTE: u32 => 10,
+        INVITATION_PER_MINUTE: u32 => 10,
+        PASSWORD_RESET_PER_MINUTE: u32 => 5,
+        // The invitation and password reset views, which look up the code of the url
+        VIEW_PER_MINUTE: u32 => 20,
+    },
+    #[allow(non_snake_case)]
+    LOGIN {
+        // Failed attempts allowed per email before it gets locked
+        MAX_ATTEMPTS: i32 => 5,
+        // Failed attempts allowed per ip address before it gets locked
+        MAX_IP_ATTEMPTS: i32 => 50,
+        LOCKOUT_MINUTES: i64 => 15,
+    },
+    #[allow(non_snake_case)]
+    MAILER {
+        // Either `smtp` or `stdout` (prints the emails instead of sending them), required so that
+        // a production instance can't silently print the emails
+        TRANSPORT: String,
+        FROM: String => "Zagreus <no-reply@localhost>",
+        SMTP_HOST: Option<String>,
+        SMTP_PORT: Option<u16>,
+        SMTP_USERNAME: Option<String>,
+        SMTP_PASSWORD: Option<String>,
+    },
+    #[allow(non_snake_case)]
+    TOTP {
+        // Name displayed by the authenticator apps
+        ISSUER: String => "Zagreus",
+    },
+    #[allow(non_snake_case)]
+    WEBAUTHN {
+        // Relying party id, defaults to the host of `URL`
+        RP_ID: Option<String>,
+        RP_NAME: String => "Zagreus",
+    },
+    #[allow(non_snake_case)]
     STATIC_PATH: &'static str,
     #[allow(non_snake_case)]
     TEMPLATES_PATH: &'static str,
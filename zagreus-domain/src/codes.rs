@@ -0,0 +1,31 @@
+/// The codes of the links sent by email (invitations and password resets) are only persisted
+/// hashed, so that a copy of the database doesn't give access to the pending links.
+/// A fast hash is enough: unlike passwords, the codes are random and long.
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// The hex encoded SHA-256 hash of the code.
+pub fn hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+/// Whether the code matches the hash, compared in constant time.
+pub fn verify(code: &str, code_hash: &str) -> bool {
+    hash(code).as_bytes().ct_eq(code_hash.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, verify};
+
+    #[test]
+    fn it_hashes_the_codes() {
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(verify("abc", hash("abc").as_str()));
+        assert!(!verify("abd", hash("abc").as_str()));
+        assert!(!verify("abc", "abc"));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod codes;
 pub mod db;
 pub mod models;
+pub mod repositories;
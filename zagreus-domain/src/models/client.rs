@@ -1,23 +1,95 @@
 use anyhow::Result;
-use sqlx::query;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as};
 
 use crate::db::PgPool;
 
-#[derive(Debug)]
-pub struct Client;
+#[derive(Clone, Debug)]
+pub struct Client {
+    pub id: String,
+    pub name: String,
+    /// The urls the invitations and password resets of the client can redirect to.
+    pub redirect_uris: Vec<String>,
+    /// Trusted clients are granted the requested scopes without showing the consent screen.
+    pub skip_consent: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
 
 impl Client {
-    pub async fn create(pool: &PgPool, client_name: &str) -> Result<()> {
+    pub async fn get_by_id(pool: &PgPool, id: &str) -> Result<Option<Client>> {
+        let client = query_as!(
+            Client,
+            "
+                SELECT id, name, redirect_uris, skip_consent, created_at, updated_at
+                FROM idp_clients
+                WHERE id = $1
+            ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    /// Only the registered redirect uris are allowed, as is.
+    pub fn is_redirect_uri_allowed(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|uri| uri == redirect_uri)
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        client_name: &str,
+        redirect_uris: &[String],
+        skip_consent: bool,
+    ) -> Result<()> {
         query(
             "
-                    INSERT INTO idp_clients (id, name, redirect_uris)
-                    VALUES ($1, $1, '{/}');
+                    INSERT INTO idp_clients (id, name, redirect_uris, skip_consent)
+                    VALUES ($1, $1, $2, $3);
                 ",
         )
         .bind(client_name)
+        .bind(redirect_uris)
+        .bind(skip_consent)
         .execute(pool)
         .await?;
 
         Ok(())
     }
+
+    pub async fn update_redirect_uris(
+        pool: &PgPool,
+        id: &str,
+        redirect_uris: &[String],
+    ) -> Result<Option<String>> {
+        let client = query!(
+            "
+                UPDATE idp_clients SET redirect_uris = $1
+                WHERE id = $2
+                RETURNING id
+            ",
+            redirect_uris,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(client.map(|client| client.id))
+    }
+
+    pub async fn delete(pool: &PgPool, id: &str) -> Result<bool> {
+        let result = query!(
+            "
+                DELETE FROM idp_clients
+                WHERE id = $1
+            ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
 }
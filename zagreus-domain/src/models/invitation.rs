@@ -1,28 +1,58 @@
 use anyhow::Result;
-use chrono::NaiveDateTime;
-use sqlx::{query, query_as};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as, Executor, Postgres};
 use uuid::Uuid;
 
+use crate::codes;
 use crate::db::PgPool;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Invitation {
     pub id: Uuid,
     pub email: String,
-    pub code: String,
+    /// The code itself is only known by the invited user, see `codes`.
+    pub code_hash: String,
     pub redirect_uri: String,
     pub idp_client_id: String,
     pub used_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    /// Names of the roles assigned to the user once the invitation is completed.
+    pub roles: Vec<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+/// An invitation to create, see `Invitation::create_all`.
+#[derive(Debug)]
+pub struct NewInvitation {
+    pub email: String,
+    pub code: String,
+    pub idp_client_id: String,
+    pub redirect_uri: String,
+    pub expires_at: NaiveDateTime,
+    pub roles: Vec<String>,
+}
+
 impl Invitation {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Neither used nor revoked, it may have expired though.
+    pub fn is_pending(&self) -> bool {
+        self.used_at.is_none() && !self.is_revoked()
+    }
+
     pub async fn get_all(pool: &PgPool) -> Result<Vec<Invitation>> {
         let invitations = query_as!(
             Invitation,
             "
-                SELECT id, email, code, redirect_uri, idp_client_id, used_at, created_at, updated_at
+                SELECT id, email, code AS code_hash, redirect_uri, idp_client_id, used_at, expires_at, revoked_at, roles, created_at, updated_at
                 FROM invitations
             ",
         )
@@ -32,15 +62,16 @@ impl Invitation {
         Ok(invitations)
     }
 
+    /// The lookup is made on the hash, its timing doesn't tell anything about the code.
     pub async fn get_by_code(pool: &PgPool, code: &str) -> Result<Option<Invitation>> {
         let invitation = query_as!(
             Invitation,
             "
-                SELECT id, email, code, redirect_uri, idp_client_id, used_at, created_at, updated_at
+                SELECT id, email, code AS code_hash, redirect_uri, idp_client_id, used_at, expires_at, revoked_at, roles, created_at, updated_at
                 FROM invitations
                 WHERE code = $1
             ",
-            code
+            codes::hash(code)
         )
         .fetch_optional(pool)
         .await?;
@@ -48,43 +79,178 @@ impl Invitation {
         Ok(invitation)
     }
 
-    pub async fn create(
-        pool: &PgPool,
+    pub async fn get_by_id(pool: &PgPool, id: &Uuid) -> Result<Option<Invitation>> {
+        let invitation = query_as!(
+            Invitation,
+            "
+                SELECT id, email, code AS code_hash, redirect_uri, idp_client_id, used_at, expires_at, revoked_at, roles, created_at, updated_at
+                FROM invitations
+                WHERE id = $1
+            ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(invitation)
+    }
+
+    /// There can only be one pending invitation per email.
+    pub async fn get_pending_by_email(pool: &PgPool, email: &str) -> Result<Option<Invitation>> {
+        let invitation = query_as!(
+            Invitation,
+            "
+                SELECT id, email, code AS code_hash, redirect_uri, idp_client_id, used_at, expires_at, revoked_at, roles, created_at, updated_at
+                FROM invitations
+                WHERE email = $1 AND used_at IS NULL AND revoked_at IS NULL
+            ",
+            email
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(invitation)
+    }
+
+    pub async fn create<'e, E>(
+        executor: E,
         email: &str,
         code: &str,
         idp_client_id: &str,
         redirect_uri: &str,
-    ) -> Result<Uuid> {
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Uuid>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
         let invitation = query!(
             "
-                INSERT INTO invitations(email, code, idp_client_id, redirect_uri)
-                VALUES ($1, $2, $3, $4)
+                INSERT INTO invitations(email, code, idp_client_id, redirect_uri, expires_at, roles)
+                VALUES ($1, $2, $3, $4, $5, $6)
                 RETURNING id
             ",
             email,
-            code,
+            codes::hash(code),
             idp_client_id,
-            redirect_uri
+            redirect_uri,
+            expires_at,
+            roles
         )
-        .fetch_one(pool)
+        .fetch_one(executor)
         .await?;
 
         Ok(invitation.id)
     }
 
-    pub async fn update_used_at(
-        pool: &PgPool,
-        code: &str,
+    /// Creates all the invitations at once, or none of them. An expired pending invitation
+    /// for the same email is refreshed instead.
+    pub async fn create_all(pool: &PgPool, invitations: &[NewInvitation]) -> Result<Vec<Uuid>> {
+        let mut transaction = pool.begin().await?;
+
+        let mut ids = Vec::with_capacity(invitations.len());
+
+        for invitation in invitations {
+            let created_invitation = query!(
+                "
+                    INSERT INTO invitations(email, code, idp_client_id, redirect_uri, expires_at, roles)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (email) WHERE used_at IS NULL AND revoked_at IS NULL
+                    DO UPDATE SET code = $2, idp_client_id = $3, redirect_uri = $4, expires_at = $5, roles = $6
+                    WHERE invitations.expires_at <= CURRENT_TIMESTAMP
+                    RETURNING id
+                ",
+                invitation.email,
+                codes::hash(invitation.code.as_str()),
+                invitation.idp_client_id,
+                invitation.redirect_uri,
+                invitation.expires_at,
+                &invitation.roles
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+
+            ids.push(created_invitation.id);
+        }
+
+        transaction.commit().await?;
+
+        Ok(ids)
+    }
+
+    /// Marks a pending invitation as used, returns `None` if it has been used (or revoked)
+    /// already. Concurrent calls wait for each other, so an invitation is only used once.
+    pub async fn update_used_at<'e, E>(
+        executor: E,
+        id: &Uuid,
         used_at: &NaiveDateTime,
-    ) -> Result<Option<Uuid>> {
+    ) -> Result<Option<Uuid>>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
         let invitation = query!(
             "
                 UPDATE invitations SET used_at = $1
-                WHERE code = $2
+                WHERE id = $2 AND used_at IS NULL AND revoked_at IS NULL
                 RETURNING id
             ",
             used_at,
-            code,
+            id,
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(invitation.map(|invitation| invitation.id))
+    }
+
+    /// Replaces the code (the previous invitation link doesn't work anymore) and the
+    /// invitation details of a pending invitation.
+    pub async fn refresh<'e, E>(
+        executor: E,
+        id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Option<Uuid>>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let invitation = query!(
+            "
+                UPDATE invitations
+                SET code = $1, idp_client_id = $2, redirect_uri = $3, expires_at = $4, roles = $5
+                WHERE id = $6 AND used_at IS NULL AND revoked_at IS NULL
+                RETURNING id
+            ",
+            codes::hash(code),
+            idp_client_id,
+            redirect_uri,
+            expires_at,
+            roles,
+            id,
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(invitation.map(|invitation| invitation.id))
+    }
+
+    /// Revokes a pending invitation, returns `None` if it has been used (or revoked) already.
+    pub async fn update_revoked_at(
+        pool: &PgPool,
+        id: &Uuid,
+        revoked_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let invitation = query!(
+            "
+                UPDATE invitations SET revoked_at = $1
+                WHERE id = $2 AND used_at IS NULL AND revoked_at IS NULL
+                RETURNING id
+            ",
+            revoked_at,
+            id,
         )
         .fetch_optional(pool)
         .await?;
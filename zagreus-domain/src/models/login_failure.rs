@@ -0,0 +1,130 @@
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// What the failed login attempts are counted against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginFailureKind {
+    Email,
+    Ip,
+}
+
+impl LoginFailureKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginFailureKind::Email => "email",
+            LoginFailureKind::Ip => "ip",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoginFailure {
+    pub id: Uuid,
+    pub kind: String,
+    pub identifier: String,
+    pub failed_attempts: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl LoginFailure {
+    pub fn is_locked(&self) -> bool {
+        matches!(self.locked_until, Some(locked_until) if locked_until > Utc::now().naive_utc())
+    }
+
+    pub async fn get(
+        pool: &PgPool,
+        kind: LoginFailureKind,
+        identifier: &str,
+    ) -> Result<Option<LoginFailure>> {
+        let login_failure = query_as!(
+            LoginFailure,
+            "
+                SELECT id, kind, identifier, failed_attempts, last_failed_at, locked_until, created_at, updated_at
+                FROM login_failures
+                WHERE kind = $1 AND identifier = $2
+            ",
+            kind.as_str(),
+            identifier
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(login_failure)
+    }
+
+    /// Counts a new failed attempt, the counter starts over when the previous
+    /// failure happened before `window_start`.
+    pub async fn increment(
+        pool: &PgPool,
+        kind: LoginFailureKind,
+        identifier: &str,
+        window_start: &NaiveDateTime,
+    ) -> Result<LoginFailure> {
+        let login_failure = query_as!(
+            LoginFailure,
+            "
+                INSERT INTO login_failures(kind, identifier, failed_attempts)
+                VALUES ($1, $2, 1)
+                ON CONFLICT (kind, identifier) DO UPDATE
+                SET failed_attempts = CASE
+                        WHEN login_failures.last_failed_at < $3 THEN 1
+                        ELSE login_failures.failed_attempts + 1
+                    END,
+                    last_failed_at = CURRENT_TIMESTAMP
+                RETURNING id, kind, identifier, failed_attempts, last_failed_at, locked_until, created_at, updated_at
+            ",
+            kind.as_str(),
+            identifier,
+            window_start
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(login_failure)
+    }
+
+    pub async fn update_locked_until(
+        pool: &PgPool,
+        kind: LoginFailureKind,
+        identifier: &str,
+        locked_until: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let login_failure = query!(
+            "
+                UPDATE login_failures SET locked_until = $1
+                WHERE kind = $2 AND identifier = $3
+                RETURNING id
+            ",
+            locked_until,
+            kind.as_str(),
+            identifier,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(login_failure.map(|login_failure| login_failure.id))
+    }
+
+    /// Forgets the failed attempts (and the lock), returns `false` if there was nothing to forget.
+    pub async fn delete(pool: &PgPool, kind: LoginFailureKind, identifier: &str) -> Result<bool> {
+        let result = query!(
+            "
+                DELETE FROM login_failures
+                WHERE kind = $1 AND identifier = $2
+            ",
+            kind.as_str(),
+            identifier,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -1,3 +1,13 @@
 pub mod client;
 pub mod invitation;
+pub mod login_failure;
+pub mod password_reset;
+pub mod permission;
+pub mod rate_limit_bucket;
+pub mod role;
+pub mod totp_challenge;
+pub mod totp_recovery_code;
 pub mod user;
+pub mod user_role;
+pub mod webauthn_challenge;
+pub mod webauthn_credential;
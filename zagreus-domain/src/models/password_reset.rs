@@ -0,0 +1,103 @@
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as, Executor, Postgres};
+use uuid::Uuid;
+
+use crate::codes;
+use crate::db::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// The code itself is only known by the user, see `codes`.
+    pub code_hash: String,
+    pub redirect_uri: String,
+    pub idp_client_id: String,
+    pub used_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl PasswordReset {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+
+    /// The lookup is made on the hash, its timing doesn't tell anything about the code.
+    pub async fn get_by_code(pool: &PgPool, code: &str) -> Result<Option<PasswordReset>> {
+        let password_reset = query_as!(
+            PasswordReset,
+            "
+                SELECT id, user_id, code AS code_hash, redirect_uri, idp_client_id, used_at, expires_at, created_at, updated_at
+                FROM password_resets
+                WHERE code = $1
+            ",
+            codes::hash(code)
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(password_reset)
+    }
+
+    /// Creates a password reset for the user, a user can only have one password reset
+    /// at a time so any previous reset is replaced (and its code invalidated).
+    pub async fn create(
+        pool: &PgPool,
+        user_id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        let password_reset = query!(
+            "
+                INSERT INTO password_resets(user_id, code, idp_client_id, redirect_uri, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_id) DO UPDATE
+                SET code = EXCLUDED.code,
+                    idp_client_id = EXCLUDED.idp_client_id,
+                    redirect_uri = EXCLUDED.redirect_uri,
+                    expires_at = EXCLUDED.expires_at,
+                    used_at = NULL,
+                    created_at = CURRENT_TIMESTAMP
+                RETURNING id
+            ",
+            user_id,
+            codes::hash(code),
+            idp_client_id,
+            redirect_uri,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(password_reset.id)
+    }
+
+    /// Claims the password reset, `None` when it has already been used (or has expired).
+    pub async fn update_used_at<'e, E>(
+        executor: E,
+        code: &str,
+        used_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let password_reset = query!(
+            "
+                UPDATE password_resets SET used_at = $1
+                WHERE code = $2 AND used_at IS NULL AND expires_at > $1
+                RETURNING id
+            ",
+            used_at,
+            codes::hash(code),
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(password_reset.map(|password_reset| password_reset.id))
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::query_as;
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct Permission {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Permission {
+    /// Returns the permissions granted by the roles of the user that apply to the client,
+    /// see `Role::get_by_user_id`.
+    pub async fn get_by_user_id(
+        pool: &PgPool,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Permission>> {
+        let permissions = query_as!(
+            Permission,
+            "
+                SELECT id, name, created_at, updated_at
+                FROM permissions
+                WHERE id IN (
+                    SELECT role_permissions.permission_id
+                    FROM role_permissions
+                    INNER JOIN user_roles ON user_roles.role_id = role_permissions.role_id
+                    WHERE user_roles.user_id = $1
+                        AND (user_roles.idp_client_id IS NULL OR user_roles.idp_client_id = $2)
+                )
+                ORDER BY name
+            ",
+            user_id,
+            idp_client_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(permissions)
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// A token bucket, `allowed` tells whether the last request took a token out of it.
+#[derive(Debug)]
+pub struct RateLimitBucket {
+    pub id: Uuid,
+    pub key: String,
+    pub tokens: f64,
+    pub allowed: bool,
+    pub refilled_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl RateLimitBucket {
+    /// Refills the bucket according to the time elapsed since the last request (a new bucket
+    /// is full), then takes a token out of it if there is one left. Atomic, so that the instances
+    /// can share the buckets.
+    pub async fn take(
+        pool: &PgPool,
+        key: &str,
+        capacity: f64,
+        refill_per_second: f64,
+    ) -> Result<RateLimitBucket> {
+        let bucket = query_as!(
+            RateLimitBucket,
+            "
+                INSERT INTO rate_limit_buckets(key, tokens, allowed)
+                VALUES ($1, $2::float8 - 1, true)
+                ON CONFLICT (key) DO UPDATE
+                SET tokens = CASE
+                        WHEN refilled_tokens(rate_limit_buckets.tokens, rate_limit_buckets.refilled_at, $2, $3) >= 1
+                        THEN refilled_tokens(rate_limit_buckets.tokens, rate_limit_buckets.refilled_at, $2, $3) - 1
+                        ELSE refilled_tokens(rate_limit_buckets.tokens, rate_limit_buckets.refilled_at, $2, $3)
+                    END,
+                    allowed = refilled_tokens(rate_limit_buckets.tokens, rate_limit_buckets.refilled_at, $2, $3) >= 1,
+                    refilled_at = CURRENT_TIMESTAMP
+                RETURNING id, key, tokens, allowed, refilled_at, created_at, updated_at
+            ",
+            key,
+            capacity,
+            refill_per_second
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(bucket)
+    }
+
+    /// Deletes the buckets of the keys starting with `key_prefix` that haven't been refilled
+    /// since `refilled_before`, returns the number of deleted buckets.
+    pub async fn delete_stale(
+        pool: &PgPool,
+        key_prefix: &str,
+        refilled_before: &NaiveDateTime,
+    ) -> Result<u64> {
+        let result = query!(
+            "
+                DELETE FROM rate_limit_buckets
+                WHERE left(key, length($1)) = $1 AND refilled_at < $2
+            ",
+            key_prefix,
+            refilled_before,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
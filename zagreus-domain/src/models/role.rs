@@ -0,0 +1,118 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as, Executor, Postgres};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct Role {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Role {
+    pub async fn get_by_name(pool: &PgPool, name: &str) -> Result<Option<Role>> {
+        let role = query_as!(
+            Role,
+            "
+                SELECT id, name, description, created_at, updated_at
+                FROM roles
+                WHERE name = $1
+            ",
+            name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    pub async fn get_by_names<'e, E>(executor: E, names: &[String]) -> Result<Vec<Role>>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let roles = query_as!(
+            Role,
+            "
+                SELECT id, name, description, created_at, updated_at
+                FROM roles
+                WHERE name = ANY($1)
+            ",
+            names
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(roles)
+    }
+
+    /// Returns the roles of the user that apply to the client, that is,
+    /// the roles assigned without client plus the roles assigned for that client.
+    pub async fn get_by_user_id(
+        pool: &PgPool,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Role>> {
+        let roles = query_as!(
+            Role,
+            "
+                SELECT id, name, description, created_at, updated_at
+                FROM roles
+                WHERE id IN (
+                    SELECT role_id FROM user_roles
+                    WHERE user_id = $1 AND (idp_client_id IS NULL OR idp_client_id = $2)
+                )
+                ORDER BY name
+            ",
+            user_id,
+            idp_client_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(roles)
+    }
+
+    pub async fn create(pool: &PgPool, name: &str, description: Option<&str>) -> Result<Uuid> {
+        let role = query!(
+            "
+                INSERT INTO roles(name, description)
+                VALUES ($1, $2)
+                RETURNING id
+            ",
+            name,
+            description
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(role.id)
+    }
+
+    /// Grants a permission to the role, the permission is created if it doesn't exist yet.
+    pub async fn add_permission(pool: &PgPool, id: &Uuid, permission_name: &str) -> Result<()> {
+        query!(
+            "
+                WITH permission AS (
+                    INSERT INTO permissions(name)
+                    VALUES ($2)
+                    ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+                    RETURNING id
+                )
+                INSERT INTO role_permissions(role_id, permission_id)
+                SELECT $1, id FROM permission
+                ON CONFLICT DO NOTHING
+            ",
+            id,
+            permission_name
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// A login that passed the password verification and waits for the totp code.
+#[derive(Clone, Debug)]
+pub struct TotpChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code: String,
+    pub login_challenge: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl TotpChallenge {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+
+    pub async fn get_by_code(pool: &PgPool, code: &str) -> Result<Option<TotpChallenge>> {
+        let totp_challenge = query_as!(
+            TotpChallenge,
+            "
+                SELECT id, user_id, code, login_challenge, expires_at, used_at, created_at, updated_at
+                FROM totp_challenges
+                WHERE code = $1
+            ",
+            code
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(totp_challenge)
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        user_id: &Uuid,
+        code: &str,
+        login_challenge: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        let totp_challenge = query!(
+            "
+                INSERT INTO totp_challenges(user_id, code, login_challenge, expires_at)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+            ",
+            user_id,
+            code,
+            login_challenge,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(totp_challenge.id)
+    }
+
+    /// Marks the challenge as used, returns `None` if it was already used.
+    pub async fn update_used_at(
+        pool: &PgPool,
+        code: &str,
+        used_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let totp_challenge = query!(
+            "
+                UPDATE totp_challenges SET used_at = $1
+                WHERE code = $2 AND used_at IS NULL
+                RETURNING id
+            ",
+            used_at,
+            code,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(totp_challenge.map(|totp_challenge| totp_challenge.id))
+    }
+}
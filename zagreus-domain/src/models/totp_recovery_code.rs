@@ -0,0 +1,106 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+#[derive(Clone, Debug)]
+pub struct TotpRecoveryCode {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub encrypted_code: String,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl TotpRecoveryCode {
+    pub async fn get_unused_by_user_id(
+        pool: &PgPool,
+        user_id: &Uuid,
+    ) -> Result<Vec<TotpRecoveryCode>> {
+        let recovery_codes = query_as!(
+            TotpRecoveryCode,
+            "
+                SELECT id, user_id, encrypted_code, used_at, created_at, updated_at
+                FROM totp_recovery_codes
+                WHERE user_id = $1 AND used_at IS NULL
+            ",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Replaces all the recovery codes of a user at once.
+    pub async fn replace_all(
+        pool: &PgPool,
+        user_id: &Uuid,
+        encrypted_codes: &[String],
+    ) -> Result<()> {
+        let mut transaction = pool.begin().await?;
+
+        query!(
+            "
+                DELETE FROM totp_recovery_codes
+                WHERE user_id = $1
+            ",
+            user_id
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        query!(
+            "
+                INSERT INTO totp_recovery_codes(user_id, encrypted_code)
+                SELECT $1, UNNEST($2::text[])
+            ",
+            user_id,
+            encrypted_codes
+        )
+        .execute(&mut transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all(pool: &PgPool, user_id: &Uuid) -> Result<()> {
+        query!(
+            "
+                DELETE FROM totp_recovery_codes
+                WHERE user_id = $1
+            ",
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a recovery code as used, returns `None` if it was already used.
+    pub async fn update_used_at(
+        pool: &PgPool,
+        id: &Uuid,
+        used_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let recovery_code = query!(
+            "
+                UPDATE totp_recovery_codes SET used_at = $1
+                WHERE id = $2 AND used_at IS NULL
+                RETURNING id
+            ",
+            used_at,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(recovery_code.map(|recovery_code| recovery_code.id))
+    }
+}
@@ -1,26 +1,57 @@
 use anyhow::Result;
 use chrono::NaiveDateTime;
-use sqlx::{query, query_as};
+use serde_json::Value;
+use sqlx::{query, query_as, Executor, Postgres};
 use uuid::Uuid;
 
 use crate::db::PgPool;
 
-#[derive(Debug)]
+/// The search matches literally, `%` and `_` aren't wildcards (see the `ESCAPE` clauses).
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[derive(Clone, Debug)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub encrypted_password: String,
     pub terms_accepted_at: Option<NaiveDateTime>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
+    pub name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    /// Arbitrary json object, defaults to `{}`.
+    pub metadata: Value,
+    /// Disabled users can't log in anymore.
+    pub disabled_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+/// The attributes of a user that can be edited by the user themselves.
+#[derive(Debug)]
+pub struct UserProfile {
+    pub name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub metadata: Value,
+}
+
 impl User {
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_at.is_some()
+    }
+
     pub async fn get_by_id(pool: &PgPool, id: &Uuid) -> Result<Option<User>> {
         let user = query_as!(
             User,
             "
-                SELECT id, email, encrypted_password, terms_accepted_at, created_at, updated_at
+                SELECT id, email, encrypted_password, terms_accepted_at, totp_secret, totp_enabled_at, name, locale, timezone, metadata, disabled_at, created_at, updated_at
                 FROM users
                 WHERE id = $1
             ",
@@ -36,7 +67,7 @@ impl User {
         let user = query_as!(
             User,
             "
-                SELECT id, email, encrypted_password, terms_accepted_at, created_at, updated_at
+                SELECT id, email, encrypted_password, terms_accepted_at, totp_secret, totp_enabled_at, name, locale, timezone, metadata, disabled_at, created_at, updated_at
                 FROM users
                 WHERE email = $1
             ",
@@ -48,25 +79,214 @@ impl User {
         Ok(user)
     }
 
-    pub async fn create(
+    /// Returns a page of users ordered by email, optionally filtered by (a part of) their email.
+    pub async fn get_page(
         pool: &PgPool,
+        email_search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>> {
+        let users = query_as!(
+            User,
+            "
+                SELECT id, email, encrypted_password, terms_accepted_at, totp_secret, totp_enabled_at, name, locale, timezone, metadata, disabled_at, created_at, updated_at
+                FROM users
+                WHERE $1::text IS NULL OR email ILIKE '%' || $1 || '%' ESCAPE '\\'
+                ORDER BY email
+                LIMIT $2 OFFSET $3
+            ",
+            email_search.map(escape_like),
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    pub async fn count(pool: &PgPool, email_search: Option<&str>) -> Result<i64> {
+        let count = query!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM users
+                WHERE $1::text IS NULL OR email ILIKE '%' || $1 || '%' ESCAPE '\'
+            "#,
+            email_search.map(escape_like)
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count.count)
+    }
+
+    pub async fn create<'e, E>(
+        executor: E,
         email: &str,
         encrypted_password: &str,
         terms_accepted_at: &NaiveDateTime,
-    ) -> Result<Uuid> {
+        profile: &UserProfile,
+    ) -> Result<Uuid>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
         let invitation = query!(
             "
-                INSERT INTO users(email, encrypted_password, terms_accepted_at)
-                VALUES ($1, $2, $3)
+                INSERT INTO users(email, encrypted_password, terms_accepted_at, name, locale, timezone, metadata)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
                 RETURNING id
             ",
             email,
             encrypted_password,
-            terms_accepted_at
+            terms_accepted_at,
+            profile.name,
+            profile.locale,
+            profile.timezone,
+            profile.metadata
         )
-        .fetch_one(pool)
+        .fetch_one(executor)
         .await?;
 
         Ok(invitation.id)
     }
+
+    pub async fn update_password<'e, E>(
+        executor: E,
+        id: &Uuid,
+        encrypted_password: &str,
+    ) -> Result<Option<Uuid>>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let user = query!(
+            "
+                UPDATE users SET encrypted_password = $1
+                WHERE id = $2
+                RETURNING id
+            ",
+            encrypted_password,
+            id,
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
+
+    pub async fn update_profile(
+        pool: &PgPool,
+        id: &Uuid,
+        profile: &UserProfile,
+    ) -> Result<Option<Uuid>> {
+        let user = query!(
+            "
+                UPDATE users SET name = $1, locale = $2, timezone = $3, metadata = $4
+                WHERE id = $5
+                RETURNING id
+            ",
+            profile.name,
+            profile.locale,
+            profile.timezone,
+            profile.metadata,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
+
+    pub async fn update_email(pool: &PgPool, id: &Uuid, email: &str) -> Result<Option<Uuid>> {
+        let user = query!(
+            "
+                UPDATE users SET email = $1
+                WHERE id = $2
+                RETURNING id
+            ",
+            email,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
+
+    /// Disables the user, or enables them again when `disabled_at` is `None`.
+    pub async fn update_disabled_at(
+        pool: &PgPool,
+        id: &Uuid,
+        disabled_at: Option<&NaiveDateTime>,
+    ) -> Result<Option<Uuid>> {
+        let user = query!(
+            "
+                UPDATE users SET disabled_at = $1
+                WHERE id = $2
+                RETURNING id
+            ",
+            disabled_at,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
+
+    /// Deletes the user along with everything attached to them, returns `false` if the user didn't exist.
+    pub async fn delete(pool: &PgPool, id: &Uuid) -> Result<bool> {
+        let result = query!(
+            "
+                DELETE FROM users
+                WHERE id = $1
+            ",
+            id,
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores a new (not yet enabled) totp secret, or removes it along with
+    /// the totp enablement when `totp_secret` is `None`.
+    pub async fn update_totp_secret(
+        pool: &PgPool,
+        id: &Uuid,
+        totp_secret: Option<&str>,
+    ) -> Result<Option<Uuid>> {
+        let user = query!(
+            "
+                UPDATE users SET totp_secret = $1, totp_enabled_at = NULL
+                WHERE id = $2
+                RETURNING id
+            ",
+            totp_secret,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
+
+    pub async fn update_totp_enabled_at(
+        pool: &PgPool,
+        id: &Uuid,
+        totp_enabled_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let user = query!(
+            "
+                UPDATE users SET totp_enabled_at = $1
+                WHERE id = $2 AND totp_secret IS NOT NULL
+                RETURNING id
+            ",
+            totp_enabled_at,
+            id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(user.map(|user| user.id))
+    }
 }
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use sqlx::{query, Executor, Postgres};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// Assignment of a role to a user, for all the clients (when `idp_client_id` is `None`)
+/// or for a single client.
+#[derive(Debug)]
+pub struct UserRole;
+
+impl UserRole {
+    /// Assigns the role to the user, does nothing if the role is already assigned.
+    pub async fn create<'e, E>(
+        executor: E,
+        user_id: &Uuid,
+        role_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        query!(
+            "
+                INSERT INTO user_roles(user_id, role_id, idp_client_id)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (user_id, role_id, COALESCE(idp_client_id, '')) DO NOTHING
+            ",
+            user_id,
+            role_id,
+            idp_client_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unassigns the role, returns `false` if the role wasn't assigned.
+    pub async fn delete(
+        pool: &PgPool,
+        user_id: &Uuid,
+        role_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<bool> {
+        let result = query!(
+            "
+                DELETE FROM user_roles
+                WHERE user_id = $1 AND role_id = $2 AND idp_client_id IS NOT DISTINCT FROM $3
+            ",
+            user_id,
+            role_id,
+            idp_client_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// The server side state of a pending WebAuthn ceremony (registration or authentication).
+/// When the ceremony is part of a login the Hydra `login_challenge` is kept along.
+#[derive(Debug)]
+pub struct WebauthnChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub state: String,
+    pub login_challenge: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl WebauthnChallenge {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        user_id: &Uuid,
+        state: &str,
+        login_challenge: Option<&str>,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        let challenge = query!(
+            "
+                INSERT INTO webauthn_challenges(user_id, state, login_challenge, expires_at)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+            ",
+            user_id,
+            state,
+            login_challenge,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(challenge.id)
+    }
+
+    /// Deletes and returns the challenge so that a ceremony can only be completed once.
+    pub async fn take(pool: &PgPool, id: &Uuid) -> Result<Option<WebauthnChallenge>> {
+        let challenge = query_as!(
+            WebauthnChallenge,
+            "
+                DELETE FROM webauthn_challenges
+                WHERE id = $1
+                RETURNING id, user_id, state, login_challenge, expires_at, created_at, updated_at
+            ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(challenge)
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::db::PgPool;
+
+/// A WebAuthn credential (passkey) registered by a user.
+/// The `passkey` is the serialized credential as returned by the WebAuthn library.
+#[derive(Debug)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub credential_id: Vec<u8>,
+    pub name: String,
+    pub passkey: String,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl WebauthnCredential {
+    pub async fn get_by_user_id(pool: &PgPool, user_id: &Uuid) -> Result<Vec<WebauthnCredential>> {
+        let credentials = query_as!(
+            WebauthnCredential,
+            "
+                SELECT id, user_id, credential_id, name, passkey, last_used_at, created_at, updated_at
+                FROM webauthn_credentials
+                WHERE user_id = $1
+            ",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(credentials)
+    }
+
+    pub async fn create(
+        pool: &PgPool,
+        user_id: &Uuid,
+        credential_id: &[u8],
+        name: &str,
+        passkey: &str,
+    ) -> Result<Uuid> {
+        let credential = query!(
+            "
+                INSERT INTO webauthn_credentials(user_id, credential_id, name, passkey)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+            ",
+            user_id,
+            credential_id,
+            name,
+            passkey
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(credential.id)
+    }
+
+    /// Persists the credential after a successful authentication (the signature counter
+    /// and backup state are part of the serialized passkey).
+    pub async fn update_passkey(
+        pool: &PgPool,
+        credential_id: &[u8],
+        passkey: &str,
+        last_used_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let credential = query!(
+            "
+                UPDATE webauthn_credentials SET passkey = $1, last_used_at = $2
+                WHERE credential_id = $3
+                RETURNING id
+            ",
+            passkey,
+            last_used_at,
+            credential_id,
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(credential.map(|credential| credential.id))
+    }
+}
@@ -0,0 +1,812 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+use super::{
+    ClientRepository, InvitationRepository, LoginFailureRepository, PasswordResetRepository,
+    RoleRepository, Then, TotpChallengeRepository, TotpRecoveryCodeRepository, UserRepository,
+};
+use crate::codes;
+use crate::models::{
+    client::Client,
+    invitation::{Invitation, NewInvitation},
+    login_failure::{LoginFailure, LoginFailureKind},
+    password_reset::PasswordReset,
+    permission::Permission,
+    role::Role,
+    totp_challenge::TotpChallenge,
+    totp_recovery_code::TotpRecoveryCode,
+    user::{User, UserProfile},
+};
+
+/// A role assigned to a user, for all the clients (when the client id is `None`) or for one.
+type UserRole = (Uuid, Uuid, Option<String>);
+
+/// Keeps the models in memory (in tests typically), with the same constraints as the database:
+/// unique emails and codes, one pending invitation per email, one password reset per user.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRepository {
+    users: Arc<Mutex<Vec<User>>>,
+    invitations: Arc<Mutex<Vec<Invitation>>>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    roles: Arc<Mutex<Vec<Role>>>,
+    /// The permissions granted by each role, by role id.
+    role_permissions: Arc<Mutex<Vec<(Uuid, Permission)>>>,
+    user_roles: Arc<Mutex<Vec<UserRole>>>,
+    password_resets: Arc<Mutex<Vec<PasswordReset>>>,
+    totp_challenges: Arc<Mutex<Vec<TotpChallenge>>>,
+    totp_recovery_codes: Arc<Mutex<Vec<TotpRecoveryCode>>>,
+    login_failures: Arc<Mutex<Vec<LoginFailure>>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| anyhow!("memory repository lock poisoned"))
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn matches_email_search(user: &User, email_search: Option<&str>) -> bool {
+    email_search.is_none_or(|email_search| {
+        user.email
+            .to_lowercase()
+            .contains(email_search.to_lowercase().as_str())
+    })
+}
+
+fn insert_user(
+    users: &mut Vec<User>,
+    email: &str,
+    encrypted_password: &str,
+    terms_accepted_at: &NaiveDateTime,
+    profile: &UserProfile,
+) -> Result<Uuid> {
+    if users.iter().any(|user| user.email == email) {
+        return Err(anyhow!("email {} already exists", email));
+    }
+
+    let id = Uuid::new_v4();
+
+    users.push(User {
+        id,
+        email: email.to_string(),
+        encrypted_password: encrypted_password.to_string(),
+        terms_accepted_at: Some(*terms_accepted_at),
+        totp_secret: None,
+        totp_enabled_at: None,
+        name: profile.name.clone(),
+        locale: profile.locale.clone(),
+        timezone: profile.timezone.clone(),
+        metadata: profile.metadata.clone(),
+        disabled_at: None,
+        created_at: now(),
+        updated_at: now(),
+    });
+
+    Ok(id)
+}
+
+/// The ids of the roles of the user that apply to the client, see `Role::get_by_user_id`.
+fn role_ids(user_roles: &[UserRole], user_id: &Uuid, idp_client_id: Option<&str>) -> Vec<Uuid> {
+    user_roles
+        .iter()
+        .filter(|(role_user_id, _, role_idp_client_id)| {
+            role_user_id == user_id
+                && (role_idp_client_id.is_none() || role_idp_client_id.as_deref() == idp_client_id)
+        })
+        .map(|(_, role_id, _)| *role_id)
+        .collect()
+}
+
+impl MemoryRepository {
+    pub fn insert_client(&self, client: Client) -> Result<()> {
+        lock(&self.clients)?.push(client);
+
+        Ok(())
+    }
+
+    /// Creates a role granting the permissions (created if they don't exist yet).
+    pub fn insert_role(&self, name: &str, permission_names: &[&str]) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+
+        lock(&self.roles)?.push(Role {
+            id,
+            name: name.to_string(),
+            description: None,
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        let mut role_permissions = lock(&self.role_permissions)?;
+
+        for permission_name in permission_names {
+            let permission = role_permissions
+                .iter()
+                .map(|(_, permission)| permission)
+                .find(|permission| &permission.name == permission_name)
+                .cloned()
+                .unwrap_or_else(|| Permission {
+                    id: Uuid::new_v4(),
+                    name: permission_name.to_string(),
+                    created_at: now(),
+                    updated_at: now(),
+                });
+
+            role_permissions.push((id, permission));
+        }
+
+        Ok(id)
+    }
+
+    /// Assigns the role to the user, for all the clients when `idp_client_id` is `None`.
+    pub fn assign_role(
+        &self,
+        user_id: &Uuid,
+        role_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<()> {
+        lock(&self.user_roles)?.push((*user_id, *role_id, idp_client_id.map(String::from)));
+
+        Ok(())
+    }
+
+    fn update_user<F>(&self, id: &Uuid, update: F) -> Result<Option<Uuid>>
+    where
+        F: FnOnce(&mut User),
+    {
+        let mut users = lock(&self.users)?;
+
+        Ok(users.iter_mut().find(|user| &user.id == id).map(|user| {
+            update(user);
+            user.updated_at = now();
+            user.id
+        }))
+    }
+
+    /// Only the pending invitations can be updated.
+    fn update_pending_invitation<F>(&self, id: &Uuid, update: F) -> Result<Option<Uuid>>
+    where
+        F: FnOnce(&mut Invitation),
+    {
+        let mut invitations = lock(&self.invitations)?;
+
+        Ok(invitations
+            .iter_mut()
+            .find(|invitation| &invitation.id == id && invitation.is_pending())
+            .map(|invitation| {
+                update(invitation);
+                invitation.updated_at = now();
+                invitation.id
+            }))
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<User>> {
+        Ok(lock(&self.users)?
+            .iter()
+            .find(|user| &user.id == id)
+            .cloned())
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>> {
+        Ok(lock(&self.users)?
+            .iter()
+            .find(|user| user.email == email)
+            .cloned())
+    }
+
+    async fn get_page(
+        &self,
+        email_search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>> {
+        let mut users: Vec<User> = lock(&self.users)?
+            .iter()
+            .filter(|user| matches_email_search(user, email_search))
+            .cloned()
+            .collect();
+
+        users.sort_by(|a, b| a.email.cmp(&b.email));
+
+        Ok(users
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn count(&self, email_search: Option<&str>) -> Result<i64> {
+        Ok(lock(&self.users)?
+            .iter()
+            .filter(|user| matches_email_search(user, email_search))
+            .count() as i64)
+    }
+
+    async fn create(
+        &self,
+        email: &str,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Uuid> {
+        insert_user(
+            &mut *lock(&self.users)?,
+            email,
+            encrypted_password,
+            terms_accepted_at,
+            profile,
+        )
+    }
+
+    async fn update_profile(&self, id: &Uuid, profile: &UserProfile) -> Result<Option<Uuid>> {
+        self.update_user(id, |user| {
+            user.name = profile.name.clone();
+            user.locale = profile.locale.clone();
+            user.timezone = profile.timezone.clone();
+            user.metadata = profile.metadata.clone();
+        })
+    }
+
+    async fn update_email(&self, id: &Uuid, email: &str) -> Result<Option<Uuid>> {
+        if lock(&self.users)?
+            .iter()
+            .any(|user| user.email == email && &user.id != id)
+        {
+            return Err(anyhow!("email {} already exists", email));
+        }
+
+        self.update_user(id, |user| user.email = email.to_string())
+    }
+
+    async fn update_disabled_at(
+        &self,
+        id: &Uuid,
+        disabled_at: Option<&NaiveDateTime>,
+    ) -> Result<Option<Uuid>> {
+        self.update_user(id, |user| user.disabled_at = disabled_at.copied())
+    }
+
+    async fn delete(&self, id: &Uuid) -> Result<bool> {
+        let mut users = lock(&self.users)?;
+
+        let count = users.len();
+
+        users.retain(|user| &user.id != id);
+
+        Ok(users.len() < count)
+    }
+
+    async fn update_totp_secret(
+        &self,
+        id: &Uuid,
+        totp_secret: Option<&str>,
+    ) -> Result<Option<Uuid>> {
+        self.update_user(id, |user| {
+            user.totp_secret = totp_secret.map(String::from);
+            user.totp_enabled_at = None;
+        })
+    }
+
+    async fn update_totp_enabled_at(
+        &self,
+        id: &Uuid,
+        totp_enabled_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        let mut users = lock(&self.users)?;
+
+        Ok(users
+            .iter_mut()
+            .find(|user| &user.id == id && user.totp_secret.is_some())
+            .map(|user| {
+                user.totp_enabled_at = Some(*totp_enabled_at);
+                user.updated_at = now();
+                user.id
+            }))
+    }
+}
+
+#[async_trait]
+impl InvitationRepository for MemoryRepository {
+    async fn get_all(&self) -> Result<Vec<Invitation>> {
+        Ok(lock(&self.invitations)?.clone())
+    }
+
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<Invitation>> {
+        Ok(lock(&self.invitations)?
+            .iter()
+            .find(|invitation| &invitation.id == id)
+            .cloned())
+    }
+
+    async fn get_by_code(&self, code: &str) -> Result<Option<Invitation>> {
+        Ok(lock(&self.invitations)?
+            .iter()
+            .find(|invitation| codes::verify(code, invitation.code_hash.as_str()))
+            .cloned())
+    }
+
+    async fn get_pending_by_email(&self, email: &str) -> Result<Option<Invitation>> {
+        Ok(lock(&self.invitations)?
+            .iter()
+            .find(|invitation| invitation.email == email && invitation.is_pending())
+            .cloned())
+    }
+
+    async fn create(
+        &self,
+        email: &str,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Uuid> {
+        let mut invitations = lock(&self.invitations)?;
+
+        if invitations.iter().any(|invitation| {
+            codes::verify(code, invitation.code_hash.as_str())
+                || (invitation.email == email && invitation.is_pending())
+        }) {
+            return Err(anyhow!("invitation for {} already exists", email));
+        }
+
+        let id = Uuid::new_v4();
+
+        invitations.push(Invitation {
+            id,
+            email: email.to_string(),
+            code_hash: codes::hash(code),
+            redirect_uri: redirect_uri.to_string(),
+            idp_client_id: idp_client_id.to_string(),
+            used_at: None,
+            expires_at: *expires_at,
+            revoked_at: None,
+            roles: roles.to_vec(),
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        Ok(id)
+    }
+
+    async fn refresh(
+        &self,
+        id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Option<Uuid>> {
+        self.update_pending_invitation(id, |invitation| {
+            invitation.code_hash = codes::hash(code);
+            invitation.idp_client_id = idp_client_id.to_string();
+            invitation.redirect_uri = redirect_uri.to_string();
+            invitation.expires_at = *expires_at;
+            invitation.roles = roles.to_vec();
+        })
+    }
+
+    async fn update_revoked_at(
+        &self,
+        id: &Uuid,
+        revoked_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        self.update_pending_invitation(id, |invitation| invitation.revoked_at = Some(*revoked_at))
+    }
+
+    async fn create_and_then(
+        &self,
+        invitation: &NewInvitation,
+        pending_id: Option<&Uuid>,
+        then: Then<'_>,
+    ) -> Result<Option<Uuid>> {
+        let id = match pending_id {
+            Some(pending_id) => {
+                let pending_invitation = InvitationRepository::get_by_id(self, pending_id).await?;
+
+                self.refresh(
+                    pending_id,
+                    invitation.code.as_str(),
+                    invitation.idp_client_id.as_str(),
+                    invitation.redirect_uri.as_str(),
+                    &invitation.expires_at,
+                    &invitation.roles,
+                )
+                .await?
+                .map(|id| (id, pending_invitation))
+            }
+            None => InvitationRepository::create(
+                self,
+                invitation.email.as_str(),
+                invitation.code.as_str(),
+                invitation.idp_client_id.as_str(),
+                invitation.redirect_uri.as_str(),
+                &invitation.expires_at,
+                &invitation.roles,
+            )
+            .await
+            .map(|id| Some((id, None)))?,
+        };
+
+        let (id, pending_invitation) = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        // Puts the invitation back as it was, like a rolled back transaction
+        if let Err(error) = then.await {
+            let mut invitations = lock(&self.invitations)?;
+
+            invitations.retain(|invitation| invitation.id != id);
+            invitations.extend(pending_invitation);
+
+            return Err(error);
+        }
+
+        Ok(Some(id))
+    }
+
+    async fn complete(
+        &self,
+        invitation: &Invitation,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Option<Uuid>> {
+        let mut invitations = lock(&self.invitations)?;
+
+        let invitation = match invitations
+            .iter_mut()
+            .find(|pending| pending.id == invitation.id && pending.is_pending())
+        {
+            Some(invitation) => invitation,
+            None => return Ok(None),
+        };
+
+        let user_id = insert_user(
+            &mut *lock(&self.users)?,
+            invitation.email.as_str(),
+            encrypted_password,
+            terms_accepted_at,
+            profile,
+        )?;
+
+        invitation.used_at = Some(*terms_accepted_at);
+        invitation.updated_at = now();
+
+        let roles = lock(&self.roles)?;
+
+        lock(&self.user_roles)?.extend(
+            roles
+                .iter()
+                .filter(|role| invitation.roles.contains(&role.name))
+                .map(|role| (user_id, role.id, None)),
+        );
+
+        Ok(Some(user_id))
+    }
+}
+
+#[async_trait]
+impl ClientRepository for MemoryRepository {
+    async fn get_by_id(&self, id: &str) -> Result<Option<Client>> {
+        Ok(lock(&self.clients)?
+            .iter()
+            .find(|client| client.id == id)
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl RoleRepository for MemoryRepository {
+    async fn get_by_names(&self, names: &[String]) -> Result<Vec<Role>> {
+        Ok(lock(&self.roles)?
+            .iter()
+            .filter(|role| names.contains(&role.name))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Role>> {
+        let role_ids = role_ids(&lock(&self.user_roles)?, user_id, idp_client_id);
+
+        let mut roles: Vec<Role> = lock(&self.roles)?
+            .iter()
+            .filter(|role| role_ids.contains(&role.id))
+            .cloned()
+            .collect();
+
+        roles.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(roles)
+    }
+
+    async fn get_permissions_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Permission>> {
+        let role_ids = role_ids(&lock(&self.user_roles)?, user_id, idp_client_id);
+
+        let mut permissions: Vec<Permission> = lock(&self.role_permissions)?
+            .iter()
+            .filter(|(role_id, _)| role_ids.contains(role_id))
+            .map(|(_, permission)| permission.clone())
+            .collect();
+
+        permissions.sort_by(|a, b| a.name.cmp(&b.name));
+        permissions.dedup_by(|a, b| a.id == b.id);
+
+        Ok(permissions)
+    }
+}
+
+#[async_trait]
+impl PasswordResetRepository for MemoryRepository {
+    async fn get_by_code(&self, code: &str) -> Result<Option<PasswordReset>> {
+        Ok(lock(&self.password_resets)?
+            .iter()
+            .find(|password_reset| codes::verify(code, password_reset.code_hash.as_str()))
+            .cloned())
+    }
+
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        let mut password_resets = lock(&self.password_resets)?;
+
+        let id = password_resets
+            .iter()
+            .find(|password_reset| &password_reset.user_id == user_id)
+            .map(|password_reset| password_reset.id)
+            .unwrap_or_else(Uuid::new_v4);
+
+        password_resets.retain(|password_reset| &password_reset.user_id != user_id);
+
+        password_resets.push(PasswordReset {
+            id,
+            user_id: *user_id,
+            code_hash: codes::hash(code),
+            redirect_uri: redirect_uri.to_string(),
+            idp_client_id: idp_client_id.to_string(),
+            used_at: None,
+            expires_at: *expires_at,
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        Ok(id)
+    }
+
+    async fn complete(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        encrypted_password: &str,
+    ) -> Result<Option<Uuid>> {
+        let mut password_resets = lock(&self.password_resets)?;
+
+        let password_reset = match password_resets.iter_mut().find(|password_reset| {
+            codes::verify(code, password_reset.code_hash.as_str())
+                && password_reset.used_at.is_none()
+                && !password_reset.is_expired()
+        }) {
+            Some(password_reset) => password_reset,
+            None => return Ok(None),
+        };
+
+        let mut users = lock(&self.users)?;
+
+        let user = users
+            .iter_mut()
+            .find(|user| &user.id == user_id)
+            .ok_or_else(|| anyhow!("user {} not found", user_id))?;
+
+        user.encrypted_password = encrypted_password.to_string();
+        user.updated_at = now();
+
+        password_reset.used_at = Some(now());
+        password_reset.updated_at = now();
+
+        Ok(Some(user.id))
+    }
+}
+
+#[async_trait]
+impl TotpChallengeRepository for MemoryRepository {
+    async fn get_by_code(&self, code: &str) -> Result<Option<TotpChallenge>> {
+        Ok(lock(&self.totp_challenges)?
+            .iter()
+            .find(|totp_challenge| totp_challenge.code == code)
+            .cloned())
+    }
+
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        login_challenge: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+
+        lock(&self.totp_challenges)?.push(TotpChallenge {
+            id,
+            user_id: *user_id,
+            code: code.to_string(),
+            login_challenge: login_challenge.to_string(),
+            expires_at: *expires_at,
+            used_at: None,
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        Ok(id)
+    }
+
+    async fn update_used_at(&self, code: &str, used_at: &NaiveDateTime) -> Result<Option<Uuid>> {
+        Ok(lock(&self.totp_challenges)?
+            .iter_mut()
+            .find(|totp_challenge| totp_challenge.code == code && totp_challenge.used_at.is_none())
+            .map(|totp_challenge| {
+                totp_challenge.used_at = Some(*used_at);
+                totp_challenge.updated_at = now();
+                totp_challenge.id
+            }))
+    }
+}
+
+#[async_trait]
+impl TotpRecoveryCodeRepository for MemoryRepository {
+    async fn get_unused_by_user_id(&self, user_id: &Uuid) -> Result<Vec<TotpRecoveryCode>> {
+        Ok(lock(&self.totp_recovery_codes)?
+            .iter()
+            .filter(|recovery_code| {
+                &recovery_code.user_id == user_id && recovery_code.used_at.is_none()
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn replace_all(&self, user_id: &Uuid, encrypted_codes: &[String]) -> Result<()> {
+        let mut recovery_codes = lock(&self.totp_recovery_codes)?;
+
+        recovery_codes.retain(|recovery_code| &recovery_code.user_id != user_id);
+
+        recovery_codes.extend(
+            encrypted_codes
+                .iter()
+                .map(|encrypted_code| TotpRecoveryCode {
+                    id: Uuid::new_v4(),
+                    user_id: *user_id,
+                    encrypted_code: encrypted_code.clone(),
+                    used_at: None,
+                    created_at: now(),
+                    updated_at: now(),
+                }),
+        );
+
+        Ok(())
+    }
+
+    async fn delete_all(&self, user_id: &Uuid) -> Result<()> {
+        lock(&self.totp_recovery_codes)?.retain(|recovery_code| &recovery_code.user_id != user_id);
+
+        Ok(())
+    }
+
+    async fn update_used_at(&self, id: &Uuid, used_at: &NaiveDateTime) -> Result<Option<Uuid>> {
+        Ok(lock(&self.totp_recovery_codes)?
+            .iter_mut()
+            .find(|recovery_code| &recovery_code.id == id && recovery_code.used_at.is_none())
+            .map(|recovery_code| {
+                recovery_code.used_at = Some(*used_at);
+                recovery_code.updated_at = now();
+                recovery_code.id
+            }))
+    }
+}
+
+#[async_trait]
+impl LoginFailureRepository for MemoryRepository {
+    async fn get(&self, kind: LoginFailureKind, identifier: &str) -> Result<Option<LoginFailure>> {
+        Ok(lock(&self.login_failures)?
+            .iter()
+            .find(|login_failure| {
+                login_failure.kind == kind.as_str() && login_failure.identifier == identifier
+            })
+            .cloned())
+    }
+
+    async fn increment(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        window_start: &NaiveDateTime,
+    ) -> Result<LoginFailure> {
+        let mut login_failures = lock(&self.login_failures)?;
+
+        let login_failure = login_failures.iter_mut().find(|login_failure| {
+            login_failure.kind == kind.as_str() && login_failure.identifier == identifier
+        });
+
+        let login_failure = match login_failure {
+            Some(login_failure) => {
+                login_failure.failed_attempts = if &login_failure.last_failed_at < window_start {
+                    1
+                } else {
+                    login_failure.failed_attempts + 1
+                };
+                login_failure.last_failed_at = now();
+                login_failure.updated_at = now();
+                login_failure.clone()
+            }
+            None => {
+                let login_failure = LoginFailure {
+                    id: Uuid::new_v4(),
+                    kind: kind.as_str().to_string(),
+                    identifier: identifier.to_string(),
+                    failed_attempts: 1,
+                    last_failed_at: now(),
+                    locked_until: None,
+                    created_at: now(),
+                    updated_at: now(),
+                };
+
+                login_failures.push(login_failure.clone());
+
+                login_failure
+            }
+        };
+
+        Ok(login_failure)
+    }
+
+    async fn update_locked_until(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        locked_until: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        Ok(lock(&self.login_failures)?
+            .iter_mut()
+            .find(|login_failure| {
+                login_failure.kind == kind.as_str() && login_failure.identifier == identifier
+            })
+            .map(|login_failure| {
+                login_failure.locked_until = Some(*locked_until);
+                login_failure.updated_at = now();
+                login_failure.id
+            }))
+    }
+
+    async fn delete(&self, kind: LoginFailureKind, identifier: &str) -> Result<bool> {
+        let mut login_failures = lock(&self.login_failures)?;
+
+        let count = login_failures.len();
+
+        login_failures.retain(|login_failure| {
+            login_failure.kind != kind.as_str() || login_failure.identifier != identifier
+        });
+
+        Ok(login_failures.len() < count)
+    }
+}
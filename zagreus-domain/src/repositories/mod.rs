@@ -0,0 +1,240 @@
+/// Storage of the models behind traits, so that the handlers don't depend on Postgres:
+/// `postgres` implements them with the models functions, and `memory` (behind the `memory`
+/// feature) keeps everything in memory, in tests typically.
+/// The operations that must be atomic are single methods (`InvitationRepository::complete` for
+/// example), run in a transaction by `postgres`.
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use std::{future::Future, pin::Pin};
+use uuid::Uuid;
+
+use crate::models::{
+    client::Client,
+    invitation::{Invitation, NewInvitation},
+    login_failure::{LoginFailure, LoginFailureKind},
+    password_reset::PasswordReset,
+    permission::Permission,
+    role::Role,
+    totp_challenge::TotpChallenge,
+    totp_recovery_code::TotpRecoveryCode,
+    user::{User, UserProfile},
+};
+
+#[cfg(feature = "memory")]
+pub mod memory;
+pub mod postgres;
+
+/// What has to succeed for an invitation to be kept, see `InvitationRepository::create_and_then`.
+pub type Then<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<User>>;
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>>;
+
+    /// The users ordered by email, optionally filtered by a part of their email.
+    async fn get_page(
+        &self,
+        email_search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>>;
+
+    async fn count(&self, email_search: Option<&str>) -> Result<i64>;
+
+    async fn create(
+        &self,
+        email: &str,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Uuid>;
+
+    async fn update_profile(&self, id: &Uuid, profile: &UserProfile) -> Result<Option<Uuid>>;
+
+    async fn update_email(&self, id: &Uuid, email: &str) -> Result<Option<Uuid>>;
+
+    async fn update_disabled_at(
+        &self,
+        id: &Uuid,
+        disabled_at: Option<&NaiveDateTime>,
+    ) -> Result<Option<Uuid>>;
+
+    async fn delete(&self, id: &Uuid) -> Result<bool>;
+
+    /// See `User::update_totp_secret`.
+    async fn update_totp_secret(
+        &self,
+        id: &Uuid,
+        totp_secret: Option<&str>,
+    ) -> Result<Option<Uuid>>;
+
+    /// `None` when the user has no totp secret.
+    async fn update_totp_enabled_at(
+        &self,
+        id: &Uuid,
+        totp_enabled_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>>;
+}
+
+#[async_trait]
+pub trait InvitationRepository: Send + Sync {
+    async fn get_all(&self) -> Result<Vec<Invitation>>;
+
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<Invitation>>;
+
+    async fn get_by_code(&self, code: &str) -> Result<Option<Invitation>>;
+
+    async fn get_pending_by_email(&self, email: &str) -> Result<Option<Invitation>>;
+
+    async fn create(
+        &self,
+        email: &str,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Uuid>;
+
+    /// See `Invitation::refresh`.
+    async fn refresh(
+        &self,
+        id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Option<Uuid>>;
+
+    async fn update_revoked_at(
+        &self,
+        id: &Uuid,
+        revoked_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>>;
+
+    /// Creates the invitation, or refreshes the pending invitation `pending_id` with it, and only
+    /// keeps it once `then` (sending its email typically) succeeded, the error of `then` is
+    /// returned as is. `None` when the pending invitation has been used or revoked meanwhile.
+    async fn create_and_then(
+        &self,
+        invitation: &NewInvitation,
+        pending_id: Option<&Uuid>,
+        then: Then<'_>,
+    ) -> Result<Option<Uuid>>;
+
+    /// Uses the invitation and creates its user with the roles of the invitation at once: if
+    /// anything fails the invitation can be used again. `None` when the invitation has already
+    /// been used, concurrent completions of the same invitation only create a single user.
+    async fn complete(
+        &self,
+        invitation: &Invitation,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Option<Uuid>>;
+}
+
+#[async_trait]
+pub trait ClientRepository: Send + Sync {
+    async fn get_by_id(&self, id: &str) -> Result<Option<Client>>;
+}
+
+#[async_trait]
+pub trait RoleRepository: Send + Sync {
+    async fn get_by_names(&self, names: &[String]) -> Result<Vec<Role>>;
+
+    /// See `Role::get_by_user_id`.
+    async fn get_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Role>>;
+
+    /// See `Permission::get_by_user_id`.
+    async fn get_permissions_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Permission>>;
+}
+
+#[async_trait]
+pub trait PasswordResetRepository: Send + Sync {
+    async fn get_by_code(&self, code: &str) -> Result<Option<PasswordReset>>;
+
+    /// See `PasswordReset::create`.
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid>;
+
+    /// Uses the password reset and replaces the password of its user at once: if anything fails
+    /// the code can be used again. `None` when it has already been used (or has expired),
+    /// concurrent completions of the same code only replace the password once.
+    async fn complete(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        encrypted_password: &str,
+    ) -> Result<Option<Uuid>>;
+}
+
+#[async_trait]
+pub trait TotpChallengeRepository: Send + Sync {
+    async fn get_by_code(&self, code: &str) -> Result<Option<TotpChallenge>>;
+
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        login_challenge: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid>;
+
+    /// See `TotpChallenge::update_used_at`.
+    async fn update_used_at(&self, code: &str, used_at: &NaiveDateTime) -> Result<Option<Uuid>>;
+}
+
+#[async_trait]
+pub trait TotpRecoveryCodeRepository: Send + Sync {
+    async fn get_unused_by_user_id(&self, user_id: &Uuid) -> Result<Vec<TotpRecoveryCode>>;
+
+    /// See `TotpRecoveryCode::replace_all`.
+    async fn replace_all(&self, user_id: &Uuid, encrypted_codes: &[String]) -> Result<()>;
+
+    async fn delete_all(&self, user_id: &Uuid) -> Result<()>;
+
+    /// See `TotpRecoveryCode::update_used_at`.
+    async fn update_used_at(&self, id: &Uuid, used_at: &NaiveDateTime) -> Result<Option<Uuid>>;
+}
+
+#[async_trait]
+pub trait LoginFailureRepository: Send + Sync {
+    async fn get(&self, kind: LoginFailureKind, identifier: &str) -> Result<Option<LoginFailure>>;
+
+    /// See `LoginFailure::increment`.
+    async fn increment(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        window_start: &NaiveDateTime,
+    ) -> Result<LoginFailure>;
+
+    async fn update_locked_until(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        locked_until: &NaiveDateTime,
+    ) -> Result<Option<Uuid>>;
+
+    /// See `LoginFailure::delete`.
+    async fn delete(&self, kind: LoginFailureKind, identifier: &str) -> Result<bool>;
+}
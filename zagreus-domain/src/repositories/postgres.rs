@@ -0,0 +1,654 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use super::{
+    ClientRepository, InvitationRepository, LoginFailureRepository, PasswordResetRepository,
+    RoleRepository, Then, TotpChallengeRepository, TotpRecoveryCodeRepository, UserRepository,
+};
+use crate::db::PgPool;
+use crate::models::{
+    client::Client,
+    invitation::{Invitation, NewInvitation},
+    login_failure::{LoginFailure, LoginFailureKind},
+    password_reset::PasswordReset,
+    permission::Permission,
+    role::Role,
+    totp_challenge::TotpChallenge,
+    totp_recovery_code::TotpRecoveryCode,
+    user::{User, UserProfile},
+    user_role::UserRole,
+};
+
+#[async_trait]
+impl UserRepository for PgPool {
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<User>> {
+        User::get_by_id(self, id).await
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>> {
+        User::get_by_email(self, email).await
+    }
+
+    async fn get_page(
+        &self,
+        email_search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>> {
+        User::get_page(self, email_search, limit, offset).await
+    }
+
+    async fn count(&self, email_search: Option<&str>) -> Result<i64> {
+        User::count(self, email_search).await
+    }
+
+    async fn create(
+        &self,
+        email: &str,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Uuid> {
+        User::create(self, email, encrypted_password, terms_accepted_at, profile).await
+    }
+
+    async fn update_profile(&self, id: &Uuid, profile: &UserProfile) -> Result<Option<Uuid>> {
+        User::update_profile(self, id, profile).await
+    }
+
+    async fn update_email(&self, id: &Uuid, email: &str) -> Result<Option<Uuid>> {
+        User::update_email(self, id, email).await
+    }
+
+    async fn update_disabled_at(
+        &self,
+        id: &Uuid,
+        disabled_at: Option<&NaiveDateTime>,
+    ) -> Result<Option<Uuid>> {
+        User::update_disabled_at(self, id, disabled_at).await
+    }
+
+    async fn delete(&self, id: &Uuid) -> Result<bool> {
+        User::delete(self, id).await
+    }
+
+    async fn update_totp_secret(
+        &self,
+        id: &Uuid,
+        totp_secret: Option<&str>,
+    ) -> Result<Option<Uuid>> {
+        User::update_totp_secret(self, id, totp_secret).await
+    }
+
+    async fn update_totp_enabled_at(
+        &self,
+        id: &Uuid,
+        totp_enabled_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        User::update_totp_enabled_at(self, id, totp_enabled_at).await
+    }
+}
+
+#[async_trait]
+impl InvitationRepository for PgPool {
+    async fn get_all(&self) -> Result<Vec<Invitation>> {
+        Invitation::get_all(self).await
+    }
+
+    async fn get_by_id(&self, id: &Uuid) -> Result<Option<Invitation>> {
+        Invitation::get_by_id(self, id).await
+    }
+
+    async fn get_by_code(&self, code: &str) -> Result<Option<Invitation>> {
+        Invitation::get_by_code(self, code).await
+    }
+
+    async fn get_pending_by_email(&self, email: &str) -> Result<Option<Invitation>> {
+        Invitation::get_pending_by_email(self, email).await
+    }
+
+    async fn create(
+        &self,
+        email: &str,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Uuid> {
+        Invitation::create(
+            self,
+            email,
+            code,
+            idp_client_id,
+            redirect_uri,
+            expires_at,
+            roles,
+        )
+        .await
+    }
+
+    async fn refresh(
+        &self,
+        id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+        roles: &[String],
+    ) -> Result<Option<Uuid>> {
+        Invitation::refresh(
+            self,
+            id,
+            code,
+            idp_client_id,
+            redirect_uri,
+            expires_at,
+            roles,
+        )
+        .await
+    }
+
+    async fn update_revoked_at(
+        &self,
+        id: &Uuid,
+        revoked_at: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        Invitation::update_revoked_at(self, id, revoked_at).await
+    }
+
+    async fn create_and_then(
+        &self,
+        invitation: &NewInvitation,
+        pending_id: Option<&Uuid>,
+        then: Then<'_>,
+    ) -> Result<Option<Uuid>> {
+        let mut transaction = self.begin().await?;
+
+        let id = match pending_id {
+            Some(pending_id) => {
+                Invitation::refresh(
+                    &mut transaction,
+                    pending_id,
+                    invitation.code.as_str(),
+                    invitation.idp_client_id.as_str(),
+                    invitation.redirect_uri.as_str(),
+                    &invitation.expires_at,
+                    &invitation.roles,
+                )
+                .await?
+            }
+            None => Some(
+                Invitation::create(
+                    &mut transaction,
+                    invitation.email.as_str(),
+                    invitation.code.as_str(),
+                    invitation.idp_client_id.as_str(),
+                    invitation.redirect_uri.as_str(),
+                    &invitation.expires_at,
+                    &invitation.roles,
+                )
+                .await?,
+            ),
+        };
+
+        // The transaction is rolled back when dropped
+        if id.is_none() {
+            return Ok(None);
+        }
+
+        then.await?;
+
+        transaction.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn complete(
+        &self,
+        invitation: &Invitation,
+        encrypted_password: &str,
+        terms_accepted_at: &NaiveDateTime,
+        profile: &UserProfile,
+    ) -> Result<Option<Uuid>> {
+        let mut transaction = self.begin().await?;
+
+        // The invitation is locked until the end of the transaction,
+        // the concurrent completions wait for it and then find it used
+        if Invitation::update_used_at(&mut transaction, &invitation.id, terms_accepted_at)
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        let user_id = User::create(
+            &mut transaction,
+            invitation.email.as_str(),
+            encrypted_password,
+            terms_accepted_at,
+            profile,
+        )
+        .await?;
+
+        let roles = Role::get_by_names(&mut transaction, &invitation.roles).await?;
+
+        for role in roles {
+            UserRole::create(&mut transaction, &user_id, &role.id, None).await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(Some(user_id))
+    }
+}
+
+#[async_trait]
+impl ClientRepository for PgPool {
+    async fn get_by_id(&self, id: &str) -> Result<Option<Client>> {
+        Client::get_by_id(self, id).await
+    }
+}
+
+#[async_trait]
+impl RoleRepository for PgPool {
+    async fn get_by_names(&self, names: &[String]) -> Result<Vec<Role>> {
+        Role::get_by_names(self, names).await
+    }
+
+    async fn get_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Role>> {
+        Role::get_by_user_id(self, user_id, idp_client_id).await
+    }
+
+    async fn get_permissions_by_user_id(
+        &self,
+        user_id: &Uuid,
+        idp_client_id: Option<&str>,
+    ) -> Result<Vec<Permission>> {
+        Permission::get_by_user_id(self, user_id, idp_client_id).await
+    }
+}
+
+#[async_trait]
+impl PasswordResetRepository for PgPool {
+    async fn get_by_code(&self, code: &str) -> Result<Option<PasswordReset>> {
+        PasswordReset::get_by_code(self, code).await
+    }
+
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        idp_client_id: &str,
+        redirect_uri: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        PasswordReset::create(self, user_id, code, idp_client_id, redirect_uri, expires_at).await
+    }
+
+    async fn complete(
+        &self,
+        code: &str,
+        user_id: &Uuid,
+        encrypted_password: &str,
+    ) -> Result<Option<Uuid>> {
+        let mut transaction = self.begin().await?;
+
+        // The password reset is locked until the end of the transaction,
+        // the concurrent completions wait for it and then find it used
+        if PasswordReset::update_used_at(&mut transaction, code, &Utc::now().naive_utc())
+            .await?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        let user_id = User::update_password(&mut transaction, user_id, encrypted_password)
+            .await?
+            .ok_or_else(|| anyhow!("user {} not found", user_id))?;
+
+        transaction.commit().await?;
+
+        Ok(Some(user_id))
+    }
+}
+
+#[async_trait]
+impl TotpChallengeRepository for PgPool {
+    async fn get_by_code(&self, code: &str) -> Result<Option<TotpChallenge>> {
+        TotpChallenge::get_by_code(self, code).await
+    }
+
+    async fn create(
+        &self,
+        user_id: &Uuid,
+        code: &str,
+        login_challenge: &str,
+        expires_at: &NaiveDateTime,
+    ) -> Result<Uuid> {
+        TotpChallenge::create(self, user_id, code, login_challenge, expires_at).await
+    }
+
+    async fn update_used_at(&self, code: &str, used_at: &NaiveDateTime) -> Result<Option<Uuid>> {
+        TotpChallenge::update_used_at(self, code, used_at).await
+    }
+}
+
+#[async_trait]
+impl TotpRecoveryCodeRepository for PgPool {
+    async fn get_unused_by_user_id(&self, user_id: &Uuid) -> Result<Vec<TotpRecoveryCode>> {
+        TotpRecoveryCode::get_unused_by_user_id(self, user_id).await
+    }
+
+    async fn replace_all(&self, user_id: &Uuid, encrypted_codes: &[String]) -> Result<()> {
+        TotpRecoveryCode::replace_all(self, user_id, encrypted_codes).await
+    }
+
+    async fn delete_all(&self, user_id: &Uuid) -> Result<()> {
+        TotpRecoveryCode::delete_all(self, user_id).await
+    }
+
+    async fn update_used_at(&self, id: &Uuid, used_at: &NaiveDateTime) -> Result<Option<Uuid>> {
+        TotpRecoveryCode::update_used_at(self, id, used_at).await
+    }
+}
+
+#[async_trait]
+impl LoginFailureRepository for PgPool {
+    async fn get(&self, kind: LoginFailureKind, identifier: &str) -> Result<Option<LoginFailure>> {
+        LoginFailure::get(self, kind, identifier).await
+    }
+
+    async fn increment(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        window_start: &NaiveDateTime,
+    ) -> Result<LoginFailure> {
+        LoginFailure::increment(self, kind, identifier, window_start).await
+    }
+
+    async fn update_locked_until(
+        &self,
+        kind: LoginFailureKind,
+        identifier: &str,
+        locked_until: &NaiveDateTime,
+    ) -> Result<Option<Uuid>> {
+        LoginFailure::update_locked_until(self, kind, identifier, locked_until).await
+    }
+
+    async fn delete(&self, kind: LoginFailureKind, identifier: &str) -> Result<bool> {
+        LoginFailure::delete(self, kind, identifier).await
+    }
+}
+
+/// The transactions against the database of `DATABASE_URL`.
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use chrono::{Duration, Utc};
+    use futures_util::future::join;
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use crate::db::PgPool;
+    use crate::models::{
+        client::Client,
+        invitation::{Invitation, NewInvitation},
+        password_reset::PasswordReset,
+        user::{User, UserProfile},
+    };
+    use crate::repositories::{InvitationRepository, PasswordResetRepository};
+
+    async fn connect() -> PgPool {
+        dotenv::dotenv().ok();
+
+        crate::db::connect().await.unwrap()
+    }
+
+    fn profile() -> UserProfile {
+        UserProfile {
+            name: None,
+            locale: None,
+            timezone: None,
+            metadata: Value::Object(Default::default()),
+        }
+    }
+
+    /// Creates a client and one of its invitations, returns the client id and the invitation.
+    async fn invite(pool: &PgPool) -> (String, Invitation) {
+        let client_id = Uuid::new_v4().to_string();
+
+        let code = Uuid::new_v4().to_string();
+
+        Client::create(pool, client_id.as_str(), &[], false)
+            .await
+            .unwrap();
+
+        Invitation::create(
+            pool,
+            format!("{}@example.com", code).as_str(),
+            code.as_str(),
+            client_id.as_str(),
+            "https://example.com",
+            &(Utc::now().naive_utc() + Duration::hours(1)),
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let invitation = Invitation::get_by_code(pool, code.as_str())
+            .await
+            .unwrap()
+            .unwrap();
+
+        (client_id, invitation)
+    }
+
+    /// Creates a user, a client and a password reset, returns the client id, the user and the code.
+    async fn request_reset(pool: &PgPool, expires_in: Duration) -> (String, User, String) {
+        let client_id = Uuid::new_v4().to_string();
+
+        let code = Uuid::new_v4().to_string();
+
+        Client::create(pool, client_id.as_str(), &[], false)
+            .await
+            .unwrap();
+
+        let user_id = User::create(
+            pool,
+            format!("{}@example.com", code).as_str(),
+            "password",
+            &Utc::now().naive_utc(),
+            &profile(),
+        )
+        .await
+        .unwrap();
+
+        let user = User::get_by_id(pool, &user_id).await.unwrap().unwrap();
+
+        PasswordReset::create(
+            pool,
+            &user.id,
+            code.as_str(),
+            client_id.as_str(),
+            "https://example.com",
+            &(Utc::now().naive_utc() + expires_in),
+        )
+        .await
+        .unwrap();
+
+        (client_id, user, code)
+    }
+
+    async fn password(pool: &PgPool, user: &User) -> String {
+        User::get_by_id(pool, &user.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .encrypted_password
+    }
+
+    async fn cleanup(pool: &PgPool, client_id: &str, email: &str) {
+        if let Some(user) = User::get_by_email(pool, email).await.unwrap() {
+            User::delete(pool, &user.id).await.unwrap();
+        }
+
+        Client::delete(pool, client_id).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn it_completes_an_invitation_only_once_concurrently() {
+        let pool = connect().await;
+
+        let (client_id, invitation) = invite(&pool).await;
+
+        let now = Utc::now().naive_utc();
+
+        let profile = profile();
+
+        let (first, second) = join(
+            InvitationRepository::complete(&pool, &invitation, "password", &now, &profile),
+            InvitationRepository::complete(&pool, &invitation, "password", &now, &profile),
+        )
+        .await;
+
+        cleanup(&pool, client_id.as_str(), invitation.email.as_str()).await;
+
+        let user_ids = [first.unwrap(), second.unwrap()];
+
+        assert_eq!(
+            user_ids.iter().filter(|user_id| user_id.is_some()).count(),
+            1
+        );
+    }
+
+    #[actix_rt::test]
+    async fn it_keeps_the_invitation_usable_when_the_user_isnt_created() {
+        let pool = connect().await;
+
+        let (client_id, invitation) = invite(&pool).await;
+
+        let now = Utc::now().naive_utc();
+
+        // The email is already taken, so the user can't be created
+        User::create(
+            &pool,
+            invitation.email.as_str(),
+            "password",
+            &now,
+            &profile(),
+        )
+        .await
+        .unwrap();
+
+        let result =
+            InvitationRepository::complete(&pool, &invitation, "password", &now, &profile()).await;
+
+        let used_at = Invitation::get_by_id(&pool, &invitation.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .used_at;
+
+        cleanup(&pool, client_id.as_str(), invitation.email.as_str()).await;
+
+        assert!(result.is_err());
+        assert!(used_at.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn it_drops_the_invitation_when_then_fails() {
+        let pool = connect().await;
+
+        let (client_id, pending_invitation) = invite(&pool).await;
+
+        let email = format!("{}@example.com", Uuid::new_v4());
+
+        let new_invitation = |email: &str| NewInvitation {
+            email: email.to_string(),
+            code: Uuid::new_v4().to_string(),
+            idp_client_id: client_id.clone(),
+            redirect_uri: String::from("https://example.com/refreshed"),
+            expires_at: Utc::now().naive_utc() + Duration::hours(1),
+            roles: vec![],
+        };
+
+        let created = pool
+            .create_and_then(
+                &new_invitation(email.as_str()),
+                None,
+                Box::pin(async { Err(anyhow!("email not sent")) }),
+            )
+            .await;
+
+        let refreshed = pool
+            .create_and_then(
+                &new_invitation(pending_invitation.email.as_str()),
+                Some(&pending_invitation.id),
+                Box::pin(async { Err(anyhow!("email not sent")) }),
+            )
+            .await;
+
+        let created_invitation = Invitation::get_pending_by_email(&pool, email.as_str())
+            .await
+            .unwrap();
+
+        let refreshed_invitation = Invitation::get_by_id(&pool, &pending_invitation.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        cleanup(&pool, client_id.as_str(), email.as_str()).await;
+
+        assert_eq!(created.unwrap_err().to_string(), "email not sent");
+        assert!(refreshed.is_err());
+        assert!(created_invitation.is_none());
+        assert_eq!(refreshed_invitation.code_hash, pending_invitation.code_hash);
+        assert_eq!(refreshed_invitation.redirect_uri, "https://example.com");
+    }
+
+    #[actix_rt::test]
+    async fn it_completes_a_password_reset_only_once_concurrently() {
+        let pool = connect().await;
+
+        let (client_id, user, code) = request_reset(&pool, Duration::hours(1)).await;
+
+        let (first, second) = join(
+            PasswordResetRepository::complete(&pool, code.as_str(), &user.id, "first"),
+            PasswordResetRepository::complete(&pool, code.as_str(), &user.id, "second"),
+        )
+        .await;
+
+        let password = password(&pool, &user).await;
+
+        cleanup(&pool, client_id.as_str(), user.email.as_str()).await;
+
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        assert_eq!(first.is_some() as u8 + second.is_some() as u8, 1);
+        assert_eq!(password, if first.is_some() { "first" } else { "second" });
+    }
+
+    #[actix_rt::test]
+    async fn it_rejects_an_expired_password_reset() {
+        let pool = connect().await;
+
+        let (client_id, user, code) = request_reset(&pool, Duration::hours(-1)).await;
+
+        let result = PasswordResetRepository::complete(&pool, code.as_str(), &user.id, "new").await;
+
+        let password = password(&pool, &user).await;
+
+        cleanup(&pool, client_id.as_str(), user.email.as_str()).await;
+
+        assert!(result.unwrap().is_none());
+        assert_eq!(password, "password");
+    }
+}
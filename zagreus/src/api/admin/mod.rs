@@ -0,0 +1,2 @@
+/// Admin endpoints, used to manage Zagreus (see `crate::session::AdminSession`).
+pub mod users;
@@ -0,0 +1,475 @@
+use actix_web::{delete, get, http::StatusCode, post, put, web, HttpResponse, Result};
+use chrono::{NaiveDateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use uuid::Uuid;
+use validator::Validate;
+use zagreus_domain::{
+    models::user::{User, UserProfile},
+    repositories::{ClientRepository, PasswordResetRepository, UserRepository},
+};
+
+use crate::api::password_reset::send_password_reset;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+use crate::mailer::Mailer;
+use crate::session::AdminSession;
+use crate::validations::validate;
+
+const DEFAULT_PER_PAGE: i64 = 20;
+
+#[derive(Error, Debug)]
+pub enum AdminUserError {
+    #[error("user request error")]
+    UserError,
+    #[error("user not found")]
+    UserNotFound,
+    #[error("email already exists")]
+    EmailAlreadyExists,
+    #[error("user couldn't be updated")]
+    UserNotUpdated,
+    #[error("user couldn't be deleted")]
+    UserNotDeleted,
+    #[error("user sessions couldn't be revoked")]
+    SessionsNotRevoked,
+}
+
+impl ErrorDetails for AdminUserError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            AdminUserError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            AdminUserError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            AdminUserError::EmailAlreadyExists => (StatusCode::CONFLICT, "email_already_exists"),
+            AdminUserError::UserNotUpdated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "user_not_updated")
+            }
+            AdminUserError::UserNotDeleted => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "user_not_deleted")
+            }
+            AdminUserError::SessionsNotRevoked => (StatusCode::BAD_GATEWAY, "sessions_not_revoked"),
+        }
+    }
+}
+
+json_response_error!(AdminUserError);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UserResponse {
+    id: Uuid,
+    email: String,
+    name: Option<String>,
+    locale: Option<String>,
+    timezone: Option<String>,
+    metadata: Value,
+    totp_enabled: bool,
+    disabled_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        UserResponse {
+            id: user.id,
+            email: user.email,
+            name: user.name,
+            locale: user.locale,
+            timezone: user.timezone,
+            metadata: user.metadata,
+            totp_enabled: user.totp_enabled_at.is_some(),
+            disabled_at: user.disabled_at,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+async fn get_user(users: &dyn UserRepository, id: &Uuid) -> Result<User, AdminUserError> {
+    let user = users
+        .get_by_id(id)
+        .await
+        .map_err(|_| AdminUserError::UserError)?;
+
+    user.ok_or(AdminUserError::UserNotFound)
+}
+
+/// Logs the user out of all the clients.
+async fn revoke_sessions(hydra: &dyn HydraAdmin, user: &User) -> Result<(), AdminUserError> {
+    hydra
+        .revoke_sessions(user.id.to_string().as_str())
+        .await
+        .map_err(|_| AdminUserError::SessionsNotRevoked)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUsersPayload {
+    /// Filters the users whose email contains this value, ignoring the case.
+    #[validate(length(min = 1))]
+    email: Option<String>,
+    #[validate(range(min = 1))]
+    page: Option<i64>,
+    #[validate(range(min = 1, max = 100))]
+    per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetUsersResponse {
+    users: Vec<UserResponse>,
+    page: i64,
+    per_page: i64,
+    total: i64,
+}
+
+/// Lists the users, page by page.
+#[get("/api/admin/users")]
+pub async fn get_users(
+    _admin: AdminSession,
+    payload: web::Query<GetUsersPayload>,
+    users: web::Data<dyn UserRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let page = payload.page.unwrap_or(1);
+
+    let per_page = payload.per_page.unwrap_or(DEFAULT_PER_PAGE);
+
+    let page_users = users
+        .get_page(payload.email.as_deref(), per_page, (page - 1) * per_page)
+        .await
+        .map_err(|_| AdminUserError::UserError)?;
+
+    let total = users
+        .count(payload.email.as_deref())
+        .await
+        .map_err(|_| AdminUserError::UserError)?;
+
+    Ok(HttpResponse::Ok().json(GetUsersResponse {
+        users: page_users.into_iter().map(UserResponse::from).collect(),
+        page,
+        per_page,
+        total,
+    }))
+}
+
+#[get("/api/admin/users/{id}")]
+pub async fn get_user_by_id(
+    _admin: AdminSession,
+    id: web::Path<Uuid>,
+    users: web::Data<dyn UserRepository>,
+) -> Result<HttpResponse> {
+    let user = get_user(&**users, &id).await?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateUserPayload {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 1, max = 256))]
+    name: Option<String>,
+    #[validate(length(min = 2, max = 35))]
+    locale: Option<String>,
+    #[validate(length(min = 1, max = 64))]
+    timezone: Option<String>,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+}
+
+/// Replaces the email and the profile of the user.
+#[put("/api/admin/users/{id}")]
+pub async fn update_user(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    payload: web::Json<UpdateUserPayload>,
+    users: web::Data<dyn UserRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let payload = payload.into_inner();
+
+    let user = get_user(&**users, &id).await?;
+
+    if user.email != payload.email {
+        let existing_user = users
+            .get_by_email(payload.email.as_str())
+            .await
+            .map_err(|_| AdminUserError::UserError)?;
+
+        if existing_user.is_some() {
+            return Err(AdminUserError::EmailAlreadyExists.into());
+        }
+
+        users
+            .update_email(&user.id, payload.email.as_str())
+            .await
+            .map_err(|_| AdminUserError::UserNotUpdated)?;
+    }
+
+    users
+        .update_profile(
+            &user.id,
+            &UserProfile {
+                name: payload.name,
+                locale: payload.locale,
+                timezone: payload.timezone,
+                metadata: Value::Object(payload.metadata),
+            },
+        )
+        .await
+        .map_err(|_| AdminUserError::UserNotUpdated)?;
+
+    info!("User {} has been updated by {}", user.id, admin.subject);
+
+    let user = get_user(&**users, &user.id).await?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+/// Disables the user and revokes their sessions, disabled users can't log in anymore.
+#[post("/api/admin/users/{id}/disable")]
+pub async fn disable_user(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    users: web::Data<dyn UserRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
+    let user = get_user(&**users, &id).await?;
+
+    users
+        .update_disabled_at(&user.id, Some(&Utc::now().naive_utc()))
+        .await
+        .map_err(|_| AdminUserError::UserNotUpdated)?;
+
+    revoke_sessions(&**hydra, &user).await?;
+
+    info!("User {} has been disabled by {}", user.id, admin.subject);
+
+    let user = get_user(&**users, &user.id).await?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+#[post("/api/admin/users/{id}/enable")]
+pub async fn enable_user(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    users: web::Data<dyn UserRepository>,
+) -> Result<HttpResponse> {
+    let user = get_user(&**users, &id).await?;
+
+    users
+        .update_disabled_at(&user.id, None)
+        .await
+        .map_err(|_| AdminUserError::UserNotUpdated)?;
+
+    info!("User {} has been enabled by {}", user.id, admin.subject);
+
+    let user = get_user(&**users, &user.id).await?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(user)))
+}
+
+/// Deletes the user and revokes their sessions.
+#[delete("/api/admin/users/{id}")]
+pub async fn delete_user(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    users: web::Data<dyn UserRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
+    let user = get_user(&**users, &id).await?;
+
+    revoke_sessions(&**hydra, &user).await?;
+
+    users
+        .delete(&user.id)
+        .await
+        .map_err(|_| AdminUserError::UserNotDeleted)?;
+
+    info!("User {} has been deleted by {}", user.id, admin.subject);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ForcePasswordResetPayload {
+    #[validate(length(min = 1))]
+    client_id: String,
+    #[validate(url)]
+    redirect_uri: String,
+}
+
+/// Sends a password reset link to the user, as if they requested it themselves.
+#[post("/api/admin/users/{id}/password-reset")]
+pub async fn force_password_reset(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    payload: web::Json<ForcePasswordResetPayload>,
+    users: web::Data<dyn UserRepository>,
+    clients: web::Data<dyn ClientRepository>,
+    password_resets: web::Data<dyn PasswordResetRepository>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = get_user(&**users, &id).await?;
+
+    send_password_reset(
+        &**clients,
+        &**password_resets,
+        &mailer,
+        &user,
+        payload.client_id.as_str(),
+        payload.redirect_uri.as_str(),
+    )
+    .await?;
+
+    info!(
+        "A password reset has been sent to user {} by {}",
+        user.id, admin.subject
+    );
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use uuid::Uuid;
+    use zagreus_domain::{
+        models::user::User,
+        repositories::{memory::MemoryRepository, UserRepository},
+    };
+
+    use super::{get_user_by_id, update_user};
+    use crate::test_utils::{as_admin, connect, create_user};
+
+    #[actix_rt::test]
+    async fn it_gets_a_user_by_id() {
+        let repository = MemoryRepository::default();
+
+        let id = create_user(&repository, "alice@example.com").await;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(as_admin)
+                .app_data(users)
+                .service(get_user_by_id),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(format!("/api/admin/users/{}", id).as_str())
+            .to_request();
+
+        let user: Value = test::read_response_json(&app, req).await;
+
+        assert_eq!(user["email"], "alice@example.com");
+
+        let req = test::TestRequest::get()
+            .uri(format!("/api/admin/users/{}", Uuid::new_v4()).as_str())
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn it_doesnt_update_a_user_with_an_existing_email() {
+        let repository = MemoryRepository::default();
+
+        let id = create_user(&repository, "alice@example.com").await;
+
+        create_user(&repository, "bob@example.com").await;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(as_admin)
+                .app_data(users)
+                .service(update_user),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri(format!("/api/admin/users/{}", id).as_str())
+            .set_json(&json!({ "email": "bob@example.com", "name": "Alice" }))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let user = UserRepository::get_by_id(&repository, &id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(user.email, "alice@example.com");
+        assert_eq!(user.name, None);
+    }
+
+    #[actix_rt::test]
+    async fn it_searches_the_emails_literally() {
+        let pool = connect().await;
+
+        let memory = MemoryRepository::default();
+
+        let prefix = Uuid::new_v4().to_simple().to_string();
+
+        let emails = [
+            format!("{}_a%@example.com", prefix),
+            format!("{}xab@example.com", prefix),
+        ];
+
+        let repositories: [&dyn UserRepository; 2] = [&pool, &memory];
+
+        for repository in repositories {
+            for email in &emails {
+                create_user(repository, email.as_str()).await;
+            }
+        }
+
+        // `_` and `%` would match any character in a pattern
+        let searches = [
+            (format!("{}_a%", prefix), 1),
+            (format!("{}_", prefix), 1),
+            (prefix.clone(), 2),
+        ];
+
+        let mut counts = Vec::new();
+
+        for repository in repositories {
+            for (search, _) in &searches {
+                counts.push(repository.count(Some(search.as_str())).await.unwrap());
+            }
+        }
+
+        for email in &emails {
+            if let Some(user) = User::get_by_email(&pool, email.as_str()).await.unwrap() {
+                User::delete(&pool, &user.id).await.unwrap();
+            }
+        }
+
+        let expected_counts = searches
+            .iter()
+            .map(|(_, count)| *count)
+            .collect::<Vec<i64>>();
+
+        assert_eq!(counts, [expected_counts.clone(), expected_counts].concat());
+    }
+}
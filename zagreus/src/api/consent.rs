@@ -1,15 +1,17 @@
-use actix_web::{post, web, HttpResponse, ResponseError, Result};
-use ory_hydra_client::{
-    apis::admin_api::{accept_consent_request, get_consent_request},
-    models::{AcceptConsentRequest, ConsentRequestSession},
-};
+use actix_web::{http::StatusCode, post, web, HttpResponse, Result};
+use ory_hydra_client::models::{AcceptConsentRequest, ConsentRequest, RejectRequest};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 use validator::Validate;
-use zagreus_domain::{db::PgPool, models::user::User};
+use zagreus_domain::{
+    models::user::User,
+    repositories::{RoleRepository, UserRepository},
+};
 
-use crate::hydra_configuration::CONFIGURATION;
+use crate::claims::{self, Authorizations};
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
 use crate::validations::validate;
 
 #[derive(Error, Debug)]
@@ -24,76 +26,237 @@ pub enum ConsentError {
     UserNotFound,
     #[error("user request error")]
     UserError,
+    #[error("role request error")]
+    RoleError,
+    #[error("scope hasn't been requested")]
+    ScopeNotRequested,
     #[error("couldn't accept consent")]
     CouldntAcceptConsent,
+    #[error("couldn't reject consent")]
+    CouldntRejectConsent,
+}
+
+impl ErrorDetails for ConsentError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            ConsentError::ConsentNotFound => (StatusCode::NOT_FOUND, "consent_not_found"),
+            ConsentError::SubjectNotFound => (StatusCode::BAD_GATEWAY, "consent_subject_not_found"),
+            ConsentError::WrongSubject => (StatusCode::BAD_GATEWAY, "wrong_consent_subject"),
+            ConsentError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            ConsentError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            ConsentError::RoleError => (StatusCode::INTERNAL_SERVER_ERROR, "role_error"),
+            ConsentError::ScopeNotRequested => (StatusCode::BAD_REQUEST, "scope_not_requested"),
+            ConsentError::CouldntAcceptConsent => (StatusCode::BAD_GATEWAY, "consent_not_accepted"),
+            ConsentError::CouldntRejectConsent => (StatusCode::BAD_GATEWAY, "consent_not_rejected"),
+        }
+    }
+}
+
+json_response_error!(ConsentError);
+
+/// Fetches the user behind the consent request subject.
+pub async fn get_subject(
+    users: &dyn UserRepository,
+    subject: Option<String>,
+) -> Result<User, ConsentError> {
+    let subject = subject.ok_or(ConsentError::SubjectNotFound)?;
+
+    let user_id = Uuid::parse_str(subject.as_str()).map_err(|_| ConsentError::WrongSubject)?;
+
+    let user = users
+        .get_by_id(&user_id)
+        .await
+        .map_err(|_| ConsentError::UserError)?;
+
+    user.ok_or(ConsentError::UserNotFound)
+}
+
+/// Returns the id of the client that issued the consent request.
+pub fn get_client_id(consent_request: &ConsentRequest) -> Option<String> {
+    consent_request
+        .client
+        .as_ref()
+        .and_then(|client| client.client_id.clone())
 }
 
-impl ResponseError for ConsentError {}
+/// Accepts the consent request for the granted scopes (see `claims` for the tokens content),
+/// returns the url the user must be redirected to.
+pub async fn accept(
+    roles: &dyn RoleRepository,
+    hydra: &dyn HydraAdmin,
+    consent_challenge: &str,
+    user: User,
+    client_id: Option<&str>,
+    grant_scope: Vec<String>,
+    remember: bool,
+) -> Result<String, ConsentError> {
+    let authorizations = Authorizations::get(roles, &user, client_id)
+        .await
+        .map_err(|_| ConsentError::RoleError)?;
+
+    let session = claims::session(&user, &authorizations, &grant_scope);
+
+    let completed_request = hydra
+        .accept_consent_request(
+            consent_challenge,
+            AcceptConsentRequest {
+                grant_access_token_audience: Some(vec![
+                    zagreus_config::env::ACCESS_TOKEN_AUDIENCE(),
+                ]),
+                grant_scope: Some(grant_scope),
+                remember: Some(remember),
+                remember_for: Some(0),
+                session: Some(Box::new(session)),
+                ..AcceptConsentRequest::new()
+            },
+        )
+        .await
+        .map_err(|_| ConsentError::CouldntAcceptConsent)?;
+
+    Ok(completed_request.redirect_to)
+}
 
 #[derive(Debug, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct ConsentPayload {
     #[validate(length(min = 1))]
-    code: String,
-}
-
-#[derive(Debug, Serialize)]
-struct IdToken {
-    email: String,
+    consent_challenge: String,
+    /// The scopes the user agreed to, must be a subset of the requested scopes.
+    grant_scope: Vec<String>,
+    /// Whether the user shouldn't be asked again for this client.
+    #[serde(default)]
+    remember: bool,
 }
 
 #[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 struct ConsentResponse {
     redirect_to: String,
 }
 
-// TODO: INACTIVE - Not used by the application, can probably be removed.
-/// Takes a code that can be resolved to a consent challenge and consent to the terms.
-/// _You can use the get version for a faster consent policy._
-#[allow(dead_code)]
+/// Accepts the consent request with the scopes selected by the user on the consent screen.
 #[post("/api/consent")]
 pub async fn consent(
     payload: web::Json<ConsentPayload>,
-    pool: web::Data<PgPool>,
+    users: web::Data<dyn UserRepository>,
+    roles: web::Data<dyn RoleRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let get = get_consent_request(&CONFIGURATION, payload.code.as_str())
+    let payload = payload.into_inner();
+
+    let consent_request = hydra
+        .get_consent_request(payload.consent_challenge.as_str())
         .await
         .map_err(|_| ConsentError::ConsentNotFound)?;
 
-    let subject = get.subject.ok_or(ConsentError::SubjectNotFound)?;
+    let client_id = get_client_id(&consent_request);
 
-    let uuid = Uuid::parse_str(subject.as_str()).map_err(|_| ConsentError::WrongSubject)?;
+    let requested_scope = consent_request.requested_scope.unwrap_or_default();
 
-    let user = User::get_by_id(&pool, &uuid)
-        .await
-        .map_err(|_| ConsentError::UserError)?;
+    if !payload
+        .grant_scope
+        .iter()
+        .all(|scope| requested_scope.contains(scope))
+    {
+        return Err(ConsentError::ScopeNotRequested.into());
+    }
+
+    let user = get_subject(&**users, consent_request.subject).await?;
 
-    let user = user.ok_or(ConsentError::UserNotFound)?;
-
-    let id_token = IdToken { email: user.email };
-
-    let id_token = serde_json::to_value(&id_token)?;
-
-    let accept = accept_consent_request(
-        &CONFIGURATION,
-        payload.code.as_str(),
-        Some(AcceptConsentRequest {
-            grant_scope: Some(zagreus_config::hydra_scopes()),
-            remember: Some(true),
-            remember_for: Some(0),
-            session: Some(Box::new(ConsentRequestSession {
-                id_token: Some(id_token),
-                ..ConsentRequestSession::new()
-            })),
-            ..AcceptConsentRequest::new()
-        }),
+    let redirect_to = accept(
+        &**roles,
+        &**hydra,
+        payload.consent_challenge.as_str(),
+        user,
+        client_id.as_deref(),
+        payload.grant_scope,
+        payload.remember,
     )
-    .await
-    .map_err(|_| ConsentError::CouldntAcceptConsent)?;
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ConsentResponse { redirect_to }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectConsentPayload {
+    #[validate(length(min = 1))]
+    consent_challenge: String,
+}
+
+/// Rejects the consent request, the client receives an `access_denied` error.
+#[post("/api/consent/reject")]
+pub async fn reject_consent(
+    payload: web::Json<RejectConsentPayload>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let completed_request = hydra
+        .reject_consent_request(
+            payload.consent_challenge.as_str(),
+            RejectRequest {
+                error: Some("access_denied".to_string()),
+                error_description: Some("The resource owner denied the request".to_string()),
+                ..RejectRequest::new()
+            },
+        )
+        .await
+        .map_err(|_| ConsentError::CouldntRejectConsent)?;
 
     Ok(HttpResponse::Ok().json(ConsentResponse {
-        redirect_to: accept.redirect_to,
+        redirect_to: completed_request.redirect_to,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web::Data, App};
+    use ory_hydra_client::models::RejectRequest;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    use super::reject_consent;
+    use crate::hydra::{
+        fake::{FakeHydraAdmin, HydraCall},
+        HydraAdmin,
+    };
+
+    #[actix_rt::test]
+    async fn it_rejects_the_consent_request() {
+        let fake_hydra = FakeHydraAdmin::default();
+
+        fake_hydra.add_consent_request(
+            "challenge",
+            false,
+            "subject",
+            "http://hydra.test/oauth2/auth?redirect_uri=https%3A%2F%2Fexample.com",
+            "client",
+            &["openid"],
+        );
+
+        let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(fake_hydra.clone()) as Arc<_>);
+
+        let app = test::init_service(App::new().app_data(hydra).service(reject_consent)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/consent/reject")
+            .set_json(&json!({ "consentChallenge": "challenge" }))
+            .to_request();
+
+        let response: Value = test::read_response_json(&app, req).await;
+
+        assert_eq!(
+            response["redirectTo"],
+            "http://hydra.test/oauth2/auth?consent_verifier=challenge"
+        );
+
+        assert!(matches!(
+            fake_hydra.calls().as_slice(),
+            [HydraCall::RejectConsentRequest(challenge, RejectRequest { error: Some(error), .. })]
+                if challenge == "challenge" && error == "access_denied"
+        ));
+    }
+}
@@ -1,19 +1,29 @@
-use actix_web::{get, post, put, web, HttpResponse, ResponseError, Result};
+use actix_web::{delete, get, http::StatusCode, post, put, web, HttpResponse, Result};
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
-use chrono::Utc;
-use rand::{distributions, thread_rng, Rng};
+use chrono::{Duration, NaiveDateTime, Utc};
+use log::info;
 use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use thiserror::Error;
 use url::Url;
+use uuid::Uuid;
 use validator::Validate;
 use zagreus_domain::{
-    db::PgPool,
-    models::{invitation::Invitation, user::User},
+    models::{
+        invitation::{Invitation, NewInvitation},
+        user::UserProfile,
+    },
+    repositories::{ClientRepository, InvitationRepository, RoleRepository, UserRepository},
 };
 
-use crate::validations::{validate, validate_password, validate_terms_accepted};
+use crate::codes;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::mailer::Mailer;
+use crate::password_policy::PASSWORD_POLICY;
+use crate::session::AdminSession;
+use crate::validations::{validate, validate_terms_accepted, validation_errors_response};
 
 #[derive(Error, Debug)]
 pub enum InvitationError {
@@ -27,19 +37,84 @@ pub enum InvitationError {
     EmailAlreadyExists,
     #[error("invitation couldn't be created")]
     InvitationNotCreated,
+    #[error("invitation email couldn't be sent")]
+    InvitationNotSent,
     #[error("invitation couldn't be updated")]
     InvitationNotUpdated,
     #[error("invitation has already been used")]
     InvitationAlreadyUsed,
+    #[error("invitation has expired")]
+    Expired,
+    #[error("invitation has been revoked")]
+    InvitationRevoked,
+    #[error("a pending invitation already exists for this email")]
+    InvitationAlreadyPending,
     #[error("password encryption failed")]
     PasswordEncryptionFailed,
     #[error("user couldn't be created")]
     UserNotCreated,
     #[error("invalid redirect to url")]
     InvalidRedirectToUrl,
+    #[error("role request error")]
+    RoleError,
+    #[error("role couldn't be found")]
+    RoleNotFound,
+    #[error("client couldn't be found")]
+    ClientNotFound,
+    #[error("client request error")]
+    ClientError,
+    #[error("redirect uri isn't registered for the client")]
+    RedirectUriNotAllowed,
+    #[error("invitations import is invalid")]
+    InvalidImport,
+    #[error("invitations import is too large")]
+    ImportTooLarge,
 }
 
-impl ResponseError for InvitationError {}
+impl ErrorDetails for InvitationError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            InvitationError::InvitationNotFound => (StatusCode::NOT_FOUND, "invitation_not_found"),
+            InvitationError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            InvitationError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            InvitationError::EmailAlreadyExists => (StatusCode::CONFLICT, "email_already_exists"),
+            InvitationError::InvitationNotCreated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "invitation_not_created")
+            }
+            InvitationError::InvitationNotSent => (StatusCode::BAD_GATEWAY, "invitation_not_sent"),
+            InvitationError::InvitationNotUpdated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "invitation_not_updated")
+            }
+            InvitationError::InvitationAlreadyUsed => (StatusCode::GONE, "invitation_already_used"),
+            InvitationError::Expired => (StatusCode::GONE, "invitation_expired"),
+            InvitationError::InvitationRevoked => (StatusCode::GONE, "invitation_revoked"),
+            InvitationError::InvitationAlreadyPending => {
+                (StatusCode::CONFLICT, "invitation_already_pending")
+            }
+            InvitationError::PasswordEncryptionFailed => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "password_encryption_failed",
+            ),
+            InvitationError::UserNotCreated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "user_not_created")
+            }
+            InvitationError::InvalidRedirectToUrl => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "invalid_redirect_to_url")
+            }
+            InvitationError::RoleError => (StatusCode::INTERNAL_SERVER_ERROR, "role_error"),
+            InvitationError::RoleNotFound => (StatusCode::BAD_REQUEST, "role_not_found"),
+            InvitationError::ClientNotFound => (StatusCode::BAD_REQUEST, "client_not_found"),
+            InvitationError::ClientError => (StatusCode::INTERNAL_SERVER_ERROR, "client_error"),
+            InvitationError::RedirectUriNotAllowed => {
+                (StatusCode::BAD_REQUEST, "redirect_uri_not_allowed")
+            }
+            InvitationError::InvalidImport => (StatusCode::BAD_REQUEST, "invalid_import"),
+            InvitationError::ImportTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "import_too_large"),
+        }
+    }
+}
+
+json_response_error!(InvitationError);
 
 #[derive(Debug, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
@@ -61,17 +136,20 @@ struct GetCompleteInvitationResponse {
 #[get("/api/invitation")]
 pub async fn get_complete_invitation(
     payload: web::Query<GetCompleteInvitationPayload>,
-    pool: web::Data<PgPool>,
+    users: web::Data<dyn UserRepository>,
+    invitations: web::Data<dyn InvitationRepository>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let invitation = Invitation::get_by_code(&pool, payload.invitation_challenge.as_str())
+    let invitation = invitations
+        .get_by_code(payload.invitation_challenge.as_str())
         .await
         .map_err(|_| InvitationError::InvitationNotFound)?;
 
     let invitation = invitation.ok_or(InvitationError::InvitationNotFound)?;
 
-    let user = User::get_by_email(&pool, &invitation.email)
+    let user = users
+        .get_by_email(&invitation.email)
         .await
         .map_err(|_| InvitationError::UserNotFound)?;
 
@@ -87,35 +165,114 @@ pub async fn get_complete_invitation(
 #[serde(rename_all = "camelCase")]
 pub struct CreateInvitationPayload {
     #[validate(length(min = 1))]
-    client_id: String,
+    pub(crate) client_id: String,
     #[validate(email)]
-    email: String,
+    pub(crate) email: String,
     #[validate(url)]
-    redirect_uri: String,
+    pub(crate) redirect_uri: String,
+    /// Names of (existing) roles assigned to the user once the invitation is completed.
+    #[serde(default)]
+    pub(crate) roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct InvitationEmail {
+    email: String,
+    invitation_url: String,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct CreateInvitationResponse {
+struct InvitationResponse {
     code: String,
     redirect_to: String,
 }
 
-/// Creates an invitation from an email.
+pub(crate) fn expires_at() -> NaiveDateTime {
+    Utc::now().naive_utc() + Duration::hours(zagreus_config::env::INVITATION::TTL_HOURS())
+}
+
+pub(crate) async fn send_invitation(
+    mailer: &Mailer,
+    email: &str,
+    code: &str,
+) -> Result<(), InvitationError> {
+    let invitation_url = format!(
+        "{url}/invitations/{challenge}",
+        url = mailer.url(),
+        challenge = code
+    );
+
+    mailer
+        .send(
+            email,
+            "invitation",
+            InvitationEmail {
+                email: email.to_string(),
+                invitation_url,
+            },
+        )
+        .await
+        .map_err(|_| InvitationError::InvitationNotSent)
+}
+
+/// The users can only be redirected to one of the redirect uris registered for the client.
+pub(crate) async fn check_redirect_uri(
+    clients: &dyn ClientRepository,
+    client_id: &str,
+    redirect_uri: &str,
+) -> Result<(), InvitationError> {
+    let client = clients
+        .get_by_id(client_id)
+        .await
+        .map_err(|_| InvitationError::ClientError)?
+        .ok_or(InvitationError::ClientNotFound)?;
+
+    if !client.is_redirect_uri_allowed(redirect_uri) {
+        return Err(InvitationError::RedirectUriNotAllowed);
+    }
+
+    Ok(())
+}
+
+async fn get_invitation(
+    invitations: &dyn InvitationRepository,
+    id: &Uuid,
+) -> Result<Invitation, InvitationError> {
+    let invitation = invitations
+        .get_by_id(id)
+        .await
+        .map_err(|_| InvitationError::InvitationNotFound)?;
+
+    invitation.ok_or(InvitationError::InvitationNotFound)
+}
+
+/// Creates an invitation from an email and sends the invitation link to that email.
+/// An expired pending invitation for the same email is refreshed instead, while a valid one
+/// must be resent or revoked first.
 #[post("/api/invitation")]
 pub async fn create_invitation(
+    admin: AdminSession,
     payload: web::Json<CreateInvitationPayload>,
-    pool: web::Data<PgPool>,
+    users: web::Data<dyn UserRepository>,
+    invitations: web::Data<dyn InvitationRepository>,
+    clients: web::Data<dyn ClientRepository>,
+    roles: web::Data<dyn RoleRepository>,
+    mailer: web::Data<Mailer>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let code: String = thread_rng()
-        .sample_iter(distributions::Alphanumeric)
-        .take(12)
-        .map(char::from)
-        .collect();
+    check_redirect_uri(
+        &**clients,
+        payload.client_id.as_str(),
+        payload.redirect_uri.as_str(),
+    )
+    .await?;
+
+    let code = codes::generate();
 
-    let user = User::get_by_email(&pool, payload.email.as_str())
+    let user = users
+        .get_by_email(payload.email.as_str())
         .await
         .map_err(|_| InvitationError::UserError)?;
 
@@ -123,29 +280,159 @@ pub async fn create_invitation(
         return Err(InvitationError::EmailAlreadyExists.into());
     }
 
-    Invitation::create(
-        &pool,
-        payload.email.as_str(),
-        code.as_str(),
-        &payload.client_id,
-        payload.redirect_uri.as_str(),
-    )
-    .await
-    .map_err(|_| InvitationError::InvitationNotCreated)?;
+    let roles = roles
+        .get_by_names(&payload.roles)
+        .await
+        .map_err(|_| InvitationError::RoleError)?;
+
+    if !payload
+        .roles
+        .iter()
+        .all(|name| roles.iter().any(|role| &role.name == name))
+    {
+        return Err(InvitationError::RoleNotFound.into());
+    }
 
-    Ok(HttpResponse::Ok().json(CreateInvitationResponse {
+    let pending_invitation = invitations
+        .get_pending_by_email(payload.email.as_str())
+        .await
+        .map_err(|_| InvitationError::InvitationNotCreated)?;
+
+    if matches!(&pending_invitation, Some(invitation) if !invitation.is_expired()) {
+        return Err(InvitationError::InvitationAlreadyPending.into());
+    }
+
+    let invitation = NewInvitation {
+        email: payload.email.clone(),
+        code: code.clone(),
+        idp_client_id: payload.client_id.clone(),
+        redirect_uri: payload.redirect_uri.clone(),
+        expires_at: expires_at(),
+        roles: payload.roles.clone(),
+    };
+
+    let send = async {
+        send_invitation(&mailer, payload.email.as_str(), code.as_str())
+            .await
+            .map_err(anyhow::Error::from)
+    };
+
+    // The invitation is only kept once its email has been sent, so that a failed
+    // delivery can be retried right away
+    invitations
+        .create_and_then(
+            &invitation,
+            pending_invitation.as_ref().map(|invitation| &invitation.id),
+            Box::pin(send),
+        )
+        .await
+        .map_err(|error| {
+            error
+                .downcast::<InvitationError>()
+                .unwrap_or(InvitationError::InvitationNotCreated)
+        })?
+        .ok_or(InvitationError::InvitationNotFound)?;
+
+    info!(
+        "An invitation has been sent to {} by {}",
+        payload.email, admin.subject
+    );
+
+    Ok(HttpResponse::Ok().json(InvitationResponse {
         code,
         redirect_to: String::from("/"),
     }))
 }
 
+/// Sends the invitation again with a new code and a new expiration date,
+/// the previous invitation link doesn't work anymore.
+#[post("/api/invitation/{id}/resend")]
+pub async fn resend_invitation(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    invitations: web::Data<dyn InvitationRepository>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    let invitation = get_invitation(&**invitations, &id).await?;
+
+    if invitation.used_at.is_some() {
+        return Err(InvitationError::InvitationAlreadyUsed.into());
+    }
+
+    if invitation.is_revoked() {
+        return Err(InvitationError::InvitationRevoked.into());
+    }
+
+    let code = codes::generate();
+
+    invitations
+        .refresh(
+            &invitation.id,
+            code.as_str(),
+            invitation.idp_client_id.as_str(),
+            invitation.redirect_uri.as_str(),
+            &expires_at(),
+            &invitation.roles,
+        )
+        .await
+        .map_err(|_| InvitationError::InvitationNotUpdated)?
+        .ok_or(InvitationError::InvitationNotFound)?;
+
+    send_invitation(&mailer, invitation.email.as_str(), code.as_str()).await?;
+
+    info!(
+        "Invitation {} has been resent by {}",
+        invitation.id, admin.subject
+    );
+
+    Ok(HttpResponse::Ok().json(InvitationResponse {
+        code,
+        redirect_to: String::from("/"),
+    }))
+}
+
+/// Revokes a pending invitation, its link doesn't work anymore and the email can be invited again.
+#[delete("/api/invitation/{id}")]
+pub async fn revoke_invitation(
+    admin: AdminSession,
+    id: web::Path<Uuid>,
+    invitations: web::Data<dyn InvitationRepository>,
+) -> Result<HttpResponse> {
+    let invitation = get_invitation(&**invitations, &id).await?;
+
+    if invitation.used_at.is_some() {
+        return Err(InvitationError::InvitationAlreadyUsed.into());
+    }
+
+    if !invitation.is_revoked() {
+        invitations
+            .update_revoked_at(&invitation.id, &Utc::now().naive_utc())
+            .await
+            .map_err(|_| InvitationError::InvitationNotUpdated)?;
+
+        info!(
+            "Invitation {} has been revoked by {}",
+            invitation.id, admin.subject
+        );
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[derive(Debug, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct CompleteInvitationPayload {
     #[validate(length(min = 1))]
     invitation_challenge: String,
-    #[validate(custom = "validate_password")]
+    /// Checked against the password policy once the invitation is found.
     password: String,
+    #[validate(length(min = 1, max = 256))]
+    name: Option<String>,
+    #[validate(length(min = 2, max = 35))]
+    locale: Option<String>,
+    #[validate(length(min = 1, max = 64))]
+    timezone: Option<String>,
+    /// Stored as the user metadata.
     extra_payload: Option<HashMap<String, String>>,
     #[validate(custom = "validate_terms_accepted")]
     terms_accepted: bool,
@@ -158,17 +445,20 @@ struct CompleteInvitationResponse {
 }
 
 /// Completes an invitation, that is, register a user.
-/// Additionally to the required password and terms values a generic "payload" attribute
-/// that will be serialized and injected into the redirect url _use with care and don't send
+/// Additionally to the required password and terms values the profile of the user can be
+/// provided, including a generic "payload" attribute that is stored as the user metadata,
+/// and that will be serialized and injected into the redirect url _use with care and don't send
 /// sensitive data in the `payload` attribute_.
 #[put("/api/invitation")]
 pub async fn complete_invitation(
     payload: web::Json<CompleteInvitationPayload>,
-    pool: web::Data<PgPool>,
+    invitations: web::Data<dyn InvitationRepository>,
+    clients: web::Data<dyn ClientRepository>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let invitation = Invitation::get_by_code(&pool, payload.invitation_challenge.as_str())
+    let invitation = invitations
+        .get_by_code(payload.invitation_challenge.as_str())
         .await
         .map_err(|_| InvitationError::InvitationNotFound)?;
 
@@ -178,6 +468,28 @@ pub async fn complete_invitation(
         return Err(InvitationError::InvitationAlreadyUsed.into());
     }
 
+    if invitation.is_revoked() {
+        return Err(InvitationError::InvitationRevoked.into());
+    }
+
+    if invitation.is_expired() {
+        return Err(InvitationError::Expired.into());
+    }
+
+    // The redirect uris of the client may have changed since the invitation was created
+    check_redirect_uri(
+        &**clients,
+        invitation.idp_client_id.as_str(),
+        invitation.redirect_uri.as_str(),
+    )
+    .await?;
+
+    if let Err(validation_errors) =
+        PASSWORD_POLICY.validate(payload.password.as_str(), invitation.email.as_str())
+    {
+        return Ok(validation_errors_response(&validation_errors));
+    }
+
     let salt = SaltString::generate(&mut OsRng);
 
     let argon2 = Argon2::default();
@@ -189,24 +501,31 @@ pub async fn complete_invitation(
 
     let terms_accepted_at = Utc::now().naive_utc();
 
-    let new_user_id = User::create(
-        &pool,
-        invitation.email.as_str(),
-        encrypted_password.as_str(),
-        &terms_accepted_at,
-    )
-    .await
-    .map_err(|_| InvitationError::UserNotCreated)?;
-
-    let invitation_id = Invitation::update_used_at(
-        &pool,
-        payload.invitation_challenge.as_str(),
-        &terms_accepted_at,
-    )
-    .await
-    .map_err(|_| InvitationError::InvitationNotUpdated)?;
+    let metadata = payload
+        .extra_payload
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
 
-    invitation_id.ok_or(InvitationError::InvitationNotFound)?;
+    let profile = UserProfile {
+        name: payload.name.clone(),
+        locale: payload.locale.clone(),
+        timezone: payload.timezone.clone(),
+        metadata: Value::Object(metadata),
+    };
+
+    // Concurrent completions of the same invitation only create a single user
+    let new_user_id = invitations
+        .complete(
+            &invitation,
+            encrypted_password.as_str(),
+            &terms_accepted_at,
+            &profile,
+        )
+        .await
+        .map_err(|_| InvitationError::UserNotCreated)?
+        .ok_or(InvitationError::InvitationAlreadyUsed)?;
 
     let mut redirect_to = Url::parse(invitation.redirect_uri.as_str())
         .map_err(|_| InvitationError::InvalidRedirectToUrl)?;
@@ -228,3 +547,258 @@ pub async fn complete_invitation(
         redirect_to: redirect_to.to_string(),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tera::Tera;
+    use url::Url;
+    use uuid::Uuid;
+    use zagreus_domain::repositories::{
+        memory::MemoryRepository, ClientRepository, InvitationRepository, RoleRepository,
+        UserRepository,
+    };
+
+    use super::{complete_invitation, create_invitation, revoke_invitation};
+    use crate::mailer::{memory::MemoryTransport, Mailer};
+    use crate::test_utils::{as_admin, client};
+
+    /// Creates an invitation to the `client` client, returns its id.
+    async fn invite(
+        repository: &MemoryRepository,
+        code: &str,
+        expires_in: Duration,
+        roles: &[String],
+    ) -> Uuid {
+        InvitationRepository::create(
+            repository,
+            "alice@example.com",
+            code,
+            "client",
+            "https://example.com",
+            &(Utc::now() + expires_in).naive_utc(),
+            roles,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn complete_app_data(
+        repository: &MemoryRepository,
+    ) -> (Data<dyn InvitationRepository>, Data<dyn ClientRepository>) {
+        repository
+            .insert_client(client("client", &["https://example.com"]))
+            .unwrap();
+
+        (
+            Data::from(Arc::new(repository.clone()) as Arc<_>),
+            Data::from(Arc::new(repository.clone()) as Arc<_>),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn it_completes_an_invitation_once() {
+        let repository = MemoryRepository::default();
+
+        let role_id = repository.insert_role("editor", &["posts:write"]).unwrap();
+
+        invite(
+            &repository,
+            "code",
+            Duration::hours(1),
+            &[String::from("editor")],
+        )
+        .await;
+
+        let (invitations, clients) = complete_app_data(&repository);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(invitations)
+                .app_data(clients)
+                .service(complete_invitation),
+        )
+        .await;
+
+        let complete = || {
+            test::TestRequest::put()
+                .uri("/api/invitation")
+                .set_json(&json!({
+                    "invitationChallenge": "code",
+                    "password": "Correct-Horse-9",
+                    "name": "Alice",
+                    "termsAccepted": true,
+                }))
+                .to_request()
+        };
+
+        let body: Value = test::read_response_json(&app, complete()).await;
+
+        let user = UserRepository::get_by_email(&repository, "alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let redirect_to = Url::parse(body["redirectTo"].as_str().unwrap()).unwrap();
+
+        assert!(redirect_to
+            .query_pairs()
+            .any(|(key, value)| key == "user_id" && value == user.id.to_string()));
+
+        assert_eq!(user.name.as_deref(), Some("Alice"));
+
+        let roles = RoleRepository::get_by_user_id(&repository, &user.id, Some("client"))
+            .await
+            .unwrap();
+
+        assert!(matches!(roles.as_slice(), [role] if role.id == role_id));
+
+        let res = test::call_service(&app, complete()).await;
+
+        assert_eq!(res.status(), StatusCode::GONE);
+
+        let body: Value = test::read_body_json(res).await;
+
+        assert_eq!(body["code"], "invitation_already_used");
+    }
+
+    #[actix_rt::test]
+    async fn it_rejects_an_expired_invitation() {
+        let repository = MemoryRepository::default();
+
+        invite(&repository, "code", Duration::minutes(-1), &[]).await;
+
+        let (invitations, clients) = complete_app_data(&repository);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(invitations)
+                .app_data(clients)
+                .service(complete_invitation),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/api/invitation")
+            .set_json(&json!({
+                "invitationChallenge": "code",
+                "password": "Correct-Horse-9",
+                "termsAccepted": true,
+            }))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        let status = res.status();
+
+        let body: Value = test::read_body_json(res).await;
+
+        let user = UserRepository::get_by_email(&repository, "alice@example.com")
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::GONE);
+        assert_eq!(body["code"], "invitation_expired");
+        assert!(user.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn it_revokes_a_pending_invitation() {
+        let repository = MemoryRepository::default();
+
+        let id = invite(&repository, "code", Duration::days(1), &[]).await;
+
+        let invitations: Data<dyn InvitationRepository> =
+            Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(as_admin)
+                .app_data(invitations)
+                .service(revoke_invitation),
+        )
+        .await;
+
+        // Revoking is idempotent
+        for _ in 0..2 {
+            let req = test::TestRequest::delete()
+                .uri(format!("/api/invitation/{}", id).as_str())
+                .to_request();
+
+            let res = test::call_service(&app, req).await;
+
+            assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        }
+
+        let invitation = InvitationRepository::get_by_id(&repository, &id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(invitation.is_revoked());
+
+        let req = test::TestRequest::delete()
+            .uri(format!("/api/invitation/{}", Uuid::new_v4()).as_str())
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn it_drops_the_invitation_when_its_email_isnt_sent() {
+        lazy_static! {
+            // Without the invitation templates, the email can't be sent
+            static ref TEMPLATES: Tera = Tera::default();
+        }
+
+        let repository = MemoryRepository::default();
+
+        let (invitations, clients) = complete_app_data(&repository);
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let roles: Data<dyn RoleRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let mailer = Mailer::new(
+            String::from("zagreus@example.com"),
+            String::from("http://zagreus.test"),
+            &TEMPLATES,
+            Box::new(MemoryTransport::default()),
+        );
+
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(as_admin)
+                .app_data(users)
+                .app_data(invitations)
+                .app_data(clients)
+                .app_data(roles)
+                .app_data(Data::new(mailer))
+                .service(create_invitation),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/invitation")
+            .set_json(&json!({
+                "clientId": "client",
+                "email": "alice@example.com",
+                "redirectUri": "https://example.com",
+            }))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        let invitation =
+            InvitationRepository::get_pending_by_email(&repository, "alice@example.com")
+                .await
+                .unwrap();
+
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        assert!(invitation.is_none());
+    }
+}
@@ -0,0 +1,400 @@
+/// Bulk invitations, imported from a csv file with an `email` column and an optional `roles`
+/// column (the role names separated by spaces).
+use actix_multipart::Multipart;
+use actix_web::{post, web, HttpResponse, Result};
+use futures_util::TryStreamExt;
+use log::info;
+use serde::Serialize;
+use std::collections::HashSet;
+use validator::Validate;
+use zagreus_domain::{
+    db::PgPool,
+    models::{
+        invitation::{Invitation, NewInvitation},
+        role::Role,
+        user::User,
+    },
+};
+
+use super::invitation::{
+    check_redirect_uri, expires_at, send_invitation, CreateInvitationPayload, InvitationError,
+};
+use crate::codes;
+use crate::errors::ErrorDetails;
+use crate::mailer::Mailer;
+use crate::session::AdminSession;
+
+/// The csv file can't be larger than 1MiB.
+const MAX_IMPORT_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Created,
+    /// The user already exists, or has a pending invitation.
+    Skipped,
+    Invalid,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedRow {
+    /// The line of the row in the csv file.
+    pub line: u64,
+    pub email: Option<String>,
+    pub status: ImportStatus,
+    /// The error codes explaining why the row has been skipped or is invalid.
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub created: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+    pub rows: Vec<ImportedRow>,
+}
+
+struct ParsedRow {
+    line: u64,
+    email: Option<String>,
+    payload: Result<CreateInvitationPayload, Vec<String>>,
+}
+
+fn code(error: InvitationError) -> String {
+    error.details().1.to_string()
+}
+
+/// Parses the csv rows and validates them with the same rules as a single invitation.
+fn parse(csv: &[u8], client_id: &str, redirect_uri: &str) -> Vec<ParsedRow> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv);
+
+    let invalid_import = |line| ParsedRow {
+        line,
+        email: None,
+        payload: Err(vec![code(InvitationError::InvalidImport)]),
+    };
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(_) => return vec![invalid_import(1)],
+    };
+
+    let email_column = match headers.iter().position(|header| header == "email") {
+        Some(email_column) => email_column,
+        None => return vec![invalid_import(1)],
+    };
+
+    let roles_column = headers.iter().position(|header| header == "roles");
+
+    reader
+        .records()
+        .enumerate()
+        .map(|(index, record)| {
+            let record = match record {
+                Ok(record) => record,
+                Err(_) => return invalid_import(index as u64 + 2),
+            };
+
+            let line = record
+                .position()
+                .map(|position| position.line())
+                .unwrap_or(index as u64 + 2);
+
+            let email = match record.get(email_column) {
+                Some(email) => email.to_string(),
+                None => return invalid_import(line),
+            };
+
+            let roles = roles_column
+                .and_then(|roles_column| record.get(roles_column))
+                .unwrap_or_default();
+
+            let payload = CreateInvitationPayload {
+                client_id: client_id.to_string(),
+                email: email.clone(),
+                redirect_uri: redirect_uri.to_string(),
+                roles: roles.split_whitespace().map(String::from).collect(),
+            };
+
+            let payload = match payload.validate() {
+                Ok(()) => Ok(payload),
+                Err(validation_errors) => {
+                    let mut fields: Vec<String> = validation_errors
+                        .field_errors()
+                        .keys()
+                        .map(|field| format!("invalid_{}", field))
+                        .collect();
+
+                    fields.sort();
+
+                    Err(fields)
+                }
+            };
+
+            ParsedRow {
+                line,
+                email: Some(email),
+                payload,
+            }
+        })
+        .collect()
+}
+
+/// Creates the invitations of all the valid rows in a single transaction, then sends them.
+/// Returns the outcome of each row.
+pub async fn import(
+    pool: &PgPool,
+    mailer: &Mailer,
+    csv: &[u8],
+    client_id: &str,
+    redirect_uri: &str,
+) -> Result<ImportReport, InvitationError> {
+    check_redirect_uri(pool, client_id, redirect_uri).await?;
+
+    let parsed_rows = parse(csv, client_id, redirect_uri);
+
+    let role_names: Vec<String> = parsed_rows
+        .iter()
+        .filter_map(|row| row.payload.as_ref().ok())
+        .flat_map(|payload| payload.roles.iter().cloned())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let roles = Role::get_by_names(pool, &role_names)
+        .await
+        .map_err(|_| InvitationError::RoleError)?;
+
+    let mut emails = HashSet::new();
+
+    let mut rows = Vec::with_capacity(parsed_rows.len());
+
+    let mut new_invitations = Vec::new();
+
+    for parsed_row in parsed_rows {
+        let mut row = ImportedRow {
+            line: parsed_row.line,
+            email: parsed_row.email,
+            status: ImportStatus::Invalid,
+            errors: Vec::new(),
+        };
+
+        let payload = match parsed_row.payload {
+            Ok(payload) => payload,
+            Err(errors) => {
+                row.errors = errors;
+                rows.push(row);
+                continue;
+            }
+        };
+
+        if !payload
+            .roles
+            .iter()
+            .all(|name| roles.iter().any(|role| &role.name == name))
+        {
+            row.errors.push(code(InvitationError::RoleNotFound));
+            rows.push(row);
+            continue;
+        }
+
+        row.status = ImportStatus::Skipped;
+
+        // The same email is only invited once
+        if !emails.insert(payload.email.to_lowercase()) {
+            row.errors
+                .push(code(InvitationError::InvitationAlreadyPending));
+            rows.push(row);
+            continue;
+        }
+
+        let user = User::get_by_email(pool, payload.email.as_str())
+            .await
+            .map_err(|_| InvitationError::UserError)?;
+
+        if user.is_some() {
+            row.errors.push(code(InvitationError::EmailAlreadyExists));
+            rows.push(row);
+            continue;
+        }
+
+        let pending_invitation = Invitation::get_pending_by_email(pool, payload.email.as_str())
+            .await
+            .map_err(|_| InvitationError::InvitationNotCreated)?;
+
+        if matches!(pending_invitation, Some(invitation) if !invitation.is_expired()) {
+            row.errors
+                .push(code(InvitationError::InvitationAlreadyPending));
+            rows.push(row);
+            continue;
+        }
+
+        row.status = ImportStatus::Created;
+        rows.push(row);
+
+        new_invitations.push(NewInvitation {
+            email: payload.email,
+            code: codes::generate(),
+            idp_client_id: payload.client_id,
+            redirect_uri: payload.redirect_uri,
+            expires_at: expires_at(),
+            roles: payload.roles,
+        });
+    }
+
+    Invitation::create_all(pool, &new_invitations)
+        .await
+        .map_err(|_| InvitationError::InvitationNotCreated)?;
+
+    let created_rows = rows
+        .iter_mut()
+        .filter(|row| row.status == ImportStatus::Created);
+
+    // The invitations can be resent one by one if an email couldn't be sent
+    for (row, invitation) in created_rows.zip(new_invitations.iter()) {
+        if send_invitation(mailer, invitation.email.as_str(), invitation.code.as_str())
+            .await
+            .is_err()
+        {
+            row.errors.push(code(InvitationError::InvitationNotSent));
+        }
+    }
+
+    let count = |status: ImportStatus| rows.iter().filter(|row| row.status == status).count();
+
+    Ok(ImportReport {
+        created: count(ImportStatus::Created),
+        skipped: count(ImportStatus::Skipped),
+        invalid: count(ImportStatus::Invalid),
+        rows,
+    })
+}
+
+#[derive(Debug, Default)]
+struct ImportForm {
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    file: Option<Vec<u8>>,
+}
+
+async fn read_form(mut multipart: Multipart) -> Result<ImportForm, InvitationError> {
+    let mut form = ImportForm::default();
+
+    while let Some(mut field) = multipart
+        .try_next()
+        .await
+        .map_err(|_| InvitationError::InvalidImport)?
+    {
+        let name = field
+            .content_disposition()
+            .and_then(|content_disposition| content_disposition.get_name().map(String::from))
+            .ok_or(InvitationError::InvalidImport)?;
+
+        let mut value = Vec::new();
+
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|_| InvitationError::InvalidImport)?
+        {
+            if value.len() + chunk.len() > MAX_IMPORT_SIZE {
+                return Err(InvitationError::ImportTooLarge);
+            }
+
+            value.extend_from_slice(&chunk);
+        }
+
+        match name.as_str() {
+            "clientId" => form.client_id = String::from_utf8(value).ok(),
+            "redirectUri" => form.redirect_uri = String::from_utf8(value).ok(),
+            "file" => form.file = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(form)
+}
+
+/// Imports invitations from a csv `file`, sent as `multipart/form-data` along with the
+/// `clientId` and `redirectUri` of all the invitations.
+#[post("/api/invitations/bulk")]
+pub async fn import_invitations(
+    admin: AdminSession,
+    multipart: Multipart,
+    pool: web::Data<PgPool>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    let form = read_form(multipart).await?;
+
+    let (client_id, redirect_uri, file) = match (form.client_id, form.redirect_uri, form.file) {
+        (Some(client_id), Some(redirect_uri), Some(file)) => (client_id, redirect_uri, file),
+        _ => return Err(InvitationError::InvalidImport.into()),
+    };
+
+    let report = import(
+        &pool,
+        &mailer,
+        &file,
+        client_id.as_str(),
+        redirect_uri.as_str(),
+    )
+    .await?;
+
+    info!(
+        "{} invitations have been imported by {}",
+        report.created, admin.subject
+    );
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn it_parses_and_validates_the_rows() {
+        let csv = b"email,roles\n\
+            alice@example.com,admin editor\n\
+            not-an-email,\n\
+            bob@example.com\n\
+            \xff@example.com,admin\n";
+
+        let rows = parse(csv, "client", "https://example.com");
+
+        assert_eq!(rows.len(), 4);
+
+        let alice = rows[0].payload.as_ref().unwrap();
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(alice.email, "alice@example.com");
+        assert_eq!(alice.roles, vec!["admin", "editor"]);
+
+        assert_eq!(
+            rows[1].payload.as_ref().unwrap_err(),
+            &vec!["invalid_email".to_string()]
+        );
+
+        assert!(rows[2].payload.as_ref().unwrap().roles.is_empty());
+
+        assert_eq!(
+            rows[3].payload.as_ref().unwrap_err(),
+            &vec!["invalid_import".to_string()]
+        );
+        assert_eq!(rows[3].line, 5);
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_redirect_uri_on_every_row() {
+        let rows = parse(b"email\nalice@example.com\n", "client", "not a url");
+
+        assert_eq!(
+            rows[0].payload.as_ref().unwrap_err(),
+            &vec!["invalid_redirect_uri".to_string()]
+        );
+    }
+}
@@ -1,28 +1,95 @@
-use actix_web::{post, web, HttpResponse, ResponseError, Result};
+use actix_web::{http::StatusCode, post, web, Error, HttpRequest, HttpResponse, Result};
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use ory_hydra_client::apis::admin_api::accept_login_request;
+use chrono::{Duration, Utc};
 use ory_hydra_client::models::AcceptLoginRequest;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use url::form_urlencoded;
 use validator::Validate;
-use zagreus_domain::{db::PgPool, models::user::User};
+use zagreus_domain::{
+    models::user::User,
+    repositories::{
+        LoginFailureRepository, TotpChallengeRepository, TotpRecoveryCodeRepository, UserRepository,
+    },
+};
 
-use crate::hydra_configuration::CONFIGURATION;
-use crate::validations::{validate, validate_password};
+use crate::codes;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+use crate::lockout::{self, Lock};
+use crate::middlewares::rate_limit::ClientIp;
+use crate::totp;
+use crate::validations::validate;
+
+/// Number of minutes the user has to provide the totp code once the password has been verified.
+const TOTP_CHALLENGE_TTL_MINUTES: i64 = 5;
+
+/// The hash of a random password with the `Argon2::default()` parameters, verified when the email
+/// is unknown so that the response takes as long as for a wrong password.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=4096,t=3,p=1$AbtygLtbvo94IqvKtnv3OA$wYR3uvK5qCfrQdeAjkkGWpNSuFrtzwHFrmM1JFMNOmU";
 
 #[derive(Error, Debug)]
 pub enum LoginError {
-    #[error("user not found")]
-    UserNotFound,
+    #[error("invalid email or password")]
+    InvalidCredentials,
     #[error("persisted encrypted password couldn't be hashed")]
     PersistedPasswordInvalidFormat,
-    #[error("invalid password")]
-    InvalidPassword,
     #[error("login request rejected")]
     LoginRequestRejected,
+    #[error("user request error")]
+    UserError,
+    #[error("totp challenge couldn't be created")]
+    TotpChallengeNotCreated,
+    #[error("totp challenge couldn't be found")]
+    TotpChallengeNotFound,
+    #[error("totp challenge has already been used")]
+    TotpChallengeAlreadyUsed,
+    #[error("totp challenge has expired")]
+    TotpChallengeExpired,
+    #[error("totp is not enabled")]
+    TotpNotEnabled,
+    #[error("invalid totp code")]
+    InvalidTotpCode,
+    #[error("account is temporarily locked")]
+    AccountLocked,
+    #[error("user is disabled")]
+    UserDisabled,
+    #[error("too many failed login attempts")]
+    TooManyAttempts,
+}
+
+impl ErrorDetails for LoginError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            LoginError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
+            LoginError::PersistedPasswordInvalidFormat => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "persisted_password_invalid_format",
+            ),
+            LoginError::LoginRequestRejected => (StatusCode::BAD_GATEWAY, "login_request_rejected"),
+            LoginError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            LoginError::TotpChallengeNotCreated => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "totp_challenge_not_created",
+            ),
+            LoginError::TotpChallengeNotFound => {
+                (StatusCode::NOT_FOUND, "totp_challenge_not_found")
+            }
+            LoginError::TotpChallengeAlreadyUsed => {
+                (StatusCode::GONE, "totp_challenge_already_used")
+            }
+            LoginError::TotpChallengeExpired => (StatusCode::GONE, "totp_challenge_expired"),
+            LoginError::TotpNotEnabled => (StatusCode::BAD_REQUEST, "totp_not_enabled"),
+            LoginError::InvalidTotpCode => (StatusCode::UNAUTHORIZED, "invalid_totp_code"),
+            LoginError::AccountLocked => (StatusCode::LOCKED, "account_locked"),
+            LoginError::UserDisabled => (StatusCode::FORBIDDEN, "user_disabled"),
+            LoginError::TooManyAttempts => (StatusCode::TOO_MANY_REQUESTS, "too_many_attempts"),
+        }
+    }
 }
 
-impl ResponseError for LoginError {}
+json_response_error!(LoginError);
 
 #[derive(Debug, Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
@@ -31,7 +98,8 @@ pub struct LoginPayload {
     login_challenge: String,
     #[validate(email)]
     email: String,
-    #[validate(custom = "validate_password")]
+    /// Not checked against the password policy, which may have changed since the password was set.
+    #[validate(length(min = 1))]
     password: String,
 }
 
@@ -41,41 +109,312 @@ struct LoginResponse {
     redirect_to: String,
 }
 
+/// The ip address of the client, forwarded by a trusted proxy (see `middlewares::rate_limit`).
+pub(crate) fn peer_ip(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| *ip)
+        .or_else(|| req.peer_addr().map(|addr| addr.ip()))
+        .map(|ip| ip.to_string())
+}
+
+/// Rejects the attempt if the email or the ip address is locked.
+/// The other endpoints checking the password (totp, passkey registration) share the lock.
+pub(crate) async fn check_lock(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<(), LoginError> {
+    let lock = lockout::get_lock(login_failures, email, ip)
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    match lock {
+        Some(Lock::Account) => Err(LoginError::AccountLocked),
+        Some(Lock::Ip) => Err(LoginError::TooManyAttempts),
+        None => Ok(()),
+    }
+}
+
+/// Spends the time of a password verification, see `DUMMY_PASSWORD_HASH`.
+pub(crate) fn verify_dummy_password(password: &str) {
+    if let Ok(password_hash) = PasswordHash::new(DUMMY_PASSWORD_HASH) {
+        let _ = Argon2::default().verify_password(password.as_bytes(), &password_hash);
+    }
+}
+
+/// Counts the failed attempt and returns the error.
+pub(crate) async fn fail<E>(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+    ip: Option<&str>,
+    error: E,
+) -> Error
+where
+    E: Into<Error>,
+{
+    match lockout::record_failure(login_failures, email, ip).await {
+        Ok(_) => error.into(),
+        Err(_) => LoginError::UserError.into(),
+    }
+}
+
 /// Take credentials and try to authenticate the user.
+/// Users with totp enabled are redirected to the totp form instead (see `login_totp`).
 #[post("/api/login")]
 pub async fn login(
+    req: HttpRequest,
     payload: web::Json<LoginPayload>,
-    pool: web::Data<PgPool>,
+    users: web::Data<dyn UserRepository>,
+    totp_challenges: web::Data<dyn TotpChallengeRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let user = User::get_by_email(&pool, payload.email.as_str())
+    let ip = peer_ip(&req);
+
+    check_lock(&**login_failures, payload.email.as_str(), ip.as_deref()).await?;
+
+    let user = users
+        .get_by_email(payload.email.as_str())
         .await
-        .map_err(|_| LoginError::UserNotFound)?;
+        .map_err(|_| LoginError::UserError)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => {
+            verify_dummy_password(payload.password.as_str());
 
-    let user = user.ok_or(LoginError::UserNotFound)?;
+            return Err(fail(
+                &**login_failures,
+                payload.email.as_str(),
+                ip.as_deref(),
+                LoginError::InvalidCredentials,
+            )
+            .await);
+        }
+    };
 
     let password_hash = PasswordHash::new(user.encrypted_password.as_str())
         .map_err(|_| LoginError::PersistedPasswordInvalidFormat)?;
 
     let argon2 = Argon2::default();
 
-    argon2
+    if argon2
         .verify_password(payload.password.as_bytes(), &password_hash)
-        .map_err(|_| LoginError::InvalidPassword)?;
-
-    let completed_request = accept_login_request(
-        &CONFIGURATION,
-        payload.login_challenge.as_str(),
-        Some(AcceptLoginRequest {
-            remember: Some(true),
-            ..AcceptLoginRequest::new(user.id.to_string())
-        }),
-    )
-    .await
-    .map_err(|_| LoginError::LoginRequestRejected)?;
+        .is_err()
+    {
+        return Err(fail(
+            &**login_failures,
+            payload.email.as_str(),
+            ip.as_deref(),
+            LoginError::InvalidCredentials,
+        )
+        .await);
+    }
+
+    if user.is_disabled() {
+        return Err(LoginError::UserDisabled.into());
+    }
+
+    if user.totp_enabled_at.is_some() {
+        let totp_challenge = codes::generate();
+
+        let expires_at = Utc::now().naive_utc() + Duration::minutes(TOTP_CHALLENGE_TTL_MINUTES);
+
+        totp_challenges
+            .create(
+                &user.id,
+                totp_challenge.as_str(),
+                payload.login_challenge.as_str(),
+                &expires_at,
+            )
+            .await
+            .map_err(|_| LoginError::TotpChallengeNotCreated)?;
+
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("totp_challenge", totp_challenge.as_str())
+            .finish();
+
+        return Ok(HttpResponse::Ok().json(LoginResponse {
+            redirect_to: format!("/login/totp?{}", query),
+        }));
+    }
+
+    lockout::unlock_account(&**login_failures, user.email.as_str())
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    let completed_request = hydra
+        .accept_login_request(
+            payload.login_challenge.as_str(),
+            AcceptLoginRequest {
+                remember: Some(true),
+                ..AcceptLoginRequest::new(user.id.to_string())
+            },
+        )
+        .await
+        .map_err(|_| LoginError::LoginRequestRejected)?;
 
     Ok(HttpResponse::Ok().json(LoginResponse {
         redirect_to: completed_request.redirect_to,
     }))
 }
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginTotpPayload {
+    #[validate(length(min = 1))]
+    totp_challenge: String,
+    /// Either a code generated by the authenticator app or a recovery code.
+    #[validate(length(min = 1))]
+    code: String,
+}
+
+/// Checks a recovery code against the user's unused ones and consumes it if it matches.
+pub(crate) async fn use_recovery_code(
+    totp_recovery_codes: &dyn TotpRecoveryCodeRepository,
+    user: &User,
+    code: &str,
+) -> Result<bool, LoginError> {
+    let recovery_codes = totp_recovery_codes
+        .get_unused_by_user_id(&user.id)
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    let argon2 = Argon2::default();
+
+    let recovery_code = recovery_codes.into_iter().find(|recovery_code| {
+        PasswordHash::new(recovery_code.encrypted_code.as_str())
+            .map(|hash| argon2.verify_password(code.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    });
+
+    let recovery_code = match recovery_code {
+        Some(recovery_code) => recovery_code,
+        None => return Ok(false),
+    };
+
+    let recovery_code_id = totp_recovery_codes
+        .update_used_at(&recovery_code.id, &Utc::now().naive_utc())
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    Ok(recovery_code_id.is_some())
+}
+
+/// Second login step for the users with totp enabled, takes the challenge
+/// created by `login` once the password has been verified.
+#[post("/api/login/totp")]
+pub async fn login_totp(
+    req: HttpRequest,
+    payload: web::Json<LoginTotpPayload>,
+    users: web::Data<dyn UserRepository>,
+    totp_challenges: web::Data<dyn TotpChallengeRepository>,
+    totp_recovery_codes: web::Data<dyn TotpRecoveryCodeRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let totp_challenge = totp_challenges
+        .get_by_code(payload.totp_challenge.as_str())
+        .await
+        .map_err(|_| LoginError::TotpChallengeNotFound)?;
+
+    let totp_challenge = totp_challenge.ok_or(LoginError::TotpChallengeNotFound)?;
+
+    if totp_challenge.used_at.is_some() {
+        return Err(LoginError::TotpChallengeAlreadyUsed.into());
+    }
+
+    if totp_challenge.is_expired() {
+        return Err(LoginError::TotpChallengeExpired.into());
+    }
+
+    let user = users
+        .get_by_id(&totp_challenge.user_id)
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    let user = user.ok_or(LoginError::TotpChallengeNotFound)?;
+
+    if user.is_disabled() {
+        return Err(LoginError::UserDisabled.into());
+    }
+
+    let ip = peer_ip(&req);
+
+    check_lock(&**login_failures, user.email.as_str(), ip.as_deref()).await?;
+
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Err(LoginError::TotpNotEnabled.into()),
+    };
+
+    let now = Utc::now();
+
+    let code = payload.code.trim();
+
+    if !totp::verify(secret.as_str(), code, now.timestamp() as u64)
+        && !use_recovery_code(&**totp_recovery_codes, &user, code).await?
+    {
+        return Err(fail(
+            &**login_failures,
+            user.email.as_str(),
+            ip.as_deref(),
+            LoginError::InvalidTotpCode,
+        )
+        .await);
+    }
+
+    lockout::unlock_account(&**login_failures, user.email.as_str())
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    let totp_challenge_id = totp_challenges
+        .update_used_at(payload.totp_challenge.as_str(), &now.naive_utc())
+        .await
+        .map_err(|_| LoginError::TotpChallengeNotFound)?;
+
+    totp_challenge_id.ok_or(LoginError::TotpChallengeAlreadyUsed)?;
+
+    let completed_request = hydra
+        .accept_login_request(
+            totp_challenge.login_challenge.as_str(),
+            AcceptLoginRequest {
+                remember: Some(true),
+                ..AcceptLoginRequest::new(user.id.to_string())
+            },
+        )
+        .await
+        .map_err(|_| LoginError::LoginRequestRejected)?;
+
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        redirect_to: completed_request.redirect_to,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher};
+    use rand_core::OsRng;
+
+    use super::DUMMY_PASSWORD_HASH;
+
+    #[test]
+    fn it_hashes_the_dummy_password_like_the_real_ones() {
+        let dummy_password_hash = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+
+        let salt = SaltString::generate(&mut OsRng);
+
+        let password_hash = Argon2::default()
+            .hash_password_simple(b"password", salt.as_ref())
+            .unwrap();
+
+        assert_eq!(dummy_password_hash.algorithm, password_hash.algorithm);
+        assert_eq!(dummy_password_hash.version, password_hash.version);
+        assert_eq!(dummy_password_hash.params, password_hash.params);
+    }
+}
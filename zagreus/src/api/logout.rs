@@ -1,10 +1,14 @@
-use actix_web::{get, http::header, web, HttpResponse, ResponseError, Result};
-use ory_hydra_client::apis::admin_api::{accept_logout_request, get_logout_request};
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web, HttpResponse, Result,
+};
 use serde::Deserialize;
 use thiserror::Error;
 use validator::Validate;
 
-use crate::hydra_configuration::CONFIGURATION;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
 use crate::validations::validate;
 
 #[derive(Debug, Error)]
@@ -13,7 +17,17 @@ pub enum LogoutError {
     LogoutRequestRejected,
 }
 
-impl ResponseError for LogoutError {}
+impl ErrorDetails for LogoutError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            LogoutError::LogoutRequestRejected => {
+                (StatusCode::BAD_GATEWAY, "logout_request_rejected")
+            }
+        }
+    }
+}
+
+json_response_error!(LogoutError);
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct LogoutPayload {
@@ -23,19 +37,79 @@ pub struct LogoutPayload {
 
 /// Log a user out.
 #[get("/api/logout")]
-pub async fn logout(payload: web::Query<LogoutPayload>) -> Result<HttpResponse> {
+pub async fn logout(
+    payload: web::Query<LogoutPayload>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
     validate!(payload);
 
-    get_logout_request(&CONFIGURATION, payload.logout_challenge.as_str())
+    hydra
+        .get_logout_request(payload.logout_challenge.as_str())
         .await
         .map_err(|_| LogoutError::LogoutRequestRejected)?;
 
-    let completed_request =
-        accept_logout_request(&CONFIGURATION, payload.logout_challenge.as_str())
-            .await
-            .map_err(|_| LogoutError::LogoutRequestRejected)?;
+    let completed_request = hydra
+        .accept_logout_request(payload.logout_challenge.as_str())
+        .await
+        .map_err(|_| LogoutError::LogoutRequestRejected)?;
 
     Ok(HttpResponse::PermanentRedirect()
         .append_header((header::LOCATION, completed_request.redirect_to))
         .finish())
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, StatusCode},
+        test,
+        web::Data,
+        App,
+    };
+    use std::sync::Arc;
+
+    use super::logout;
+    use crate::hydra::{
+        fake::{FakeHydraAdmin, HydraCall},
+        HydraAdmin,
+    };
+
+    #[actix_rt::test]
+    async fn it_accepts_the_logout_request() {
+        let fake_hydra = FakeHydraAdmin::default();
+
+        fake_hydra.add_logout_request("challenge", "subject");
+
+        let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(fake_hydra.clone()) as Arc<_>);
+
+        let app = test::init_service(App::new().app_data(hydra).service(logout)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/logout?logout_challenge=challenge")
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            "http://hydra.test/oauth2/sessions/logout?logout_verifier=challenge"
+        );
+
+        assert_eq!(
+            fake_hydra.calls(),
+            vec![
+                HydraCall::GetLogoutRequest("challenge".to_string()),
+                HydraCall::AcceptLogoutRequest("challenge".to_string()),
+            ]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/logout?logout_challenge=unknown")
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+}
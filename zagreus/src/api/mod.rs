@@ -1,7 +1,13 @@
 /// The private endpoints (plus an explicit reference to the public endpoint).
 /// Used only inside the webapp.
+pub mod admin;
 pub mod consent;
 pub mod invitation;
+pub mod invitations;
 pub mod login;
 pub mod logout;
+pub mod password_reset;
+pub mod profile;
 pub mod public;
+pub mod totp;
+pub mod webauthn;
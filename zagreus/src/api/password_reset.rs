@@ -0,0 +1,405 @@
+use actix_web::{http::StatusCode, post, put, web, HttpResponse, Result};
+use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use chrono::{Duration, Utc};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::Validate;
+use zagreus_domain::{
+    models::user::User,
+    repositories::{ClientRepository, PasswordResetRepository, UserRepository},
+};
+
+use crate::codes;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::mailer::Mailer;
+use crate::password_policy::PASSWORD_POLICY;
+use crate::validations::{validate, validation_errors_response};
+
+#[derive(Error, Debug)]
+pub enum PasswordResetError {
+    #[error("password reset couldn't be found")]
+    PasswordResetNotFound,
+    #[error("user request error")]
+    UserError,
+    #[error("user couldn't be found")]
+    UserNotFound,
+    #[error("password reset couldn't be created")]
+    PasswordResetNotCreated,
+    #[error("password reset email couldn't be sent")]
+    PasswordResetNotSent,
+    #[error("password reset has already been used")]
+    PasswordResetAlreadyUsed,
+    #[error("password reset has expired")]
+    PasswordResetExpired,
+    #[error("password encryption failed")]
+    PasswordEncryptionFailed,
+    #[error("password couldn't be updated")]
+    PasswordNotUpdated,
+    #[error("client couldn't be found")]
+    ClientNotFound,
+    #[error("client request error")]
+    ClientError,
+    #[error("redirect uri isn't registered for the client")]
+    RedirectUriNotAllowed,
+}
+
+impl ErrorDetails for PasswordResetError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            PasswordResetError::PasswordResetNotFound => {
+                (StatusCode::NOT_FOUND, "password_reset_not_found")
+            }
+            PasswordResetError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            PasswordResetError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            PasswordResetError::PasswordResetNotCreated => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "password_reset_not_created",
+            ),
+            PasswordResetError::PasswordResetNotSent => {
+                (StatusCode::BAD_GATEWAY, "password_reset_not_sent")
+            }
+            PasswordResetError::PasswordResetAlreadyUsed => {
+                (StatusCode::GONE, "password_reset_already_used")
+            }
+            PasswordResetError::PasswordResetExpired => {
+                (StatusCode::GONE, "password_reset_expired")
+            }
+            PasswordResetError::PasswordEncryptionFailed => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "password_encryption_failed",
+            ),
+            PasswordResetError::PasswordNotUpdated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "password_not_updated")
+            }
+            PasswordResetError::ClientNotFound => (StatusCode::BAD_REQUEST, "client_not_found"),
+            PasswordResetError::ClientError => (StatusCode::INTERNAL_SERVER_ERROR, "client_error"),
+            PasswordResetError::RedirectUriNotAllowed => {
+                (StatusCode::BAD_REQUEST, "redirect_uri_not_allowed")
+            }
+        }
+    }
+}
+
+json_response_error!(PasswordResetError);
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePasswordResetPayload {
+    #[validate(length(min = 1))]
+    client_id: String,
+    #[validate(email)]
+    email: String,
+    #[validate(url)]
+    redirect_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PasswordResetEmail {
+    email: String,
+    password_reset_url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatePasswordResetResponse {
+    redirect_to: String,
+}
+
+/// The reset link redirects to the client once the password is replaced.
+async fn check_client(
+    clients: &dyn ClientRepository,
+    client_id: &str,
+    redirect_uri: &str,
+) -> Result<(), PasswordResetError> {
+    let client = clients
+        .get_by_id(client_id)
+        .await
+        .map_err(|_| PasswordResetError::ClientError)?
+        .ok_or(PasswordResetError::ClientNotFound)?;
+
+    if !client.is_redirect_uri_allowed(redirect_uri) {
+        return Err(PasswordResetError::RedirectUriNotAllowed);
+    }
+
+    Ok(())
+}
+
+/// Creates a password reset request for the user and sends the reset link to their email.
+/// Any previous pending reset for the same user is replaced.
+pub async fn send_password_reset(
+    clients: &dyn ClientRepository,
+    password_resets: &dyn PasswordResetRepository,
+    mailer: &Mailer,
+    user: &User,
+    client_id: &str,
+    redirect_uri: &str,
+) -> Result<(), PasswordResetError> {
+    check_client(clients, client_id, redirect_uri).await?;
+
+    let code = codes::generate();
+
+    let expires_at = Utc::now().naive_utc()
+        + Duration::minutes(zagreus_config::env::PASSWORD_RESET::TTL_MINUTES());
+
+    password_resets
+        .create(
+            &user.id,
+            code.as_str(),
+            client_id,
+            redirect_uri,
+            &expires_at,
+        )
+        .await
+        .map_err(|_| PasswordResetError::PasswordResetNotCreated)?;
+
+    let password_reset_url = format!(
+        "{url}/password-resets/{challenge}",
+        url = mailer.url(),
+        challenge = code
+    );
+
+    mailer
+        .send(
+            user.email.as_str(),
+            "password_reset",
+            PasswordResetEmail {
+                email: user.email.clone(),
+                password_reset_url,
+            },
+        )
+        .await
+        .map_err(|_| PasswordResetError::PasswordResetNotSent)
+}
+
+/// Creates a password reset request from an email and sends the reset link to that email.
+/// The response is the same whether the email belongs to a user or not.
+#[post("/api/password-reset")]
+pub async fn create_password_reset(
+    payload: web::Json<CreatePasswordResetPayload>,
+    users: web::Data<dyn UserRepository>,
+    clients: web::Data<dyn ClientRepository>,
+    password_resets: web::Data<dyn PasswordResetRepository>,
+    mailer: web::Data<Mailer>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = users
+        .get_by_email(payload.email.as_str())
+        .await
+        .map_err(|_| PasswordResetError::UserError)?;
+
+    match user {
+        Some(user) => {
+            send_password_reset(
+                &**clients,
+                &**password_resets,
+                &mailer,
+                &user,
+                payload.client_id.as_str(),
+                payload.redirect_uri.as_str(),
+            )
+            .await?
+        }
+        // The client is still checked, an invalid one mustn't tell whether the email exists
+        None => {
+            check_client(
+                &**clients,
+                payload.client_id.as_str(),
+                payload.redirect_uri.as_str(),
+            )
+            .await?
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(CreatePasswordResetResponse {
+        redirect_to: String::from("/"),
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletePasswordResetPayload {
+    #[validate(length(min = 1))]
+    password_reset_challenge: String,
+    /// Checked against the password policy once the user is found.
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletePasswordResetResponse {
+    redirect_to: String,
+}
+
+/// Completes a password reset, that is, replaces the user's password
+/// and marks the reset code as used, both at once.
+#[put("/api/password-reset")]
+pub async fn complete_password_reset(
+    payload: web::Json<CompletePasswordResetPayload>,
+    users: web::Data<dyn UserRepository>,
+    password_resets: web::Data<dyn PasswordResetRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let password_reset = password_resets
+        .get_by_code(payload.password_reset_challenge.as_str())
+        .await
+        .map_err(|_| PasswordResetError::PasswordResetNotFound)?;
+
+    let password_reset = password_reset.ok_or(PasswordResetError::PasswordResetNotFound)?;
+
+    if password_reset.used_at.is_some() {
+        return Err(PasswordResetError::PasswordResetAlreadyUsed.into());
+    }
+
+    if password_reset.is_expired() {
+        return Err(PasswordResetError::PasswordResetExpired.into());
+    }
+
+    let user = users
+        .get_by_id(&password_reset.user_id)
+        .await
+        .map_err(|_| PasswordResetError::UserError)?
+        .ok_or(PasswordResetError::UserNotFound)?;
+
+    if let Err(validation_errors) =
+        PASSWORD_POLICY.validate(payload.password.as_str(), user.email.as_str())
+    {
+        return Ok(validation_errors_response(&validation_errors));
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+
+    let argon2 = Argon2::default();
+
+    let encrypted_password = argon2
+        .hash_password_simple(payload.password.as_bytes(), salt.as_ref())
+        .map_err(|_| PasswordResetError::PasswordEncryptionFailed)?
+        .to_string();
+
+    // Concurrent completions of the same code only replace the password once
+    password_resets
+        .complete(
+            payload.password_reset_challenge.as_str(),
+            &password_reset.user_id,
+            encrypted_password.as_str(),
+        )
+        .await
+        .map_err(|_| PasswordResetError::PasswordNotUpdated)?
+        .ok_or(PasswordResetError::PasswordResetAlreadyUsed)?;
+
+    Ok(HttpResponse::Ok().json(CompletePasswordResetResponse {
+        redirect_to: password_reset.redirect_uri,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use uuid::Uuid;
+    use zagreus_domain::repositories::{
+        memory::MemoryRepository, PasswordResetRepository, UserRepository,
+    };
+
+    use super::complete_password_reset;
+    use crate::test_utils::create_user;
+
+    /// Creates a user and one of its password resets, returns the user id.
+    async fn request_reset(repository: &MemoryRepository, expires_in: Duration) -> Uuid {
+        let user_id = create_user(repository, "alice@example.com").await;
+
+        PasswordResetRepository::create(
+            repository,
+            &user_id,
+            "code",
+            "client",
+            "https://example.com",
+            &(Utc::now().naive_utc() + expires_in),
+        )
+        .await
+        .unwrap();
+
+        user_id
+    }
+
+    fn complete() -> test::TestRequest {
+        test::TestRequest::put()
+            .uri("/api/password-reset")
+            .set_json(&json!({
+                "passwordResetChallenge": "code",
+                "password": "Correct-Horse-9",
+            }))
+    }
+
+    async fn password(repository: &MemoryRepository, user_id: &Uuid) -> String {
+        UserRepository::get_by_id(repository, user_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .encrypted_password
+    }
+
+    #[actix_rt::test]
+    async fn it_completes_a_password_reset_once() {
+        let repository = MemoryRepository::default();
+
+        let user_id = request_reset(&repository, Duration::hours(1)).await;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let password_resets: Data<dyn PasswordResetRepository> =
+            Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(users)
+                .app_data(password_resets)
+                .service(complete_password_reset),
+        )
+        .await;
+
+        let body: Value = test::read_response_json(&app, complete().to_request()).await;
+
+        assert_eq!(body["redirectTo"], "https://example.com");
+        assert_ne!(password(&repository, &user_id).await, "password");
+
+        let res = test::call_service(&app, complete().to_request()).await;
+
+        assert_eq!(res.status(), StatusCode::GONE);
+
+        let body: Value = test::read_body_json(res).await;
+
+        assert_eq!(body["code"], "password_reset_already_used");
+    }
+
+    #[actix_rt::test]
+    async fn it_rejects_an_expired_password_reset() {
+        let repository = MemoryRepository::default();
+
+        let user_id = request_reset(&repository, Duration::hours(-1)).await;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let password_resets: Data<dyn PasswordResetRepository> =
+            Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(users)
+                .app_data(password_resets)
+                .service(complete_password_reset),
+        )
+        .await;
+
+        let res = test::call_service(&app, complete().to_request()).await;
+
+        assert_eq!(res.status(), StatusCode::GONE);
+
+        let body: Value = test::read_body_json(res).await;
+
+        assert_eq!(body["code"], "password_reset_expired");
+        assert_eq!(password(&repository, &user_id).await, "password");
+    }
+}
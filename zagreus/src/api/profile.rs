@@ -0,0 +1,109 @@
+use actix_web::{get, http::StatusCode, put, web, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use validator::Validate;
+use zagreus_domain::{
+    models::user::{User, UserProfile},
+    repositories::UserRepository,
+};
+
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::session::Session;
+use crate::validations::validate;
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("profile couldn't be updated")]
+    ProfileNotUpdated,
+    #[error("user not found")]
+    UserNotFound,
+}
+
+impl ErrorDetails for ProfileError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            ProfileError::ProfileNotUpdated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "profile_not_updated")
+            }
+            ProfileError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+        }
+    }
+}
+
+json_response_error!(ProfileError);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileResponse {
+    email: String,
+    name: Option<String>,
+    locale: Option<String>,
+    timezone: Option<String>,
+    metadata: Value,
+}
+
+impl From<User> for ProfileResponse {
+    fn from(user: User) -> Self {
+        ProfileResponse {
+            email: user.email,
+            name: user.name,
+            locale: user.locale,
+            timezone: user.timezone,
+            metadata: user.metadata,
+        }
+    }
+}
+
+/// Returns the profile of the authenticated user.
+#[get("/api/profile")]
+pub async fn get_profile(session: Session) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ProfileResponse::from(session.user)))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProfilePayload {
+    #[validate(length(min = 1, max = 256))]
+    name: Option<String>,
+    #[validate(length(min = 2, max = 35))]
+    locale: Option<String>,
+    #[validate(length(min = 1, max = 64))]
+    timezone: Option<String>,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+}
+
+/// Replaces the profile of the authenticated user.
+#[put("/api/profile")]
+pub async fn update_profile(
+    session: Session,
+    payload: web::Json<UpdateProfilePayload>,
+    users: web::Data<dyn UserRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let payload = payload.into_inner();
+
+    users
+        .update_profile(
+            &session.user.id,
+            &UserProfile {
+                name: payload.name,
+                locale: payload.locale,
+                timezone: payload.timezone,
+                metadata: Value::Object(payload.metadata),
+            },
+        )
+        .await
+        .map_err(|_| ProfileError::ProfileNotUpdated)?
+        .ok_or(ProfileError::UserNotFound)?;
+
+    let user = users
+        .get_by_id(&session.user.id)
+        .await
+        .map_err(|_| ProfileError::ProfileNotUpdated)?
+        .ok_or(ProfileError::UserNotFound)?;
+
+    Ok(HttpResponse::Ok().json(ProfileResponse::from(user)))
+}
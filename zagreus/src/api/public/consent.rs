@@ -1,16 +1,19 @@
-use actix_web::{get, http::header, web, HttpResponse, ResponseError, Result};
-use ory_hydra_client::{
-    apis::admin_api::{accept_consent_request, get_consent_request},
-    models::{AcceptConsentRequest, ConsentRequestSession},
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web, HttpResponse, Result,
 };
-use serde::{Deserialize, Serialize};
+use ory_hydra_client::models::ConsentRequest;
+use serde::Deserialize;
 use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 use validator::Validate;
-use zagreus_domain::{db::PgPool, models::user::User};
+use zagreus_domain::repositories::{ClientRepository, RoleRepository, UserRepository};
 
-use crate::hydra_configuration::CONFIGURATION;
+use crate::api::consent;
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
 use crate::validations::validate;
 
 #[derive(Error, Debug)]
@@ -27,34 +30,95 @@ pub enum ConsentError {
     NoSubject,
     #[error("consent request returned a wrong redirect_uri")]
     WrongRedirectUri,
+    #[error("client request error")]
+    ClientError,
+    #[error("consent screen url couldn't be built")]
+    WrongConsentUrl,
 }
 
-impl ResponseError for ConsentError {}
-
-#[derive(Debug, Serialize)]
-struct IdToken {
-    email: String,
+impl ErrorDetails for ConsentError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            ConsentError::WrongSubject => (StatusCode::BAD_GATEWAY, "wrong_consent_subject"),
+            ConsentError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+            ConsentError::ConsentRequestFailed => {
+                (StatusCode::BAD_GATEWAY, "consent_request_failed")
+            }
+            ConsentError::WrongRequestUrl => (StatusCode::BAD_GATEWAY, "wrong_request_url"),
+            ConsentError::NoSubject => (StatusCode::BAD_GATEWAY, "consent_subject_not_found"),
+            ConsentError::WrongRedirectUri => (StatusCode::BAD_GATEWAY, "wrong_redirect_uri"),
+            ConsentError::ClientError => (StatusCode::INTERNAL_SERVER_ERROR, "client_error"),
+            ConsentError::WrongConsentUrl => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "wrong_consent_url")
+            }
+        }
+    }
 }
 
+json_response_error!(ConsentError);
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct FastConsentPayload {
     #[validate(length(min = 1))]
     consent_challenge: String,
 }
 
-/// Takes a consent challenge and assumes the user already consented to the terms and service.
-/// _Must be used only if the complete invitation form contains a consent/accept terms checkbox._
+/// Returns whether the consent screen can be skipped, either because the user already
+/// consented (and asked to be remembered) or because the client is trusted.
+async fn can_skip_consent(
+    clients: &dyn ClientRepository,
+    consent_request: &ConsentRequest,
+) -> Result<bool, ConsentError> {
+    if consent_request.skip == Some(true) {
+        return Ok(true);
+    }
+
+    let client_id = match consent::get_client_id(consent_request) {
+        Some(client_id) => client_id,
+        None => return Ok(false),
+    };
+
+    let client = clients
+        .get_by_id(client_id.as_str())
+        .await
+        .map_err(|_| ConsentError::ClientError)?;
+
+    Ok(matches!(client, Some(client) if client.skip_consent))
+}
+
+/// Hydra consent endpoint, grants the requested scopes when the consent screen can be skipped
+/// and redirects the user to the consent screen (the `consent` view) otherwise.
 #[get("/api/public/consent")]
 pub async fn public_consent(
     payload: web::Query<FastConsentPayload>,
-    pool: web::Data<PgPool>,
+    users: web::Data<dyn UserRepository>,
+    clients: web::Data<dyn ClientRepository>,
+    roles: web::Data<dyn RoleRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
 ) -> Result<HttpResponse> {
     validate!(payload);
 
-    let consent_request = get_consent_request(&CONFIGURATION, payload.consent_challenge.as_str())
+    let consent_request = hydra
+        .get_consent_request(payload.consent_challenge.as_str())
         .await
         .map_err(|_| ConsentError::ConsentRequestFailed)?;
 
+    if !can_skip_consent(&**clients, &consent_request).await? {
+        let mut consent_url = Url::parse(zagreus_config::env::URL())
+            .and_then(|url| url.join("/consent"))
+            .map_err(|_| ConsentError::WrongConsentUrl)?;
+
+        consent_url
+            .query_pairs_mut()
+            .append_pair("consent_challenge", payload.consent_challenge.as_str());
+
+        return Ok(HttpResponse::TemporaryRedirect()
+            .append_header((header::LOCATION, consent_url.to_string()))
+            .finish());
+    }
+
+    let client_id = consent::get_client_id(&consent_request);
+
     let subject = consent_request.subject.ok_or(ConsentError::NoSubject)?;
 
     let user_id = Uuid::parse_str(subject.as_str()).map_err(|_| ConsentError::WrongSubject)?;
@@ -72,35 +136,26 @@ pub async fn public_consent(
 
     let (_, redirect_uri) = redirect_uri_param.ok_or(ConsentError::WrongRedirectUri)?;
 
-    let user = User::get_by_id(&pool, &user_id)
+    let user = users
+        .get_by_id(&user_id)
         .await
         .map_err(|_| ConsentError::UserNotFound)?;
 
     let user = user.ok_or(ConsentError::UserNotFound)?;
 
-    let id_token = IdToken { email: user.email };
-
-    let id_token = serde_json::to_value(&id_token)?;
-
-    let consent_request = accept_consent_request(
-        &CONFIGURATION,
+    let redirect_to = consent::accept(
+        &**roles,
+        &**hydra,
         payload.consent_challenge.as_str(),
-        Some(AcceptConsentRequest {
-            grant_access_token_audience: Some(vec![zagreus_config::env::ACCESS_TOKEN_AUDIENCE()]),
-            grant_scope: Some(zagreus_config::hydra_scopes()),
-            remember: Some(true),
-            remember_for: Some(0),
-            session: Some(Box::new(ConsentRequestSession {
-                id_token: Some(id_token),
-                ..ConsentRequestSession::new()
-            })),
-            ..AcceptConsentRequest::new()
-        }),
+        user,
+        client_id.as_deref(),
+        consent_request.requested_scope.unwrap_or_default(),
+        true,
     )
     .await;
 
-    let redirect_to = match consent_request {
-        Ok(consent_request) => consent_request.redirect_to,
+    let redirect_to = match redirect_to {
+        Ok(redirect_to) => redirect_to,
         Err(_) => redirect_uri.into_owned(),
     };
 
@@ -0,0 +1,441 @@
+use actix_web::{delete, http::StatusCode, post, put, web, HttpRequest, HttpResponse, Result};
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::Utc;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::Validate;
+use zagreus_domain::{
+    models::user::User,
+    repositories::{LoginFailureRepository, TotpRecoveryCodeRepository, UserRepository},
+};
+
+use crate::api::login::{check_lock, fail, peer_ip, use_recovery_code, verify_dummy_password};
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::totp;
+use crate::validations::validate;
+
+#[derive(Error, Debug)]
+pub enum TotpError {
+    #[error("user request error")]
+    UserError,
+    #[error("invalid email or password")]
+    InvalidCredentials,
+    #[error("persisted encrypted password couldn't be hashed")]
+    PersistedPasswordInvalidFormat,
+    #[error("totp is already enabled")]
+    TotpAlreadyEnabled,
+    #[error("totp enrollment hasn't been started")]
+    TotpNotEnrolled,
+    #[error("invalid totp code")]
+    InvalidTotpCode,
+    #[error("totp couldn't be updated")]
+    TotpNotUpdated,
+    #[error("recovery code encryption failed")]
+    RecoveryCodeEncryptionFailed,
+    #[error("recovery codes couldn't be created")]
+    RecoveryCodesNotCreated,
+}
+
+impl ErrorDetails for TotpError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            TotpError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            TotpError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
+            TotpError::PersistedPasswordInvalidFormat => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "persisted_password_invalid_format",
+            ),
+            TotpError::TotpAlreadyEnabled => (StatusCode::CONFLICT, "totp_already_enabled"),
+            TotpError::TotpNotEnrolled => (StatusCode::CONFLICT, "totp_not_enrolled"),
+            TotpError::InvalidTotpCode => (StatusCode::BAD_REQUEST, "invalid_totp_code"),
+            TotpError::TotpNotUpdated => (StatusCode::INTERNAL_SERVER_ERROR, "totp_not_updated"),
+            TotpError::RecoveryCodeEncryptionFailed => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "recovery_code_encryption_failed",
+            ),
+            TotpError::RecoveryCodesNotCreated => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "recovery_codes_not_created",
+            ),
+        }
+    }
+}
+
+json_response_error!(TotpError);
+
+/// Verifies the user's credentials, the totp endpoints always require the password.
+/// The failed attempts count towards the login lockout (see `api::login`).
+async fn authenticate(
+    req: &HttpRequest,
+    users: &dyn UserRepository,
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+    password: &str,
+) -> Result<User> {
+    let ip = peer_ip(req);
+
+    check_lock(login_failures, email, ip.as_deref()).await?;
+
+    let user = users
+        .get_by_email(email)
+        .await
+        .map_err(|_| TotpError::UserError)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => {
+            verify_dummy_password(password);
+
+            return Err(fail(
+                login_failures,
+                email,
+                ip.as_deref(),
+                TotpError::InvalidCredentials,
+            )
+            .await);
+        }
+    };
+
+    let password_hash = PasswordHash::new(user.encrypted_password.as_str())
+        .map_err(|_| TotpError::PersistedPasswordInvalidFormat)?;
+
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &password_hash)
+        .is_err()
+    {
+        return Err(fail(
+            login_failures,
+            email,
+            ip.as_deref(),
+            TotpError::InvalidCredentials,
+        )
+        .await);
+    }
+
+    Ok(user)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrollTotpPayload {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 1))]
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EnrollTotpResponse {
+    secret: String,
+    provisioning_uri: String,
+}
+
+/// Starts the totp enrollment by generating a new secret.
+/// The secret is only active once confirmed with a valid code (see `enable_totp`).
+#[post("/api/totp")]
+pub async fn enroll_totp(
+    req: HttpRequest,
+    payload: web::Json<EnrollTotpPayload>,
+    users: web::Data<dyn UserRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = authenticate(
+        &req,
+        &**users,
+        &**login_failures,
+        payload.email.as_str(),
+        payload.password.as_str(),
+    )
+    .await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Err(TotpError::TotpAlreadyEnabled.into());
+    }
+
+    let secret = totp::generate_secret();
+
+    users
+        .update_totp_secret(&user.id, Some(secret.as_str()))
+        .await
+        .map_err(|_| TotpError::TotpNotUpdated)?;
+
+    let provisioning_uri = totp::provisioning_uri(secret.as_str(), user.email.as_str());
+
+    Ok(HttpResponse::Ok().json(EnrollTotpResponse {
+        secret,
+        provisioning_uri,
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct EnableTotpPayload {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 1))]
+    password: String,
+    #[validate(length(min = 1))]
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EnableTotpResponse {
+    recovery_codes: Vec<String>,
+}
+
+/// Confirms the totp enrollment with a code generated by the authenticator app
+/// and returns the recovery codes, _they're only displayed once_.
+#[put("/api/totp")]
+pub async fn enable_totp(
+    req: HttpRequest,
+    payload: web::Json<EnableTotpPayload>,
+    users: web::Data<dyn UserRepository>,
+    totp_recovery_codes: web::Data<dyn TotpRecoveryCodeRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = authenticate(
+        &req,
+        &**users,
+        &**login_failures,
+        payload.email.as_str(),
+        payload.password.as_str(),
+    )
+    .await?;
+
+    if user.totp_enabled_at.is_some() {
+        return Err(TotpError::TotpAlreadyEnabled.into());
+    }
+
+    let secret = user.totp_secret.ok_or(TotpError::TotpNotEnrolled)?;
+
+    let now = Utc::now();
+
+    if !totp::verify(
+        secret.as_str(),
+        payload.code.as_str(),
+        now.timestamp() as u64,
+    ) {
+        return Err(TotpError::InvalidTotpCode.into());
+    }
+
+    let recovery_codes = totp::generate_recovery_codes();
+
+    let argon2 = Argon2::default();
+
+    let encrypted_recovery_codes = recovery_codes
+        .iter()
+        .map(|recovery_code| {
+            let salt = SaltString::generate(&mut OsRng);
+
+            argon2
+                .hash_password_simple(recovery_code.as_bytes(), salt.as_ref())
+                .map(|hash| hash.to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| TotpError::RecoveryCodeEncryptionFailed)?;
+
+    totp_recovery_codes
+        .replace_all(&user.id, &encrypted_recovery_codes)
+        .await
+        .map_err(|_| TotpError::RecoveryCodesNotCreated)?;
+
+    let user_id = users
+        .update_totp_enabled_at(&user.id, &now.naive_utc())
+        .await
+        .map_err(|_| TotpError::TotpNotUpdated)?;
+
+    user_id.ok_or(TotpError::TotpNotEnrolled)?;
+
+    Ok(HttpResponse::Ok().json(EnableTotpResponse { recovery_codes }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct DisableTotpPayload {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 1))]
+    password: String,
+    /// Either a code generated by the authenticator app or a recovery code,
+    /// for the users who lost their authenticator.
+    #[validate(length(min = 1))]
+    code: String,
+}
+
+/// Disables the totp, a valid code is required, checked the same way as at login.
+#[delete("/api/totp")]
+pub async fn disable_totp(
+    req: HttpRequest,
+    payload: web::Json<DisableTotpPayload>,
+    users: web::Data<dyn UserRepository>,
+    totp_recovery_codes: web::Data<dyn TotpRecoveryCodeRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = authenticate(
+        &req,
+        &**users,
+        &**login_failures,
+        payload.email.as_str(),
+        payload.password.as_str(),
+    )
+    .await?;
+
+    if user.totp_enabled_at.is_none() {
+        return Err(TotpError::TotpNotEnrolled.into());
+    }
+
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or(TotpError::TotpNotEnrolled)?;
+
+    let code = payload.code.trim();
+
+    if !totp::verify(secret, code, Utc::now().timestamp() as u64)
+        && !use_recovery_code(&**totp_recovery_codes, &user, code).await?
+    {
+        let ip = peer_ip(&req);
+
+        return Err(fail(
+            &**login_failures,
+            user.email.as_str(),
+            ip.as_deref(),
+            TotpError::InvalidTotpCode,
+        )
+        .await);
+    }
+
+    users
+        .update_totp_secret(&user.id, None)
+        .await
+        .map_err(|_| TotpError::TotpNotUpdated)?;
+
+    totp_recovery_codes
+        .delete_all(&user.id)
+        .await
+        .map_err(|_| TotpError::TotpNotUpdated)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+    use chrono::Utc;
+    use rand_core::OsRng;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use uuid::Uuid;
+    use zagreus_domain::repositories::{
+        memory::MemoryRepository, LoginFailureRepository, TotpRecoveryCodeRepository,
+        UserRepository,
+    };
+
+    use super::disable_totp;
+    use crate::test_utils::profile;
+    use crate::totp;
+
+    fn hash(value: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Argon2::default()
+            .hash_password_simple(value.as_bytes(), salt.as_ref())
+            .unwrap()
+            .to_string()
+    }
+
+    /// Creates a user with totp enabled and a single recovery code, returns its id.
+    async fn create_totp_user(repository: &MemoryRepository, recovery_code: &str) -> Uuid {
+        let now = Utc::now().naive_utc();
+
+        let user_id = UserRepository::create(
+            repository,
+            "alice@example.com",
+            hash("password").as_str(),
+            &now,
+            &profile(),
+        )
+        .await
+        .unwrap();
+
+        repository
+            .update_totp_secret(&user_id, Some(totp::generate_secret().as_str()))
+            .await
+            .unwrap();
+
+        repository
+            .update_totp_enabled_at(&user_id, &now)
+            .await
+            .unwrap();
+
+        repository
+            .replace_all(&user_id, &[hash(recovery_code)])
+            .await
+            .unwrap();
+
+        user_id
+    }
+
+    #[actix_rt::test]
+    async fn it_disables_the_totp_with_a_recovery_code() {
+        let repository = MemoryRepository::default();
+
+        let user_id = create_totp_user(&repository, "recovery").await;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let totp_recovery_codes: Data<dyn TotpRecoveryCodeRepository> =
+            Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let login_failures: Data<dyn LoginFailureRepository> =
+            Data::from(Arc::new(repository.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(users)
+                .app_data(totp_recovery_codes)
+                .app_data(login_failures)
+                .service(disable_totp),
+        )
+        .await;
+
+        let disable = |code: &str| {
+            test::TestRequest::delete()
+                .uri("/api/totp")
+                .set_json(&json!({
+                    "email": "alice@example.com",
+                    "password": "password",
+                    "code": code,
+                }))
+                .to_request()
+        };
+
+        let res = test::call_service(&app, disable("wrong")).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let body: Value = test::read_body_json(res).await;
+
+        assert_eq!(body["code"], "invalid_totp_code");
+
+        let res = test::call_service(&app, disable(" recovery ")).await;
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let user = UserRepository::get_by_id(&repository, &user_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(user.totp_secret.is_none());
+        assert!(user.totp_enabled_at.is_none());
+    }
+}
@@ -0,0 +1,406 @@
+use actix_web::{http::StatusCode, post, web, HttpRequest, HttpResponse, Result};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::{Duration, Utc};
+use ory_hydra_client::models::AcceptLoginRequest;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+use webauthn_rs::Webauthn;
+use zagreus_domain::{
+    db::PgPool,
+    models::{
+        user::User, webauthn_challenge::WebauthnChallenge, webauthn_credential::WebauthnCredential,
+    },
+};
+
+use crate::api::login::{check_lock, fail, peer_ip, verify_dummy_password};
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+use crate::validations::validate;
+use crate::webauthn;
+
+/// Number of minutes the user has to complete a ceremony once started.
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+#[derive(Error, Debug)]
+pub enum PasskeyError {
+    #[error("user request error")]
+    UserError,
+    #[error("invalid email or password")]
+    InvalidCredentials,
+    #[error("persisted encrypted password couldn't be hashed")]
+    PersistedPasswordInvalidFormat,
+    #[error("user has no passkey")]
+    NoPasskey,
+    #[error("passkey couldn't be read")]
+    InvalidPasskey,
+    #[error("passkey couldn't be created")]
+    PasskeyNotCreated,
+    #[error("passkey couldn't be updated")]
+    PasskeyNotUpdated,
+    #[error("webauthn challenge couldn't be created")]
+    ChallengeNotCreated,
+    #[error("webauthn challenge couldn't be found")]
+    ChallengeNotFound,
+    #[error("webauthn challenge has expired")]
+    ChallengeExpired,
+    #[error("webauthn ceremony failed")]
+    CeremonyFailed,
+    #[error("login request rejected")]
+    LoginRequestRejected,
+}
+
+impl ErrorDetails for PasskeyError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            PasskeyError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            PasskeyError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "invalid_credentials"),
+            PasskeyError::PersistedPasswordInvalidFormat => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "persisted_password_invalid_format",
+            ),
+            PasskeyError::NoPasskey => (StatusCode::NOT_FOUND, "passkey_not_found"),
+            PasskeyError::InvalidPasskey => (StatusCode::INTERNAL_SERVER_ERROR, "invalid_passkey"),
+            PasskeyError::PasskeyNotCreated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "passkey_not_created")
+            }
+            PasskeyError::PasskeyNotUpdated => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "passkey_not_updated")
+            }
+            PasskeyError::ChallengeNotCreated => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "webauthn_challenge_not_created",
+            ),
+            PasskeyError::ChallengeNotFound => {
+                (StatusCode::NOT_FOUND, "webauthn_challenge_not_found")
+            }
+            PasskeyError::ChallengeExpired => (StatusCode::GONE, "webauthn_challenge_expired"),
+            PasskeyError::CeremonyFailed => (StatusCode::UNAUTHORIZED, "webauthn_ceremony_failed"),
+            PasskeyError::LoginRequestRejected => {
+                (StatusCode::BAD_GATEWAY, "login_request_rejected")
+            }
+        }
+    }
+}
+
+json_response_error!(PasskeyError);
+
+async fn get_passkeys(pool: &PgPool, user_id: &Uuid) -> Result<Vec<Passkey>, PasskeyError> {
+    WebauthnCredential::get_by_user_id(pool, user_id)
+        .await
+        .map_err(|_| PasskeyError::UserError)?
+        .iter()
+        .map(|credential| {
+            webauthn::deserialize_passkey(credential.passkey.as_str())
+                .map_err(|_| PasskeyError::InvalidPasskey)
+        })
+        .collect()
+}
+
+async fn create_challenge(
+    pool: &PgPool,
+    user_id: &Uuid,
+    state: &str,
+    login_challenge: Option<&str>,
+) -> Result<Uuid, PasskeyError> {
+    let expires_at = Utc::now().naive_utc() + Duration::minutes(CHALLENGE_TTL_MINUTES);
+
+    WebauthnChallenge::create(pool, user_id, state, login_challenge, &expires_at)
+        .await
+        .map_err(|_| PasskeyError::ChallengeNotCreated)
+}
+
+async fn take_challenge(pool: &PgPool, id: &Uuid) -> Result<WebauthnChallenge, PasskeyError> {
+    let challenge = WebauthnChallenge::take(pool, id)
+        .await
+        .map_err(|_| PasskeyError::ChallengeNotFound)?;
+
+    let challenge = challenge.ok_or(PasskeyError::ChallengeNotFound)?;
+
+    if challenge.is_expired() {
+        return Err(PasskeyError::ChallengeExpired);
+    }
+
+    Ok(challenge)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct StartRegistrationPayload {
+    #[validate(email)]
+    email: String,
+    #[validate(length(min = 1))]
+    password: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartRegistrationResponse {
+    challenge_id: Uuid,
+    options: CreationChallengeResponse,
+}
+
+/// Starts the registration of a passkey, the user must provide their password.
+/// The failed attempts count towards the login lockout (see `api::login`).
+/// The returned options must be passed to `navigator.credentials.create`.
+#[post("/api/webauthn/register/start")]
+pub async fn start_registration(
+    req: HttpRequest,
+    payload: web::Json<StartRegistrationPayload>,
+    pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let ip = peer_ip(&req);
+
+    check_lock(pool.get_ref(), payload.email.as_str(), ip.as_deref()).await?;
+
+    let user = User::get_by_email(&pool, payload.email.as_str())
+        .await
+        .map_err(|_| PasskeyError::UserError)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => {
+            verify_dummy_password(payload.password.as_str());
+
+            return Err(fail(
+                pool.get_ref(),
+                payload.email.as_str(),
+                ip.as_deref(),
+                PasskeyError::InvalidCredentials,
+            )
+            .await);
+        }
+    };
+
+    let password_hash = PasswordHash::new(user.encrypted_password.as_str())
+        .map_err(|_| PasskeyError::PersistedPasswordInvalidFormat)?;
+
+    if Argon2::default()
+        .verify_password(payload.password.as_bytes(), &password_hash)
+        .is_err()
+    {
+        return Err(fail(
+            pool.get_ref(),
+            payload.email.as_str(),
+            ip.as_deref(),
+            PasskeyError::InvalidCredentials,
+        )
+        .await);
+    }
+
+    let passkeys = get_passkeys(&pool, &user.id).await?;
+
+    let (options, state) =
+        webauthn::start_registration(&webauthn, &user.id, user.email.as_str(), &passkeys)
+            .map_err(|_| PasskeyError::CeremonyFailed)?;
+
+    let challenge_id = create_challenge(&pool, &user.id, state.as_str(), None).await?;
+
+    Ok(HttpResponse::Ok().json(StartRegistrationResponse {
+        challenge_id,
+        options,
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct FinishRegistrationPayload {
+    challenge_id: Uuid,
+    /// A name chosen by the user to recognize the passkey later on.
+    #[validate(length(min = 1))]
+    name: String,
+    credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FinishRegistrationResponse {
+    id: Uuid,
+}
+
+/// Completes the registration with the credential created by the authenticator.
+#[post("/api/webauthn/register/finish")]
+pub async fn finish_registration(
+    payload: web::Json<FinishRegistrationPayload>,
+    pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let challenge = take_challenge(&pool, &payload.challenge_id).await?;
+
+    let passkey =
+        webauthn::finish_registration(&webauthn, &payload.credential, challenge.state.as_str())
+            .map_err(|_| PasskeyError::CeremonyFailed)?;
+
+    let serialized_passkey =
+        webauthn::serialize_passkey(&passkey).map_err(|_| PasskeyError::InvalidPasskey)?;
+
+    let id = WebauthnCredential::create(
+        &pool,
+        &challenge.user_id,
+        passkey.cred_id().as_ref(),
+        payload.name.as_str(),
+        serialized_passkey.as_str(),
+    )
+    .await
+    .map_err(|_| PasskeyError::PasskeyNotCreated)?;
+
+    Ok(HttpResponse::Ok().json(FinishRegistrationResponse { id }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct StartLoginPayload {
+    #[validate(length(min = 1))]
+    login_challenge: String,
+    #[validate(email)]
+    email: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartLoginResponse {
+    challenge_id: Uuid,
+    options: RequestChallengeResponse,
+}
+
+/// Starts a passkey login, an alternative to the password form.
+/// The returned options must be passed to `navigator.credentials.get`.
+/// An unknown (or disabled) email, or one without passkeys, gets a decoy challenge which can't
+/// be completed, so that the response doesn't reveal the account.
+#[post("/api/webauthn/login/start")]
+pub async fn start_login(
+    payload: web::Json<StartLoginPayload>,
+    pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    let user = User::get_by_email(&pool, payload.email.as_str())
+        .await
+        .map_err(|_| PasskeyError::UserError)?;
+
+    let passkeys = match &user {
+        Some(user) if !user.is_disabled() => get_passkeys(&pool, &user.id).await?,
+        _ => Vec::new(),
+    };
+
+    let user = match user {
+        Some(user) if !passkeys.is_empty() => user,
+        _ => {
+            let options = webauthn::start_decoy_authentication(
+                &webauthn,
+                payload.email.as_str(),
+                zagreus_config::env::CLIENT::SECRET().as_bytes(),
+            )
+            .map_err(|_| PasskeyError::CeremonyFailed)?;
+
+            return Ok(HttpResponse::Ok().json(StartLoginResponse {
+                challenge_id: Uuid::new_v4(),
+                options,
+            }));
+        }
+    };
+
+    let (options, state) = webauthn::start_authentication(&webauthn, &passkeys)
+        .map_err(|_| PasskeyError::CeremonyFailed)?;
+
+    let challenge_id = create_challenge(
+        &pool,
+        &user.id,
+        state.as_str(),
+        Some(payload.login_challenge.as_str()),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(StartLoginResponse {
+        challenge_id,
+        options,
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct FinishLoginPayload {
+    challenge_id: Uuid,
+    credential: PublicKeyCredential,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FinishLoginResponse {
+    redirect_to: String,
+}
+
+/// Verifies the assertion produced by the authenticator and accepts the Hydra login request.
+#[post("/api/webauthn/login/finish")]
+pub async fn finish_login(
+    payload: web::Json<FinishLoginPayload>,
+    pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    // The decoy challenges (see `start_login`) aren't persisted, they fail like a wrong passkey
+    let challenge =
+        take_challenge(&pool, &payload.challenge_id)
+            .await
+            .map_err(|error| match error {
+                PasskeyError::ChallengeNotFound => PasskeyError::CeremonyFailed,
+                error => error,
+            })?;
+
+    let login_challenge = challenge
+        .login_challenge
+        .ok_or(PasskeyError::ChallengeNotFound)?;
+
+    let result =
+        webauthn::finish_authentication(&webauthn, &payload.credential, challenge.state.as_str())
+            .map_err(|_| PasskeyError::CeremonyFailed)?;
+
+    let mut passkeys = get_passkeys(&pool, &challenge.user_id).await?;
+
+    let passkey = passkeys
+        .iter_mut()
+        .find(|passkey| passkey.cred_id() == result.cred_id())
+        .ok_or(PasskeyError::NoPasskey)?;
+
+    passkey.update_credential(&result);
+
+    let serialized_passkey =
+        webauthn::serialize_passkey(passkey).map_err(|_| PasskeyError::InvalidPasskey)?;
+
+    WebauthnCredential::update_passkey(
+        &pool,
+        passkey.cred_id().as_ref(),
+        serialized_passkey.as_str(),
+        &Utc::now().naive_utc(),
+    )
+    .await
+    .map_err(|_| PasskeyError::PasskeyNotUpdated)?;
+
+    let completed_request = hydra
+        .accept_login_request(
+            login_challenge.as_str(),
+            AcceptLoginRequest {
+                remember: Some(true),
+                ..AcceptLoginRequest::new(challenge.user_id.to_string())
+            },
+        )
+        .await
+        .map_err(|_| PasskeyError::LoginRequestRejected)?;
+
+    Ok(HttpResponse::Ok().json(FinishLoginResponse {
+        redirect_to: completed_request.redirect_to,
+    }))
+}
@@ -0,0 +1,215 @@
+/// Maps the scopes granted on consent to the claims of the tokens.
+/// The standard OpenID Connect claims are emitted per scope, the custom claims
+/// (see `CLAIMS_CUSTOM`) are added on top of them, and the same claims are
+/// available in both the id token and the access token session data.
+/// The roles and permissions of the user are always added, whatever the granted scopes.
+use anyhow::Result;
+use ory_hydra_client::models::ConsentRequestSession;
+use serde_json::{json, Map, Value};
+use zagreus_domain::{models::user::User, repositories::RoleRepository};
+
+pub type Claims = Map<String, Value>;
+
+lazy_static! {
+    pub static ref CUSTOM_CLAIMS: Map<String, Value> = match zagreus_config::env::CLAIMS::CUSTOM() {
+        Some(custom_claims) => serde_json::from_str(custom_claims.as_str())
+            .expect("CLAIMS_CUSTOM must be a json object"),
+        None => Map::new(),
+    };
+}
+
+/// The names of the roles and permissions of a user for the client requesting the tokens.
+#[derive(Debug, Default)]
+pub struct Authorizations {
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+impl Authorizations {
+    pub async fn get(
+        roles: &dyn RoleRepository,
+        user: &User,
+        idp_client_id: Option<&str>,
+    ) -> Result<Self> {
+        let permissions = roles
+            .get_permissions_by_user_id(&user.id, idp_client_id)
+            .await?;
+
+        let roles = roles.get_by_user_id(&user.id, idp_client_id).await?;
+
+        Ok(Authorizations {
+            roles: roles.into_iter().map(|role| role.name).collect(),
+            permissions: permissions
+                .into_iter()
+                .map(|permission| permission.name)
+                .collect(),
+        })
+    }
+}
+
+fn scope_claims(user: &User, scope: &str) -> Claims {
+    let mut claims = Claims::new();
+
+    match scope {
+        "email" => {
+            claims.insert("email".to_string(), json!(user.email));
+            // Users are created from an invitation sent to their email
+            claims.insert("email_verified".to_string(), json!(true));
+        }
+        "profile" => {
+            claims.insert("name".to_string(), json!(user.name));
+            claims.insert("locale".to_string(), json!(user.locale));
+            claims.insert("zoneinfo".to_string(), json!(user.timezone));
+            claims.insert("metadata".to_string(), user.metadata.clone());
+            claims.insert("updated_at".to_string(), json!(user.updated_at.timestamp()));
+        }
+        _ => {}
+    }
+
+    claims
+}
+
+/// Returns the claims of the user for the granted scopes.
+pub fn map(
+    user: &User,
+    authorizations: &Authorizations,
+    granted_scopes: &[String],
+    custom_claims: &Map<String, Value>,
+) -> Claims {
+    let mut claims = Claims::new();
+
+    claims.insert("roles".to_string(), json!(authorizations.roles));
+    claims.insert("permissions".to_string(), json!(authorizations.permissions));
+
+    for scope in granted_scopes {
+        claims.extend(scope_claims(user, scope.as_str()));
+
+        if let Some(Value::Object(scope_custom_claims)) = custom_claims.get(scope) {
+            claims.extend(scope_custom_claims.clone());
+        }
+    }
+
+    claims
+}
+
+/// Builds the consent session of the user, to be passed to `accept_consent_request`.
+pub fn session(
+    user: &User,
+    authorizations: &Authorizations,
+    granted_scopes: &[String],
+) -> ConsentRequestSession {
+    let claims = Value::Object(map(user, authorizations, granted_scopes, &CUSTOM_CLAIMS));
+
+    ConsentRequestSession {
+        access_token: Some(claims.clone()),
+        id_token: Some(claims),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::{json, Map, Value};
+    use uuid::Uuid;
+    use zagreus_domain::models::user::User;
+
+    use super::{map, Authorizations};
+
+    fn user() -> User {
+        let now = NaiveDate::from_ymd(2021, 8, 1).and_hms(0, 0, 0);
+
+        User {
+            id: Uuid::new_v4(),
+            email: "user@example.com".to_string(),
+            encrypted_password: String::new(),
+            terms_accepted_at: Some(now),
+            totp_secret: None,
+            totp_enabled_at: None,
+            name: Some("User".to_string()),
+            locale: Some("en-US".to_string()),
+            timezone: None,
+            metadata: json!({}),
+            disabled_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn it_maps_the_granted_scopes_only() {
+        let authorizations = Authorizations::default();
+
+        let claims = map(
+            &user(),
+            &authorizations,
+            &scopes(&["openid", "email"]),
+            &Map::new(),
+        );
+
+        assert_eq!(
+            Value::Object(claims),
+            json!({
+                "roles": [],
+                "permissions": [],
+                "email": "user@example.com",
+                "email_verified": true
+            })
+        );
+
+        let claims = map(
+            &user(),
+            &authorizations,
+            &scopes(&["openid", "profile"]),
+            &Map::new(),
+        );
+
+        assert_eq!(
+            Value::Object(claims),
+            json!({
+                "roles": [],
+                "permissions": [],
+                "name": "User",
+                "locale": "en-US",
+                "zoneinfo": null,
+                "metadata": {},
+                "updated_at": 1627776000
+            })
+        );
+    }
+
+    #[test]
+    fn it_adds_the_custom_claims() {
+        let custom_claims = json!({ "openid": { "tenant": "acme" }, "admin": { "admin": true } });
+
+        let claims = map(
+            &user(),
+            &Authorizations::default(),
+            &scopes(&["openid"]),
+            custom_claims.as_object().unwrap(),
+        );
+
+        assert_eq!(
+            Value::Object(claims),
+            json!({ "roles": [], "permissions": [], "tenant": "acme" })
+        );
+    }
+
+    #[test]
+    fn it_adds_the_roles_and_permissions_whatever_the_scopes() {
+        let authorizations = Authorizations {
+            roles: vec!["admin".to_string()],
+            permissions: vec!["invitations:write".to_string()],
+        };
+
+        let claims = map(&user(), &authorizations, &[], &Map::new());
+
+        assert_eq!(
+            Value::Object(claims),
+            json!({ "roles": ["admin"], "permissions": ["invitations:write"] })
+        );
+    }
+}
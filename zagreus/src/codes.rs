@@ -0,0 +1,49 @@
+/// The random codes of the invitation and password reset links, of the totp challenges, and the
+/// state of the login. Their length is configured by `CODE_LENGTH`.
+use anyhow::{anyhow, Result};
+use rand::{distributions, Rng};
+use rand_core::OsRng;
+
+/// Shorter codes could be guessed, even with the rate limiting.
+const MIN_LENGTH: usize = 16;
+
+/// Fails if `CODE_LENGTH` is too short.
+pub fn check_length() -> Result<()> {
+    let length = zagreus_config::env::CODE::LENGTH();
+
+    if length < MIN_LENGTH {
+        return Err(anyhow!(
+            "CODE_LENGTH must be at least {}, got {}",
+            MIN_LENGTH,
+            length
+        ));
+    }
+
+    Ok(())
+}
+
+fn generate_with_length(length: usize) -> String {
+    OsRng
+        .sample_iter(distributions::Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+pub fn generate() -> String {
+    generate_with_length(zagreus_config::env::CODE::LENGTH().max(MIN_LENGTH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate_with_length;
+
+    #[test]
+    fn it_generates_alphanumeric_codes() {
+        let code = generate_with_length(32);
+
+        assert_eq!(code.len(), 32);
+        assert!(code.chars().all(|char| char.is_ascii_alphanumeric()));
+        assert_ne!(code, generate_with_length(32));
+    }
+}
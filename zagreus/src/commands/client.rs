@@ -0,0 +1,29 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use url::Url;
+use zagreus_domain::models::client::Client;
+
+/// The redirect uris must be absolute urls.
+pub fn check_redirect_uris(redirect_uris: &[String]) -> Result<()> {
+    for redirect_uri in redirect_uris {
+        Url::parse(redirect_uri)
+            .map_err(|_| anyhow!("Redirect uri {} isn't a valid url", redirect_uri))?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the urls the invitations and password resets of a client can redirect to.
+pub async fn set_redirect_uris(client_id: &str, redirect_uris: &[String]) -> Result<()> {
+    check_redirect_uris(redirect_uris)?;
+
+    let pool = zagreus_domain::db::connect().await?;
+
+    Client::update_redirect_uris(&pool, client_id, redirect_uris)
+        .await?
+        .ok_or_else(|| anyhow!("Client {} couldn't be found", client_id))?;
+
+    info!("Redirect uris of client {} have been updated", client_id);
+
+    Ok(())
+}
@@ -1,18 +1,20 @@
 use anyhow::{anyhow, Result};
-use ory_hydra_client::{apis::admin_api::create_o_auth2_client, models::OAuth2Client};
+use ory_hydra_client::models::OAuth2Client;
 use tokio::try_join;
 use zagreus_domain::models::client::Client;
 
-use crate::hydra_configuration::CONFIGURATION;
+use super::client::check_redirect_uris;
+use crate::hydra::{HydraAdmin, HydraAdminApi};
+
+pub async fn init(client_name: &str, redirect_uris: &[String], skip_consent: bool) -> Result<()> {
+    check_redirect_uris(redirect_uris)?;
 
-pub async fn init(client_name: &str) -> Result<()> {
     let pool = zagreus_domain::db::connect().await?;
 
     try_join!(
         async move {
-            create_o_auth2_client(
-                &CONFIGURATION,
-                OAuth2Client {
+            HydraAdminApi
+                .create_client(OAuth2Client {
                     audience: Some(vec![zagreus_config::env::ACCESS_TOKEN_AUDIENCE()]),
                     grant_types: Some(zagreus_config::hydra_grant_types()),
                     client_id: Some(client_name.to_string()),
@@ -22,12 +24,11 @@ pub async fn init(client_name: &str) -> Result<()> {
                     response_types: Some(zagreus_config::hydra_response_types()),
                     scope: Some(zagreus_config::hydra_scopes().join(" ")),
                     ..OAuth2Client::new()
-                },
-            )
-            .await
-            .map_err(|_| anyhow!("Couldn't create client in Hydra database"))
+                })
+                .await
+                .map_err(|_| anyhow!("Couldn't create client in Hydra database"))
         },
-        Client::create(&pool, client_name)
+        Client::create(&pool, client_name, redirect_uris, skip_consent)
     )?;
 
     Ok(())
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use log::{info, warn};
+use std::{fs, path::Path};
+
+use crate::api::invitations::{self, ImportStatus};
+use crate::mailer::Mailer;
+use crate::views;
+
+/// Invites all the emails of a csv file, see `api::invitations` for the file format.
+pub async fn import_invitations(file: &Path, client_id: &str, redirect_uri: &str) -> Result<()> {
+    let pool = zagreus_domain::db::connect().await?;
+
+    let mailer = Mailer::from_env(&views::TEMPLATES)?;
+
+    let csv = fs::read(file)?;
+
+    let report = invitations::import(&pool, &mailer, &csv, client_id, redirect_uri).await?;
+
+    for row in &report.rows {
+        let email = row.email.as_deref().unwrap_or_default();
+
+        match row.status {
+            ImportStatus::Created if row.errors.is_empty() => {
+                info!("Line {}: {} has been invited", row.line, email)
+            }
+            ImportStatus::Created => warn!(
+                "Line {}: {} has been invited ({})",
+                row.line,
+                email,
+                row.errors.join(", ")
+            ),
+            ImportStatus::Skipped => info!(
+                "Line {}: {} has been skipped ({})",
+                row.line,
+                email,
+                row.errors.join(", ")
+            ),
+            ImportStatus::Invalid => warn!(
+                "Line {}: {} is invalid ({})",
+                row.line,
+                email,
+                row.errors.join(", ")
+            ),
+        }
+    }
+
+    info!(
+        "{} created, {} skipped, {} invalid",
+        report.created, report.skipped, report.invalid
+    );
+
+    Ok(())
+}
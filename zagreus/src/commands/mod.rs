@@ -1,5 +1,13 @@
+pub use client::set_redirect_uris;
 pub use init::init;
+pub use invite::import_invitations;
+pub use role::{assign_role, create_role, unassign_role};
 pub use run::run;
+pub use unlock::unlock;
 
+mod client;
 mod init;
+mod invite;
+mod role;
 mod run;
+mod unlock;
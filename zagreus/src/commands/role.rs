@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use zagreus_domain::models::{role::Role, user::User, user_role::UserRole};
+
+pub async fn create_role(
+    name: &str,
+    description: Option<&str>,
+    permissions: &[String],
+) -> Result<()> {
+    let pool = zagreus_domain::db::connect().await?;
+
+    let role_id = Role::create(&pool, name, description).await?;
+
+    for permission in permissions {
+        Role::add_permission(&pool, &role_id, permission.as_str()).await?;
+    }
+
+    info!("Role {} has been created", name);
+
+    Ok(())
+}
+
+/// Assigns a role to a user, for all the clients unless a client id is provided.
+pub async fn assign_role(email: &str, role: &str, client_id: Option<&str>) -> Result<()> {
+    let pool = zagreus_domain::db::connect().await?;
+
+    let user = User::get_by_email(&pool, email)
+        .await?
+        .ok_or_else(|| anyhow!("User {} couldn't be found", email))?;
+
+    let role = Role::get_by_name(&pool, role)
+        .await?
+        .ok_or_else(|| anyhow!("Role {} couldn't be found", role))?;
+
+    UserRole::create(&pool, &user.id, &role.id, client_id).await?;
+
+    info!("Role {} has been assigned to {}", role.name, email);
+
+    Ok(())
+}
+
+pub async fn unassign_role(email: &str, role: &str, client_id: Option<&str>) -> Result<()> {
+    let pool = zagreus_domain::db::connect().await?;
+
+    let user = User::get_by_email(&pool, email)
+        .await?
+        .ok_or_else(|| anyhow!("User {} couldn't be found", email))?;
+
+    let role = Role::get_by_name(&pool, role)
+        .await?
+        .ok_or_else(|| anyhow!("Role {} couldn't be found", role))?;
+
+    if UserRole::delete(&pool, &user.id, &role.id, client_id).await? {
+        info!("Role {} has been unassigned from {}", role.name, email);
+    } else {
+        info!("Role {} wasn't assigned to {}", role.name, email);
+    }
+
+    Ok(())
+}
@@ -1,16 +1,76 @@
 use actix_cors::Cors;
 use actix_files::Files;
+use actix_web::http::Method;
 use actix_web::middleware::Logger;
-use actix_web::web::Data;
+use actix_web::web::{Data, ServiceConfig};
 use actix_web::{App, HttpServer};
 use anyhow::Result;
+use std::sync::Arc;
+use zagreus_domain::repositories::{
+    ClientRepository, InvitationRepository, LoginFailureRepository, PasswordResetRepository,
+    RoleRepository, TotpChallengeRepository, TotpRecoveryCodeRepository, UserRepository,
+};
 
 use crate::api;
+use crate::claims;
+use crate::codes;
+use crate::hydra::{HydraAdmin, HydraAdminApi};
+use crate::mailer::Mailer;
+use crate::middlewares::{
+    admin::RequireAdmin, csrf::Csrf, error_pages::ErrorPages, rate_limit::RateLimit,
+    security_headers::SecurityHeaders,
+};
+use crate::password_policy;
+use crate::rate_limit::{self, Limit, RateLimitBackend};
 use crate::views;
+use crate::webauthn;
 
 pub async fn run() -> Result<()> {
     let pool = Data::new(zagreus_domain::db::connect().await?);
 
+    // The handlers go through the repositories, the passkeys and the invitations import
+    // excepted which still use the pool
+    let users: Data<dyn UserRepository> = Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let invitations: Data<dyn InvitationRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let clients: Data<dyn ClientRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let roles: Data<dyn RoleRepository> = Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let password_resets: Data<dyn PasswordResetRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let totp_challenges: Data<dyn TotpChallengeRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let totp_recovery_codes: Data<dyn TotpRecoveryCodeRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let login_failures: Data<dyn LoginFailureRepository> =
+        Data::from(Arc::new(pool.get_ref().clone()) as Arc<_>);
+
+    let mailer = Data::new(Mailer::from_env(&views::TEMPLATES)?);
+
+    let webauthn = Data::new(webauthn::from_env()?);
+
+    let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(HydraAdminApi) as Arc<_>);
+
+    // Fails early if the custom claims are not valid json
+    lazy_static::initialize(&claims::CUSTOM_CLAIMS);
+
+    codes::check_length()?;
+
+    // Fails early if the breached passwords can't be read
+    lazy_static::initialize(&password_policy::PASSWORD_POLICY);
+
+    // Fails early if a header value is not valid
+    let security_headers = security_headers()?;
+
+    let rate_limit = rate_limit(rate_limit::from_env(pool.get_ref())?)?;
+
     HttpServer::new(move || {
         let logger = Logger::default();
 
@@ -21,23 +81,26 @@ pub async fn run() -> Result<()> {
             .max_age(3600);
 
         App::new()
+            .wrap(require_admin())
+            .wrap(csrf())
+            .wrap(ErrorPages::new(&views::TEMPLATES))
+            .wrap(rate_limit.clone())
+            .wrap(security_headers.clone())
             .wrap(cors)
             .wrap(logger)
             .app_data(pool.clone())
-            // Public endpoints used by Hydra mostly
-            .service(api::public::consent::public_consent)
-            // Private endpoints used internally by the webapp
-            // .service(api::consent::consent)
-            // .service(api::invitation::get_complete_invitation)
-            .service(api::invitation::create_invitation)
-            .service(api::invitation::complete_invitation)
-            .service(api::login::login)
-            .service(api::logout::logout)
-            // Views for the webapp
-            .service(views::home::home)
-            .service(views::invitation::invitation)
-            .service(views::invitations::invitations)
-            .service(views::login::login)
+            .app_data(users.clone())
+            .app_data(invitations.clone())
+            .app_data(clients.clone())
+            .app_data(roles.clone())
+            .app_data(password_resets.clone())
+            .app_data(totp_challenges.clone())
+            .app_data(totp_recovery_codes.clone())
+            .app_data(login_failures.clone())
+            .app_data(mailer.clone())
+            .app_data(webauthn.clone())
+            .app_data(hydra.clone())
+            .configure(services)
             // Static files
             .service(Files::new("/", zagreus_config::env::STATIC_PATH()))
     })
@@ -47,3 +110,138 @@ pub async fn run() -> Result<()> {
 
     Ok(())
 }
+
+/// The routes restricted to the admins.
+pub fn require_admin() -> RequireAdmin {
+    RequireAdmin::new()
+        .scope("/api/admin")
+        .route(Method::POST, "/api/invitation")
+        .route(Method::POST, "/api/invitation/{id}/resend")
+        .route(Method::DELETE, "/api/invitation/{id}")
+        .route(Method::POST, "/api/invitations/bulk")
+        .route(Method::GET, "/invitations")
+}
+
+/// The csrf protection, with the exemptions of `CSRF_EXEMPT_PATHS`.
+pub fn csrf() -> Csrf {
+    let csrf = Csrf::new(zagreus_config::env::CSRF::SECRET().as_str())
+        .secure(zagreus_config::env::URL().starts_with("https://"));
+
+    zagreus_config::env::CSRF::EXEMPT_PATHS()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .fold(csrf, |csrf, path| csrf.exempt(path))
+}
+
+/// The rate limits of the `RATE_LIMIT_*` variables, on the routes guessing a password or a code
+/// could go through, and the ones sending emails.
+pub fn rate_limit(backend: Arc<dyn RateLimitBackend>) -> Result<RateLimit> {
+    use zagreus_config::env::RATE_LIMIT;
+
+    let login = Limit::per_minute(RATE_LIMIT::LOGIN_PER_MINUTE());
+
+    let invitation = Limit::per_minute(RATE_LIMIT::INVITATION_PER_MINUTE());
+
+    let password_reset = Limit::per_minute(RATE_LIMIT::PASSWORD_RESET_PER_MINUTE());
+
+    let view = Limit::per_minute(RATE_LIMIT::VIEW_PER_MINUTE());
+
+    let rate_limit = RateLimit::new(backend)
+        .route(Method::POST, "/api/login", login)
+        .route(Method::POST, "/api/login/totp", login)
+        .route(Method::POST, "/api/webauthn/login/finish", login)
+        .route(Method::POST, "/api/webauthn/register/start", login)
+        .route(Method::POST, "/api/totp", login)
+        .route(Method::PUT, "/api/totp", login)
+        .route(Method::DELETE, "/api/totp", login)
+        .route(Method::POST, "/api/invitation", invitation)
+        .route(Method::PUT, "/api/invitation", invitation)
+        .route(Method::POST, "/api/password-reset", password_reset)
+        .route(Method::PUT, "/api/password-reset", password_reset)
+        .route(Method::GET, "/invitations/{challenge}", view)
+        .route(Method::GET, "/password-resets/{challenge}", view);
+
+    RATE_LIMIT::TRUSTED_PROXIES()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .try_fold(rate_limit, |rate_limit, proxy| {
+            rate_limit.trust_proxy(proxy)
+        })
+}
+
+/// The security headers of the `SECURITY_HEADERS_*` variables, an empty value omits the header.
+pub fn security_headers() -> Result<SecurityHeaders> {
+    use zagreus_config::env::SECURITY_HEADERS;
+
+    Ok(SecurityHeaders::new()
+        .content_security_policy(SECURITY_HEADERS::CONTENT_SECURITY_POLICY().as_str())
+        .header(
+            "x-frame-options",
+            SECURITY_HEADERS::FRAME_OPTIONS().as_str(),
+        )?
+        .header(
+            "strict-transport-security",
+            SECURITY_HEADERS::STRICT_TRANSPORT_SECURITY().as_str(),
+        )?
+        .header(
+            "referrer-policy",
+            SECURITY_HEADERS::REFERRER_POLICY().as_str(),
+        )?
+        .header(
+            "permissions-policy",
+            SECURITY_HEADERS::PERMISSIONS_POLICY().as_str(),
+        )?)
+}
+
+/// All the api endpoints and views, the static files excepted.
+pub fn services(cfg: &mut ServiceConfig) {
+    cfg
+        // Public endpoints used by Hydra mostly
+        .service(api::public::consent::public_consent)
+        // Admin endpoints
+        .service(api::admin::users::get_users)
+        .service(api::admin::users::get_user_by_id)
+        .service(api::admin::users::update_user)
+        .service(api::admin::users::disable_user)
+        .service(api::admin::users::enable_user)
+        .service(api::admin::users::delete_user)
+        .service(api::admin::users::force_password_reset)
+        // Private endpoints used internally by the webapp
+        .service(api::consent::consent)
+        .service(api::consent::reject_consent)
+        // .service(api::invitation::get_complete_invitation)
+        .service(api::invitation::create_invitation)
+        .service(api::invitation::complete_invitation)
+        .service(api::invitation::resend_invitation)
+        .service(api::invitation::revoke_invitation)
+        .service(api::invitations::import_invitations)
+        .service(api::login::login)
+        .service(api::login::login_totp)
+        .service(api::logout::logout)
+        .service(api::password_reset::create_password_reset)
+        .service(api::password_reset::complete_password_reset)
+        .service(api::profile::get_profile)
+        .service(api::profile::update_profile)
+        .service(api::totp::enroll_totp)
+        .service(api::totp::enable_totp)
+        .service(api::totp::disable_totp)
+        .service(api::webauthn::start_registration)
+        .service(api::webauthn::finish_registration)
+        .service(api::webauthn::start_login)
+        .service(api::webauthn::finish_login)
+        // Views for the webapp
+        .service(views::consent::consent)
+        .service(views::home::home)
+        .service(views::invitation::invitation)
+        .service(views::invitations::invitations)
+        .service(views::login::login)
+        .service(views::login::login_totp)
+        .service(views::password_reset::password_reset);
+}
+
+#[cfg(test)]
+mod tests;
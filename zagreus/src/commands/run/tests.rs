@@ -0,0 +1,420 @@
+/// Drives the whole OAuth2 flow through the app (invite → complete → login → consent → logout),
+/// against the database of `DATABASE_URL` and the fake Hydra admin api.
+use actix_web::{
+    cookie::Cookie,
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    test,
+    web::Data,
+    App,
+};
+use ory_hydra_client::models::{AcceptConsentRequest, AcceptLoginRequest};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tera::Tera;
+use uuid::Uuid;
+use zagreus_domain::{
+    codes,
+    db::PgPool,
+    models::{client::Client, invitation::Invitation, user::User},
+    repositories::{
+        ClientRepository, InvitationRepository, LoginFailureRepository, PasswordResetRepository,
+        RoleRepository, TotpChallengeRepository, TotpRecoveryCodeRepository, UserRepository,
+    },
+};
+
+use super::{csrf, rate_limit, require_admin, security_headers, services};
+use crate::hydra::{
+    fake::{FakeHydraAdmin, HydraCall},
+    HydraAdmin,
+};
+use crate::mailer::{memory::MemoryTransport, Mailer};
+use crate::middlewares::{
+    csrf::{CSRF_COOKIE, CSRF_HEADER},
+    error_pages::ErrorPages,
+};
+use crate::rate_limit::memory::MemoryBackend;
+
+const ADMIN_TOKEN: &str = "admin-token";
+
+const REDIRECT_URI: &str = "https://app.example.com/welcome";
+
+const PASSWORD: &str = "Correct-Horse-9";
+
+lazy_static! {
+    static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+
+        tera.add_raw_templates(vec![
+            ("emails/invitation_subject.html", "You are invited"),
+            ("emails/invitation.html", "{{ invitation_url | safe }}"),
+        ])
+        .expect("email templates couldn't be parsed");
+
+        tera
+    };
+}
+
+struct Context {
+    pool: PgPool,
+    hydra: FakeHydraAdmin,
+    transport: MemoryTransport,
+    client_id: String,
+    email: String,
+}
+
+impl Context {
+    async fn new() -> Self {
+        // The configuration of the app (`URL`...) is read from the `.env` of the tests
+        dotenv::dotenv().ok();
+
+        let pool = zagreus_domain::db::connect().await.unwrap();
+
+        let client_id = format!("client-{}", Uuid::new_v4());
+
+        Client::create(
+            &pool,
+            client_id.as_str(),
+            &[REDIRECT_URI.to_string()],
+            false,
+        )
+        .await
+        .unwrap();
+
+        let hydra = FakeHydraAdmin::default();
+
+        hydra.add_token(ADMIN_TOKEN, "backend", "backend", &["zagreus:admin"]);
+
+        Context {
+            pool,
+            hydra,
+            transport: MemoryTransport::default(),
+            client_id,
+            email: format!("{}@example.com", Uuid::new_v4()),
+        }
+    }
+
+    async fn cleanup(&self) {
+        if let Some(user) = User::get_by_email(&self.pool, self.email.as_str())
+            .await
+            .unwrap()
+        {
+            User::delete(&self.pool, &user.id).await.unwrap();
+        }
+
+        Client::delete(&self.pool, self.client_id.as_str())
+            .await
+            .unwrap();
+    }
+}
+
+/// The app as configured by `run`, with the fake Hydra and the memory mailer
+/// (a macro since the type of the test service can't be named).
+macro_rules! init_app {
+    ($context:expr) => {{
+        let context: &Context = &$context;
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let invitations: Data<dyn InvitationRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let clients: Data<dyn ClientRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let roles: Data<dyn RoleRepository> = Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let password_resets: Data<dyn PasswordResetRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let totp_challenges: Data<dyn TotpChallengeRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let totp_recovery_codes: Data<dyn TotpRecoveryCodeRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let login_failures: Data<dyn LoginFailureRepository> =
+            Data::from(Arc::new(context.pool.clone()) as Arc<_>);
+
+        let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(context.hydra.clone()) as Arc<_>);
+
+        let mailer = Mailer::new(
+            "Zagreus <no-reply@zagreus.test>".to_string(),
+            zagreus_config::env::URL().to_string(),
+            &TEMPLATES,
+            Box::new(context.transport.clone()),
+        );
+
+        test::init_service(
+            App::new()
+                .wrap(require_admin())
+                .wrap(csrf())
+                .wrap(ErrorPages::new(&TEMPLATES))
+                .wrap(rate_limit(Arc::new(MemoryBackend::default())).unwrap())
+                .wrap(security_headers().unwrap())
+                .app_data(Data::new(context.pool.clone()))
+                .app_data(users)
+                .app_data(invitations)
+                .app_data(clients)
+                .app_data(roles)
+                .app_data(password_resets)
+                .app_data(totp_challenges)
+                .app_data(totp_recovery_codes)
+                .app_data(login_failures)
+                .app_data(Data::new(mailer))
+                .app_data(hydra)
+                .configure(services),
+        )
+        .await
+    }};
+}
+
+/// Sends the csrf token the way the rendered pages do.
+fn with_csrf(req: test::TestRequest, csrf_cookie: &Cookie<'static>) -> test::TestRequest {
+    req.cookie(csrf_cookie.clone())
+        .insert_header((CSRF_HEADER, csrf_cookie.value()))
+}
+
+fn location(res: &ServiceResponse) -> &str {
+    res.headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .unwrap_or_default()
+}
+
+#[actix_rt::test]
+async fn it_goes_through_the_whole_oauth2_flow() {
+    let context = Context::new().await;
+
+    let app = init_app!(context);
+
+    // An admin invites the user
+    let req = test::TestRequest::post()
+        .uri("/api/invitation")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", ADMIN_TOKEN)))
+        .set_json(&json!({
+            "clientId": context.client_id,
+            "email": context.email,
+            "redirectUri": REDIRECT_URI,
+        }))
+        .to_request();
+
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+
+    // Every visitor gets a csrf token
+    let csrf_cookie = res
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == CSRF_COOKIE)
+        .expect("csrf cookie is missing")
+        .into_owned();
+
+    let messages = context.transport.messages();
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].to, context.email);
+
+    let invitation_url = messages[0].body.clone();
+
+    let code = invitation_url
+        .strip_prefix("http://zagreus.test/invitations/")
+        .expect("wrong invitation url");
+
+    // The user completes the invitation
+    let req = with_csrf(
+        test::TestRequest::put()
+            .uri("/api/invitation")
+            .set_json(&json!({
+                "invitationChallenge": code,
+                "password": PASSWORD,
+                "name": "Alice",
+                "termsAccepted": true,
+            })),
+        &csrf_cookie,
+    )
+    .to_request();
+
+    let response: Value = test::read_response_json(&app, req).await;
+
+    let user = User::get_by_email(&context.pool, context.email.as_str())
+        .await
+        .unwrap()
+        .expect("user wasn't created");
+
+    assert_eq!(user.name.as_deref(), Some("Alice"));
+
+    assert!(response["redirectTo"]
+        .as_str()
+        .unwrap()
+        .starts_with(format!("{}?user_id={}", REDIRECT_URI, user.id).as_str()));
+
+    let invitation = Invitation::get_by_code(&context.pool, code)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(invitation.used_at.is_some());
+    // Only the hash of the code is persisted
+    assert_eq!(invitation.code_hash, codes::hash(code));
+
+    // The user logs in
+    let request_url = format!(
+        "http://hydra.test/oauth2/auth?client_id={}&redirect_uri={}",
+        context.client_id, REDIRECT_URI
+    );
+
+    context
+        .hydra
+        .add_login_request("login-challenge", false, "", request_url.as_str());
+
+    let req = with_csrf(
+        test::TestRequest::post()
+            .uri("/api/login")
+            .peer_addr("127.0.0.1:4000".parse().unwrap())
+            .set_json(&json!({
+                "loginChallenge": "login-challenge",
+                "email": context.email,
+                "password": PASSWORD,
+            })),
+        &csrf_cookie,
+    )
+    .to_request();
+
+    let response: Value = test::read_response_json(&app, req).await;
+
+    assert_eq!(
+        response["redirectTo"],
+        "http://hydra.test/oauth2/auth?login_verifier=login-challenge"
+    );
+
+    // The client requires the consent of the user
+    context.hydra.add_consent_request(
+        "consent-challenge",
+        false,
+        user.id.to_string().as_str(),
+        request_url.as_str(),
+        context.client_id.as_str(),
+        &["openid", "email"],
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/public/consent?consent_challenge=consent-challenge")
+        .to_request();
+
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        location(&res),
+        "http://zagreus.test/consent?consent_challenge=consent-challenge"
+    );
+
+    let req = with_csrf(
+        test::TestRequest::post()
+            .uri("/api/consent")
+            .set_json(&json!({
+                "consentChallenge": "consent-challenge",
+                "grantScope": ["openid"],
+            })),
+        &csrf_cookie,
+    )
+    .to_request();
+
+    let response: Value = test::read_response_json(&app, req).await;
+
+    assert_eq!(
+        response["redirectTo"],
+        "http://hydra.test/oauth2/auth?consent_verifier=consent-challenge"
+    );
+
+    // The user logs out
+    context
+        .hydra
+        .add_logout_request("logout-challenge", user.id.to_string().as_str());
+
+    let req = test::TestRequest::get()
+        .uri("/api/logout?logout_challenge=logout-challenge")
+        .to_request();
+
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        location(&res),
+        "http://hydra.test/oauth2/sessions/logout?logout_verifier=logout-challenge"
+    );
+
+    let calls = context.hydra.calls();
+
+    assert_eq!(
+        calls[0],
+        HydraCall::IntrospectToken(ADMIN_TOKEN.to_string())
+    );
+
+    assert!(calls.contains(&HydraCall::AcceptLoginRequest(
+        "login-challenge".to_string(),
+        AcceptLoginRequest {
+            remember: Some(true),
+            ..AcceptLoginRequest::new(user.id.to_string())
+        }
+    )));
+
+    assert!(calls.iter().any(|call| matches!(
+        call,
+        HydraCall::AcceptConsentRequest(challenge, AcceptConsentRequest { grant_scope: Some(grant_scope), .. })
+            if challenge == "consent-challenge" && grant_scope == &vec!["openid".to_string()]
+    )));
+
+    assert_eq!(
+        calls.last(),
+        Some(&HydraCall::AcceptLogoutRequest(
+            "logout-challenge".to_string()
+        ))
+    );
+
+    context.cleanup().await;
+}
+
+#[actix_rt::test]
+async fn it_only_lets_the_admins_invite() {
+    let context = Context::new().await;
+
+    let app = init_app!(context);
+
+    context.hydra.add_token(
+        "user-token",
+        Uuid::new_v4().to_string().as_str(),
+        "client",
+        &[],
+    );
+
+    for authorization in &["", "Bearer unknown-token", "Bearer user-token"] {
+        let req = test::TestRequest::post()
+            .uri("/api/invitation")
+            .insert_header((header::AUTHORIZATION, *authorization))
+            .set_json(&json!({
+                "clientId": context.client_id,
+                "email": context.email,
+                "redirectUri": REDIRECT_URI,
+            }))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert!(res.status().is_client_error());
+    }
+
+    assert!(context.transport.messages().is_empty());
+
+    assert!(
+        Invitation::get_pending_by_email(&context.pool, context.email.as_str())
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    context.cleanup().await;
+}
@@ -0,0 +1,30 @@
+use anyhow::{anyhow, Result};
+use log::info;
+
+use crate::lockout;
+
+pub async fn unlock(email: Option<&str>, ip: Option<&str>) -> Result<()> {
+    if email.is_none() && ip.is_none() {
+        return Err(anyhow!("Either an email or an ip address must be provided"));
+    }
+
+    let pool = zagreus_domain::db::connect().await?;
+
+    if let Some(email) = email {
+        if lockout::unlock_account(&pool, email).await? {
+            info!("Account {} has been unlocked", email);
+        } else {
+            info!("Account {} wasn't locked", email);
+        }
+    }
+
+    if let Some(ip) = ip {
+        if lockout::unlock_ip(&pool, ip).await? {
+            info!("Ip address {} has been unlocked", ip);
+        } else {
+            info!("Ip address {} wasn't locked", ip);
+        }
+    }
+
+    Ok(())
+}
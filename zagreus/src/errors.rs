@@ -0,0 +1,123 @@
+/// Shared error handling for the endpoints and the views.
+/// Every error enum describes its variants (http status and stable machine-readable code)
+/// through the `ErrorDetails` trait, and gets its `ResponseError` implementation from either:
+/// - `json_response_error!` for the api endpoints, the body is a json object like
+///   `{ "status": 401, "code": "invalid_credentials", "message": "invalid email or password" }`,
+///   the `400` returned by `validate!` has the same shape (`invalid_payload`) and lists the
+///   errors of each field: `"errors": { "email": [{ "code": "email", "params": {...} }] }`
+/// - `html_response_error!` for the views, the error is rendered using the `error.html`
+///   template (receives `status`, `code`, and `message`) when it exists
+use actix_web::{http::StatusCode, HttpResponse};
+use serde::Serialize;
+use std::fmt::Display;
+use validator::ValidationErrors;
+
+pub trait ErrorDetails: Display {
+    /// The http status and the (stable) machine-readable code of the error.
+    fn details(&self) -> (StatusCode, &'static str);
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub status: u16,
+    pub code: &'static str,
+    pub message: String,
+    /// The validation errors of each field, see `validations::validation_errors_response`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<ValidationErrors>,
+}
+
+impl ErrorResponse {
+    pub fn new<E: ErrorDetails>(error: &E) -> Self {
+        let (status, code) = error.details();
+
+        ErrorResponse {
+            status: status.as_u16(),
+            code,
+            message: error.to_string(),
+            errors: None,
+        }
+    }
+}
+
+pub fn json_error_response<E: ErrorDetails>(error: &E) -> HttpResponse {
+    let (status, _) = error.details();
+
+    HttpResponse::build(status).json(ErrorResponse::new(error))
+}
+
+macro_rules! json_response_error {
+    ($error:ty) => {
+        impl ::actix_web::ResponseError for $error {
+            fn status_code(&self) -> ::actix_web::http::StatusCode {
+                crate::errors::ErrorDetails::details(self).0
+            }
+
+            fn error_response(&self) -> ::actix_web::HttpResponse {
+                crate::errors::json_error_response(self)
+            }
+        }
+    };
+}
+
+macro_rules! html_response_error {
+    ($error:ty) => {
+        impl ::actix_web::ResponseError for $error {
+            fn status_code(&self) -> ::actix_web::http::StatusCode {
+                crate::errors::ErrorDetails::details(self).0
+            }
+
+            fn error_response(&self) -> ::actix_web::HttpResponse {
+                crate::views::html_error_response(self)
+            }
+        }
+    };
+}
+
+pub(crate) use html_response_error;
+pub(crate) use json_response_error;
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::AnyBody, http::StatusCode, ResponseError};
+    use thiserror::Error;
+
+    use super::ErrorDetails;
+
+    #[derive(Debug, Error)]
+    enum TestError {
+        #[error("thing couldn't be found")]
+        NotFound,
+    }
+
+    impl ErrorDetails for TestError {
+        fn details(&self) -> (StatusCode, &'static str) {
+            match self {
+                TestError::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+            }
+        }
+    }
+
+    json_response_error!(TestError);
+
+    #[test]
+    fn it_renders_json_errors() {
+        let response = TestError::NotFound.error_response();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = match response.body() {
+            AnyBody::Bytes(bytes) => serde_json::from_slice::<serde_json::Value>(bytes).unwrap(),
+            _ => panic!("unexpected body"),
+        };
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "status": 404,
+                "code": "not_found",
+                "message": "thing couldn't be found"
+            })
+        );
+    }
+}
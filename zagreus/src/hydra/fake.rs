@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ory_hydra_client::models::{
+    AcceptConsentRequest, AcceptLoginRequest, CompletedRequest, ConsentRequest, LoginRequest,
+    LogoutRequest, OAuth2Client, OAuth2TokenIntrospection, RejectRequest,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::HydraAdmin;
+
+/// The public url of the fake Hydra, the completed requests redirect to it.
+pub const FAKE_HYDRA_URL: &str = "http://hydra.test";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HydraCall {
+    GetLoginRequest(String),
+    AcceptLoginRequest(String, AcceptLoginRequest),
+    RejectLoginRequest(String, RejectRequest),
+    GetConsentRequest(String),
+    AcceptConsentRequest(String, AcceptConsentRequest),
+    RejectConsentRequest(String, RejectRequest),
+    GetLogoutRequest(String),
+    AcceptLogoutRequest(String),
+    RejectLogoutRequest(String, RejectRequest),
+    CreateClient(Box<OAuth2Client>),
+    IntrospectToken(String),
+    RevokeSessions(String),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    login_requests: HashMap<String, LoginRequest>,
+    consent_requests: HashMap<String, ConsentRequest>,
+    logout_requests: HashMap<String, LogoutRequest>,
+    introspections: HashMap<String, OAuth2TokenIntrospection>,
+    calls: Vec<HydraCall>,
+}
+
+/// Answers the requests scripted beforehand and records every call (in tests typically).
+/// Unknown challenges fail like they do with Hydra, unknown tokens are inactive.
+#[derive(Debug, Clone, Default)]
+pub struct FakeHydraAdmin {
+    state: Arc<Mutex<State>>,
+}
+
+fn completed_request(path: &str, verifier: &str, challenge: &str) -> CompletedRequest {
+    CompletedRequest::new(format!(
+        "{}{}?{}={}",
+        FAKE_HYDRA_URL, path, verifier, challenge
+    ))
+}
+
+impl FakeHydraAdmin {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("fake hydra lock poisoned")
+    }
+
+    fn record(&self, call: HydraCall) {
+        self.state().calls.push(call);
+    }
+
+    pub fn calls(&self) -> Vec<HydraCall> {
+        self.state().calls.clone()
+    }
+
+    pub fn add_login_request(&self, challenge: &str, skip: bool, subject: &str, request_url: &str) {
+        self.state().login_requests.insert(
+            challenge.to_string(),
+            LoginRequest::new(
+                challenge.to_string(),
+                OAuth2Client::new(),
+                request_url.to_string(),
+                vec![],
+                vec![],
+                skip,
+                subject.to_string(),
+            ),
+        );
+    }
+
+    pub fn add_consent_request(
+        &self,
+        challenge: &str,
+        skip: bool,
+        subject: &str,
+        request_url: &str,
+        client_id: &str,
+        requested_scope: &[&str],
+    ) {
+        self.state().consent_requests.insert(
+            challenge.to_string(),
+            ConsentRequest {
+                client: Some(Box::new(OAuth2Client {
+                    client_id: Some(client_id.to_string()),
+                    ..OAuth2Client::new()
+                })),
+                request_url: Some(request_url.to_string()),
+                requested_scope: Some(
+                    requested_scope
+                        .iter()
+                        .map(|scope| scope.to_string())
+                        .collect(),
+                ),
+                skip: Some(skip),
+                subject: Some(subject.to_string()),
+                ..ConsentRequest::new(challenge.to_string())
+            },
+        );
+    }
+
+    pub fn add_logout_request(&self, challenge: &str, subject: &str) {
+        self.state().logout_requests.insert(
+            challenge.to_string(),
+            LogoutRequest {
+                challenge: Some(challenge.to_string()),
+                subject: Some(subject.to_string()),
+                ..LogoutRequest::new()
+            },
+        );
+    }
+
+    /// An active token, issued to a user when the client is different from the subject,
+    /// with client credentials otherwise.
+    pub fn add_token(&self, token: &str, subject: &str, client_id: &str, scope: &[&str]) {
+        self.state().introspections.insert(
+            token.to_string(),
+            OAuth2TokenIntrospection {
+                sub: Some(subject.to_string()),
+                client_id: Some(client_id.to_string()),
+                scope: Some(scope.join(" ")),
+                ..OAuth2TokenIntrospection::new(true)
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl HydraAdmin for FakeHydraAdmin {
+    async fn get_login_request(&self, login_challenge: &str) -> Result<LoginRequest> {
+        self.record(HydraCall::GetLoginRequest(login_challenge.to_string()));
+
+        self.state()
+            .login_requests
+            .get(login_challenge)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown login challenge {}", login_challenge))
+    }
+
+    async fn accept_login_request(
+        &self,
+        login_challenge: &str,
+        body: AcceptLoginRequest,
+    ) -> Result<CompletedRequest> {
+        self.record(HydraCall::AcceptLoginRequest(
+            login_challenge.to_string(),
+            body,
+        ));
+
+        if !self.state().login_requests.contains_key(login_challenge) {
+            return Err(anyhow!("Unknown login challenge {}", login_challenge));
+        }
+
+        Ok(completed_request(
+            "/oauth2/auth",
+            "login_verifier",
+            login_challenge,
+        ))
+    }
+
+    async fn reject_login_request(
+        &self,
+        login_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest> {
+        self.record(HydraCall::RejectLoginRequest(
+            login_challenge.to_string(),
+            body,
+        ));
+
+        if !self.state().login_requests.contains_key(login_challenge) {
+            return Err(anyhow!("Unknown login challenge {}", login_challenge));
+        }
+
+        Ok(completed_request(
+            "/oauth2/auth",
+            "login_verifier",
+            login_challenge,
+        ))
+    }
+
+    async fn get_consent_request(&self, consent_challenge: &str) -> Result<ConsentRequest> {
+        self.record(HydraCall::GetConsentRequest(consent_challenge.to_string()));
+
+        self.state()
+            .consent_requests
+            .get(consent_challenge)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown consent challenge {}", consent_challenge))
+    }
+
+    async fn accept_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: AcceptConsentRequest,
+    ) -> Result<CompletedRequest> {
+        self.record(HydraCall::AcceptConsentRequest(
+            consent_challenge.to_string(),
+            body,
+        ));
+
+        if !self
+            .state()
+            .consent_requests
+            .contains_key(consent_challenge)
+        {
+            return Err(anyhow!("Unknown consent challenge {}", consent_challenge));
+        }
+
+        Ok(completed_request(
+            "/oauth2/auth",
+            "consent_verifier",
+            consent_challenge,
+        ))
+    }
+
+    async fn reject_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest> {
+        self.record(HydraCall::RejectConsentRequest(
+            consent_challenge.to_string(),
+            body,
+        ));
+
+        if !self
+            .state()
+            .consent_requests
+            .contains_key(consent_challenge)
+        {
+            return Err(anyhow!("Unknown consent challenge {}", consent_challenge));
+        }
+
+        Ok(completed_request(
+            "/oauth2/auth",
+            "consent_verifier",
+            consent_challenge,
+        ))
+    }
+
+    async fn get_logout_request(&self, logout_challenge: &str) -> Result<LogoutRequest> {
+        self.record(HydraCall::GetLogoutRequest(logout_challenge.to_string()));
+
+        self.state()
+            .logout_requests
+            .get(logout_challenge)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown logout challenge {}", logout_challenge))
+    }
+
+    async fn accept_logout_request(&self, logout_challenge: &str) -> Result<CompletedRequest> {
+        self.record(HydraCall::AcceptLogoutRequest(logout_challenge.to_string()));
+
+        if !self.state().logout_requests.contains_key(logout_challenge) {
+            return Err(anyhow!("Unknown logout challenge {}", logout_challenge));
+        }
+
+        Ok(completed_request(
+            "/oauth2/sessions/logout",
+            "logout_verifier",
+            logout_challenge,
+        ))
+    }
+
+    async fn reject_logout_request(
+        &self,
+        logout_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<()> {
+        self.record(HydraCall::RejectLogoutRequest(
+            logout_challenge.to_string(),
+            body,
+        ));
+
+        if !self.state().logout_requests.contains_key(logout_challenge) {
+            return Err(anyhow!("Unknown logout challenge {}", logout_challenge));
+        }
+
+        Ok(())
+    }
+
+    async fn create_client(&self, client: OAuth2Client) -> Result<OAuth2Client> {
+        self.record(HydraCall::CreateClient(Box::new(client.clone())));
+
+        Ok(client)
+    }
+
+    async fn introspect_token(&self, token: &str) -> Result<OAuth2TokenIntrospection> {
+        self.record(HydraCall::IntrospectToken(token.to_string()));
+
+        Ok(self
+            .state()
+            .introspections
+            .get(token)
+            .cloned()
+            .unwrap_or_else(|| OAuth2TokenIntrospection::new(false)))
+    }
+
+    async fn revoke_sessions(&self, subject: &str) -> Result<()> {
+        self.record(HydraCall::RevokeSessions(subject.to_string()));
+
+        Ok(())
+    }
+}
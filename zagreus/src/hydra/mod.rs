@@ -0,0 +1,169 @@
+/// The calls to the Hydra admin api, behind the `HydraAdmin` trait so that the handlers can be
+/// tested without a running Hydra (see `fake::FakeHydraAdmin`).
+/// `HydraAdminApi` is the actual implementation, using `hydra_configuration::CONFIGURATION`.
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ory_hydra_client::{
+    apis::admin_api,
+    models::{
+        AcceptConsentRequest, AcceptLoginRequest, CompletedRequest, ConsentRequest, LoginRequest,
+        LogoutRequest, OAuth2Client, OAuth2TokenIntrospection, RejectRequest,
+    },
+};
+
+use crate::hydra_configuration::CONFIGURATION;
+
+#[cfg(test)]
+pub mod fake;
+
+#[async_trait]
+pub trait HydraAdmin: Send + Sync {
+    async fn get_login_request(&self, login_challenge: &str) -> Result<LoginRequest>;
+
+    async fn accept_login_request(
+        &self,
+        login_challenge: &str,
+        body: AcceptLoginRequest,
+    ) -> Result<CompletedRequest>;
+
+    #[allow(dead_code)]
+    async fn reject_login_request(
+        &self,
+        login_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest>;
+
+    async fn get_consent_request(&self, consent_challenge: &str) -> Result<ConsentRequest>;
+
+    async fn accept_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: AcceptConsentRequest,
+    ) -> Result<CompletedRequest>;
+
+    async fn reject_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest>;
+
+    async fn get_logout_request(&self, logout_challenge: &str) -> Result<LogoutRequest>;
+
+    async fn accept_logout_request(&self, logout_challenge: &str) -> Result<CompletedRequest>;
+
+    #[allow(dead_code)]
+    async fn reject_logout_request(
+        &self,
+        logout_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<()>;
+
+    async fn create_client(&self, client: OAuth2Client) -> Result<OAuth2Client>;
+
+    async fn introspect_token(&self, token: &str) -> Result<OAuth2TokenIntrospection>;
+
+    /// Logs the subject out of all the clients, and revokes all their consents.
+    async fn revoke_sessions(&self, subject: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HydraAdminApi;
+
+#[async_trait]
+impl HydraAdmin for HydraAdminApi {
+    async fn get_login_request(&self, login_challenge: &str) -> Result<LoginRequest> {
+        admin_api::get_login_request(&CONFIGURATION, login_challenge)
+            .await
+            .map_err(|error| anyhow!("Couldn't get login request: {}", error))
+    }
+
+    async fn accept_login_request(
+        &self,
+        login_challenge: &str,
+        body: AcceptLoginRequest,
+    ) -> Result<CompletedRequest> {
+        admin_api::accept_login_request(&CONFIGURATION, login_challenge, Some(body))
+            .await
+            .map_err(|error| anyhow!("Couldn't accept login request: {}", error))
+    }
+
+    async fn reject_login_request(
+        &self,
+        login_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest> {
+        admin_api::reject_login_request(&CONFIGURATION, login_challenge, Some(body))
+            .await
+            .map_err(|error| anyhow!("Couldn't reject login request: {}", error))
+    }
+
+    async fn get_consent_request(&self, consent_challenge: &str) -> Result<ConsentRequest> {
+        admin_api::get_consent_request(&CONFIGURATION, consent_challenge)
+            .await
+            .map_err(|error| anyhow!("Couldn't get consent request: {}", error))
+    }
+
+    async fn accept_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: AcceptConsentRequest,
+    ) -> Result<CompletedRequest> {
+        admin_api::accept_consent_request(&CONFIGURATION, consent_challenge, Some(body))
+            .await
+            .map_err(|error| anyhow!("Couldn't accept consent request: {}", error))
+    }
+
+    async fn reject_consent_request(
+        &self,
+        consent_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<CompletedRequest> {
+        admin_api::reject_consent_request(&CONFIGURATION, consent_challenge, Some(body))
+            .await
+            .map_err(|error| anyhow!("Couldn't reject consent request: {}", error))
+    }
+
+    async fn get_logout_request(&self, logout_challenge: &str) -> Result<LogoutRequest> {
+        admin_api::get_logout_request(&CONFIGURATION, logout_challenge)
+            .await
+            .map_err(|error| anyhow!("Couldn't get logout request: {}", error))
+    }
+
+    async fn accept_logout_request(&self, logout_challenge: &str) -> Result<CompletedRequest> {
+        admin_api::accept_logout_request(&CONFIGURATION, logout_challenge)
+            .await
+            .map_err(|error| anyhow!("Couldn't accept logout request: {}", error))
+    }
+
+    async fn reject_logout_request(
+        &self,
+        logout_challenge: &str,
+        body: RejectRequest,
+    ) -> Result<()> {
+        admin_api::reject_logout_request(&CONFIGURATION, logout_challenge, Some(body))
+            .await
+            .map_err(|error| anyhow!("Couldn't reject logout request: {}", error))
+    }
+
+    async fn create_client(&self, client: OAuth2Client) -> Result<OAuth2Client> {
+        admin_api::create_o_auth2_client(&CONFIGURATION, client)
+            .await
+            .map_err(|error| anyhow!("Couldn't create client: {}", error))
+    }
+
+    async fn introspect_token(&self, token: &str) -> Result<OAuth2TokenIntrospection> {
+        admin_api::introspect_o_auth2_token(&CONFIGURATION, token, None)
+            .await
+            .map_err(|error| anyhow!("Couldn't introspect token: {}", error))
+    }
+
+    async fn revoke_sessions(&self, subject: &str) -> Result<()> {
+        admin_api::revoke_authentication_session(&CONFIGURATION, subject)
+            .await
+            .map_err(|error| anyhow!("Couldn't revoke authentication session: {}", error))?;
+
+        admin_api::revoke_consent_sessions(&CONFIGURATION, subject, None, Some(true))
+            .await
+            .map_err(|error| anyhow!("Couldn't revoke consent sessions: {}", error))
+    }
+}
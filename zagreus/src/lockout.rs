@@ -0,0 +1,151 @@
+/// Brute-force protection for the login.
+/// Failed attempts are counted per email and per ip address, each failure slows the response
+/// down a bit more and once too many failures happened the email (or the ip address) is locked
+/// for a while (see the `LOGIN_*` variables).
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use std::time;
+use zagreus_domain::{
+    models::login_failure::LoginFailureKind, repositories::LoginFailureRepository,
+};
+
+const DELAY_STEP_MILLISECONDS: u64 = 250;
+
+const MAX_DELAY_MILLISECONDS: u64 = 5000;
+
+#[derive(Debug, PartialEq)]
+pub enum Lock {
+    Account,
+    Ip,
+}
+
+fn email_identifier(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// The delay applied to a failed attempt, grows linearly with the number of failures.
+fn delay(failed_attempts: i32) -> time::Duration {
+    let failed_attempts = failed_attempts.max(0) as u64;
+
+    time::Duration::from_millis(
+        (failed_attempts * DELAY_STEP_MILLISECONDS).min(MAX_DELAY_MILLISECONDS),
+    )
+}
+
+pub async fn is_account_locked(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+) -> Result<bool> {
+    let login_failure = login_failures
+        .get(LoginFailureKind::Email, email_identifier(email).as_str())
+        .await?;
+
+    Ok(matches!(login_failure, Some(login_failure) if login_failure.is_locked()))
+}
+
+/// Returns the lock preventing the login attempt, if any.
+pub async fn get_lock(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<Option<Lock>> {
+    if is_account_locked(login_failures, email).await? {
+        return Ok(Some(Lock::Account));
+    }
+
+    if let Some(ip) = ip {
+        let login_failure = login_failures.get(LoginFailureKind::Ip, ip).await?;
+
+        if matches!(login_failure, Some(login_failure) if login_failure.is_locked()) {
+            return Ok(Some(Lock::Ip));
+        }
+    }
+
+    Ok(None)
+}
+
+async fn increment(
+    login_failures: &dyn LoginFailureRepository,
+    kind: LoginFailureKind,
+    identifier: &str,
+    max_attempts: i32,
+) -> Result<i32> {
+    let now = Utc::now().naive_utc();
+
+    let lockout_duration = Duration::minutes(zagreus_config::env::LOGIN::LOCKOUT_MINUTES());
+
+    let login_failure = login_failures
+        .increment(kind, identifier, &(now - lockout_duration))
+        .await?;
+
+    if login_failure.failed_attempts >= max_attempts {
+        login_failures
+            .update_locked_until(kind, identifier, &(now + lockout_duration))
+            .await?;
+    }
+
+    Ok(login_failure.failed_attempts)
+}
+
+/// Counts a failed attempt for the email and the ip address, and waits before returning.
+pub async fn record_failure(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+    ip: Option<&str>,
+) -> Result<()> {
+    let failed_attempts = increment(
+        login_failures,
+        LoginFailureKind::Email,
+        email_identifier(email).as_str(),
+        zagreus_config::env::LOGIN::MAX_ATTEMPTS(),
+    )
+    .await?;
+
+    if let Some(ip) = ip {
+        increment(
+            login_failures,
+            LoginFailureKind::Ip,
+            ip,
+            zagreus_config::env::LOGIN::MAX_IP_ATTEMPTS(),
+        )
+        .await?;
+    }
+
+    tokio::time::sleep(delay(failed_attempts)).await;
+
+    Ok(())
+}
+
+/// Forgets the failed attempts of an email, after a successful login typically.
+pub async fn unlock_account(
+    login_failures: &dyn LoginFailureRepository,
+    email: &str,
+) -> Result<bool> {
+    login_failures
+        .delete(LoginFailureKind::Email, email_identifier(email).as_str())
+        .await
+}
+
+pub async fn unlock_ip(login_failures: &dyn LoginFailureRepository, ip: &str) -> Result<bool> {
+    login_failures.delete(LoginFailureKind::Ip, ip).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{delay, email_identifier};
+
+    #[test]
+    fn it_delays_progressively() {
+        assert_eq!(delay(0), Duration::from_millis(0));
+        assert_eq!(delay(1), Duration::from_millis(250));
+        assert_eq!(delay(4), Duration::from_millis(1000));
+        assert_eq!(delay(100), Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn it_normalizes_emails() {
+        assert_eq!(email_identifier(" User@Example.com"), "user@example.com");
+    }
+}
@@ -0,0 +1,31 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+use super::{Email, MailerError, Transport};
+
+/// Keeps the sent emails in memory so that they can be inspected (in tests typically).
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    messages: Arc<Mutex<Vec<Email>>>,
+}
+
+impl MemoryTransport {
+    pub fn messages(&self) -> Vec<Email> {
+        self.messages
+            .lock()
+            .expect("memory transport lock poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        self.messages
+            .lock()
+            .map_err(|_| MailerError::EmailNotSent)?
+            .push(email);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+/// Outgoing emails (invitations, password resets, etc...).
+/// Emails are rendered from the `emails/<name>.html` (body) and `emails/<name>_subject.html`
+/// (subject) templates and then handed over to a `Transport` that takes care of the delivery.
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use tera::{Context, Tera};
+use thiserror::Error;
+
+#[cfg(test)]
+pub mod memory;
+pub mod smtp;
+pub mod stdout;
+
+#[derive(Error, Debug)]
+pub enum MailerError {
+    #[error("email template couldn't be rendered")]
+    TemplateNotRendered,
+    #[error("email address is invalid")]
+    InvalidAddress,
+    #[error("email couldn't be sent")]
+    EmailNotSent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailerError>;
+}
+
+pub struct Mailer {
+    from: String,
+    /// The public url of Zagreus, the links of the emails point to it.
+    url: String,
+    templates: &'static Tera,
+    transport: Box<dyn Transport>,
+}
+
+impl Mailer {
+    pub fn new(
+        from: String,
+        url: String,
+        templates: &'static Tera,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        Mailer {
+            from,
+            url,
+            templates,
+            transport,
+        }
+    }
+
+    /// Builds a mailer using the transport defined by the `MAILER_TRANSPORT` variable.
+    pub fn from_env(templates: &'static Tera) -> Result<Self> {
+        let transport: Box<dyn Transport> = match zagreus_config::env::MAILER::TRANSPORT().as_str()
+        {
+            "smtp" => Box::new(smtp::SmtpTransport::from_env()?),
+            "stdout" => Box::new(stdout::StdoutTransport),
+            transport => return Err(anyhow!("Unknown mailer transport {}", transport)),
+        };
+
+        Ok(Mailer::new(
+            zagreus_config::env::MAILER::FROM(),
+            zagreus_config::env::URL().to_string(),
+            templates,
+            transport,
+        ))
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Renders the `name` email templates with the provided context and sends the result to `to`.
+    pub async fn send<T>(&self, to: &str, name: &str, context: T) -> Result<(), MailerError>
+    where
+        T: Serialize,
+    {
+        let context =
+            Context::from_serialize(context).map_err(|_| MailerError::TemplateNotRendered)?;
+
+        let subject = self
+            .templates
+            .render(format!("emails/{}_subject.html", name).as_str(), &context)
+            .map_err(|_| MailerError::TemplateNotRendered)?;
+
+        let body = self
+            .templates
+            .render(format!("emails/{}.html", name).as_str(), &context)
+            .map_err(|_| MailerError::TemplateNotRendered)?;
+
+        self.transport
+            .send(Email {
+                from: self.from.clone(),
+                to: to.to_string(),
+                subject: subject.trim().to_string(),
+                body,
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use tera::Tera;
+
+    use super::memory::MemoryTransport;
+    use super::{Email, Mailer};
+
+    lazy_static! {
+        static ref TEMPLATES: Tera = {
+            let mut tera = Tera::default();
+
+            tera.add_raw_templates(vec![
+                ("emails/welcome_subject.html", "\n  Welcome {{ email }}\n"),
+                (
+                    "emails/welcome.html",
+                    "<a href=\"{{ url | safe }}\">Join</a>",
+                ),
+            ])
+            .unwrap();
+
+            tera
+        };
+    }
+
+    #[derive(Serialize)]
+    struct WelcomeEmail {
+        email: String,
+        url: String,
+    }
+
+    #[actix_rt::test]
+    async fn it_renders_and_sends_emails() {
+        let transport = MemoryTransport::default();
+
+        let mailer = Mailer::new(
+            "zagreus@example.com".to_string(),
+            "http://localhost".to_string(),
+            &TEMPLATES,
+            Box::new(transport.clone()),
+        );
+
+        mailer
+            .send(
+                "user@example.com",
+                "welcome",
+                WelcomeEmail {
+                    email: "user@example.com".to_string(),
+                    url: "http://localhost/join".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            transport.messages(),
+            vec![Email {
+                from: "zagreus@example.com".to_string(),
+                to: "user@example.com".to_string(),
+                subject: "Welcome user@example.com".to_string(),
+                body: "<a href=\"http://localhost/join\">Join</a>".to_string(),
+            }]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn it_fails_on_missing_templates() {
+        let transport = MemoryTransport::default();
+
+        let mailer = Mailer::new(
+            "zagreus@example.com".to_string(),
+            "http://localhost".to_string(),
+            &TEMPLATES,
+            Box::new(transport.clone()),
+        );
+
+        assert!(mailer
+            .send("user@example.com", "unknown", ())
+            .await
+            .is_err());
+        assert!(transport.messages().is_empty());
+    }
+}
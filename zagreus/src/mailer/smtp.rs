@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::{Email, MailerError, Transport};
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    /// Builds a STARTTLS smtp transport from the `MAILER_SMTP_*` variables.
+    pub fn from_env() -> Result<Self> {
+        let host = zagreus_config::env::MAILER::SMTP_HOST()
+            .ok_or_else(|| anyhow!("MAILER_SMTP_HOST is required by the smtp transport"))?;
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host.as_str())?;
+
+        if let Some(port) = zagreus_config::env::MAILER::SMTP_PORT() {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (
+            zagreus_config::env::MAILER::SMTP_USERNAME(),
+            zagreus_config::env::MAILER::SMTP_PASSWORD(),
+        ) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpTransport {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for SmtpTransport {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        let message = Message::builder()
+            .from(
+                email
+                    .from
+                    .parse()
+                    .map_err(|_| MailerError::InvalidAddress)?,
+            )
+            .to(email.to.parse().map_err(|_| MailerError::InvalidAddress)?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_HTML)
+            .body(email.body)
+            .map_err(|_| MailerError::EmailNotSent)?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|_| MailerError::EmailNotSent)?;
+
+        Ok(())
+    }
+}
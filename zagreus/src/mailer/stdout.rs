@@ -0,0 +1,19 @@
+use async_trait::async_trait;
+
+use super::{Email, MailerError, Transport};
+
+/// Prints the emails instead of sending them, meant to be used during development.
+#[derive(Debug)]
+pub struct StdoutTransport;
+
+#[async_trait]
+impl Transport for StdoutTransport {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        println!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            email.from, email.to, email.subject, email.body
+        );
+
+        Ok(())
+    }
+}
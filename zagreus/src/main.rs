@@ -4,12 +4,27 @@ extern crate lazy_static;
 use anyhow::Result;
 use clap::{crate_version, Clap};
 use log::info;
+use std::path::PathBuf;
 
 mod api;
+mod claims;
+mod codes;
 mod commands;
+mod errors;
+mod hydra;
 mod hydra_configuration;
+mod lockout;
+mod mailer;
+mod middlewares;
+mod password_policy;
+mod rate_limit;
+mod session;
+#[cfg(test)]
+mod test_utils;
+mod totp;
 mod validations;
 mod views;
+mod webauthn;
 
 #[derive(Debug, Clap)]
 #[clap(version = crate_version!())]
@@ -24,8 +39,89 @@ enum Command {
         /// The client (IDP) name
         #[clap(short, long)]
         client_name: String,
+        /// A url the invitations and password resets of the client can redirect to (can be repeated)
+        #[clap(short, long, multiple_occurrences = true)]
+        redirect_uri: Vec<String>,
+        /// Trust the client, its users won't be asked for their consent
+        #[clap(long)]
+        skip_consent: bool,
     },
     Run,
+    /// Replaces the urls the invitations and password resets of a client can redirect to
+    SetRedirectUris {
+        /// The client (IDP) name
+        #[clap(short, long)]
+        client_id: String,
+        /// A redirect url (can be repeated)
+        #[clap(short, long, multiple_occurrences = true)]
+        redirect_uri: Vec<String>,
+    },
+    /// Creates a role, along with its permissions
+    CreateRole {
+        /// The role name
+        #[clap(short, long)]
+        name: String,
+        #[clap(short, long)]
+        description: Option<String>,
+        /// A permission granted by the role (can be repeated)
+        #[clap(short, long, multiple_occurrences = true)]
+        permission: Vec<String>,
+    },
+    /// Assigns a role to a user
+    AssignRole {
+        /// The email of the user
+        #[clap(short, long)]
+        email: String,
+        /// The role name
+        #[clap(short, long)]
+        role: String,
+        /// Assigns the role for this client only (for all the clients otherwise)
+        #[clap(short, long)]
+        client_id: Option<String>,
+    },
+    /// Unassigns a role from a user
+    UnassignRole {
+        /// The email of the user
+        #[clap(short, long)]
+        email: String,
+        /// The role name
+        #[clap(short, long)]
+        role: String,
+        /// Unassigns the role assigned for this client only
+        #[clap(short, long)]
+        client_id: Option<String>,
+    },
+    /// Manages the invitations
+    Invite {
+        #[clap(subcommand)]
+        command: InviteCommand,
+    },
+    /// Unlocks an account (or an ip address) locked after too many failed login attempts
+    Unlock {
+        /// The email of the locked account
+        #[clap(short, long)]
+        email: Option<String>,
+        /// The locked ip address
+        #[clap(short, long)]
+        ip: Option<String>,
+    },
+}
+
+#[derive(Debug, Clap)]
+enum InviteCommand {
+    /// Invites all the emails of a csv file (an `email` column, and an optional `roles` column
+    /// with the role names separated by spaces)
+    Import {
+        /// The csv file
+        #[clap(short, long)]
+        file: PathBuf,
+        /// The client the users are invited to
+        #[clap(short, long)]
+        client_id: String,
+        /// Where the users are redirected once their invitation is completed
+        #[clap(short, long)]
+        redirect_uri: String,
+    },
 }
 
 #[actix_web::main]
@@ -37,12 +133,44 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     match options.command {
-        Command::Init { client_name } => {
-            commands::init(client_name.as_str()).await?;
+        Command::Init {
+            client_name,
+            redirect_uri,
+            skip_consent,
+        } => {
+            commands::init(client_name.as_str(), &redirect_uri, skip_consent).await?;
 
             info!("Zagreus has been successfully initiailized");
         }
         Command::Run => commands::run().await?,
+        Command::SetRedirectUris {
+            client_id,
+            redirect_uri,
+        } => commands::set_redirect_uris(client_id.as_str(), &redirect_uri).await?,
+        Command::CreateRole {
+            name,
+            description,
+            permission,
+        } => commands::create_role(name.as_str(), description.as_deref(), &permission).await?,
+        Command::AssignRole {
+            email,
+            role,
+            client_id,
+        } => commands::assign_role(email.as_str(), role.as_str(), client_id.as_deref()).await?,
+        Command::UnassignRole {
+            email,
+            role,
+            client_id,
+        } => commands::unassign_role(email.as_str(), role.as_str(), client_id.as_deref()).await?,
+        Command::Invite {
+            command:
+                InviteCommand::Import {
+                    file,
+                    client_id,
+                    redirect_uri,
+                },
+        } => commands::import_invitations(&file, client_id.as_str(), redirect_uri.as_str()).await?,
+        Command::Unlock { email, ip } => commands::unlock(email.as_deref(), ip.as_deref()).await?,
     };
 
     Ok(())
@@ -0,0 +1,246 @@
+/// Restricts a set of routes to the admins (see `session::AdminSession`).
+/// The authenticated admin is stored in the request extensions, so the handlers extracting
+/// an `AdminSession` don't introspect the access token a second time.
+use actix_web::{
+    dev::{forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    Error, FromRequest, HttpResponse, ResponseError,
+};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use crate::session::{AdminSession, SessionError};
+use crate::views::html_error_response;
+
+#[derive(Clone, Debug)]
+struct AdminRoute {
+    /// Any method when `None`.
+    method: Option<Method>,
+    resource: ResourceDef,
+}
+
+impl AdminRoute {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        if let Some(route_method) = &self.method {
+            if route_method != method {
+                return false;
+            }
+        }
+
+        self.resource.is_prefix_match(path).is_some()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RequireAdmin {
+    routes: Rc<Vec<AdminRoute>>,
+}
+
+impl RequireAdmin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Protects a single route, the path can contain parameters (`/api/invitation/{id}`).
+    pub fn route(mut self, method: Method, path: &str) -> Self {
+        Rc::make_mut(&mut self.routes).push(AdminRoute {
+            method: Some(method),
+            resource: ResourceDef::new(path),
+        });
+
+        self
+    }
+
+    /// Protects all the routes under the path, whatever their method.
+    pub fn scope(mut self, path: &str) -> Self {
+        Rc::make_mut(&mut self.routes).push(AdminRoute {
+            method: None,
+            resource: ResourceDef::prefix(path),
+        });
+
+        self
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RequireAdmin
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = RequireAdminMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAdminMiddleware {
+            service: Rc::new(service),
+            routes: self.routes.clone(),
+        }))
+    }
+}
+
+pub struct RequireAdminMiddleware<S> {
+    service: Rc<S>,
+    routes: Rc<Vec<AdminRoute>>,
+}
+
+/// The api gets the json error, the views get the error page: the visitors can't get an admin
+/// token through the login, so they aren't sent there.
+fn error_response(path: &str, error: SessionError) -> HttpResponse {
+    if path.starts_with("/api/") {
+        return error.error_response();
+    }
+
+    html_error_response(&error)
+}
+
+impl<S> Service<ServiceRequest> for RequireAdminMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // The router matches the decoded path, `/invit%61tions` has to be checked as `/invitations`
+        let is_admin_route = self
+            .routes
+            .iter()
+            .any(|route| route.matches(req.method(), req.match_info().path()));
+
+        if !is_admin_route {
+            return Box::pin(self.service.call(req));
+        }
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let (http_req, payload) = req.into_parts();
+
+            match AdminSession::extract(&http_req).await {
+                Ok(admin) => {
+                    http_req.extensions_mut().insert(admin);
+
+                    service
+                        .call(ServiceRequest::from_parts(http_req, payload))
+                        .await
+                }
+                Err(error) => {
+                    let res = error_response(http_req.match_info().path(), error);
+
+                    Ok(ServiceResponse::new(http_req, res))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{Method, StatusCode},
+        test, web, App, HttpResponse,
+    };
+
+    use super::RequireAdmin;
+
+    fn require_admin() -> RequireAdmin {
+        RequireAdmin::new()
+            .route(Method::POST, "/api/invitation")
+            .route(Method::DELETE, "/api/invitation/{id}")
+            .route(Method::GET, "/invitations")
+            .scope("/api/admin")
+    }
+
+    #[test]
+    fn it_matches_the_admin_routes() {
+        let routes = require_admin().routes;
+
+        let is_admin_route =
+            |method: Method, path: &str| routes.iter().any(|route| route.matches(&method, path));
+
+        assert!(is_admin_route(Method::POST, "/api/invitation"));
+        assert!(!is_admin_route(Method::PUT, "/api/invitation"));
+        assert!(is_admin_route(Method::DELETE, "/api/invitation/id"));
+        assert!(!is_admin_route(Method::DELETE, "/api/invitation/id/more"));
+        assert!(!is_admin_route(Method::GET, "/invitations/code"));
+        assert!(is_admin_route(Method::DELETE, "/api/admin/users/id"));
+        assert!(is_admin_route(Method::GET, "/api/admin"));
+        assert!(!is_admin_route(Method::GET, "/api/administrators"));
+    }
+
+    #[actix_rt::test]
+    async fn it_rejects_the_requests_without_access_token() {
+        let app = test::init_service(
+            App::new()
+                .wrap(require_admin())
+                .route("/invitations", web::get().to(HttpResponse::Ok))
+                .route("/api/invitation", web::post().to(HttpResponse::Ok))
+                .route("/api/invitation", web::put().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/invitation")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/invitations").to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(res).await, "access token is missing");
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::put().uri("/api/invitation").to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn it_rejects_the_percent_encoded_admin_routes() {
+        let app = test::init_service(
+            App::new()
+                .wrap(require_admin())
+                .route("/invitations", web::get().to(HttpResponse::Ok))
+                .route("/api/invitation", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/invit%61tions").to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/invit%61tion")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,386 @@
+/// Protects the state-changing api routes against cross-site request forgery.
+/// Every visitor gets a signed token in the `csrf_token` cookie, which is also injected into the
+/// context of the rendered templates (`csrf_token`), and must be sent back in the `X-CSRF-Token`
+/// header of the `POST`, `PUT`, `PATCH` and `DELETE` requests to `/api/*`.
+/// The requests authenticated with an `Authorization` header are not concerned (browsers never
+/// add it on their own), nor are the exempted routes.
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    dev::{forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method, StatusCode},
+    Error, HttpMessage, ResponseError,
+};
+use hmac::{Hmac, Mac, NewMac};
+use rand::{distributions, Rng};
+use rand_core::OsRng;
+use sha2::Sha256;
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+use crate::errors::{json_response_error, ErrorDetails};
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const NONCE_LENGTH: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum CsrfError {
+    #[error("csrf token is missing")]
+    MissingToken,
+    #[error("csrf token is invalid")]
+    InvalidToken,
+}
+
+impl ErrorDetails for CsrfError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            CsrfError::MissingToken => (StatusCode::FORBIDDEN, "missing_csrf_token"),
+            CsrfError::InvalidToken => (StatusCode::FORBIDDEN, "invalid_csrf_token"),
+        }
+    }
+}
+
+json_response_error!(CsrfError);
+
+/// The token of the current visitor, available in the request extensions.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(pub String);
+
+#[derive(Clone, Debug)]
+struct Signer {
+    secret: Vec<u8>,
+}
+
+impl Signer {
+    fn mac(&self, nonce: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts keys of any size");
+
+        mac.update(nonce.as_bytes());
+
+        mac
+    }
+
+    /// A random nonce followed by its signature: `<nonce>.<signature>`.
+    fn generate(&self) -> String {
+        let nonce: String = OsRng
+            .sample_iter(distributions::Alphanumeric)
+            .take(NONCE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let signature = base32::encode(
+            base32::Alphabet::RFC4648 { padding: false },
+            &self.mac(nonce.as_str()).finalize().into_bytes(),
+        );
+
+        format!("{}.{}", nonce, signature)
+    }
+
+    fn verify(&self, token: &str) -> bool {
+        let (nonce, signature) = match token.split_once('.') {
+            Some(parts) => parts,
+            None => return false,
+        };
+
+        let signature =
+            match base32::decode(base32::Alphabet::RFC4648 { padding: false }, signature) {
+                Some(signature) => signature,
+                None => return false,
+            };
+
+        // Constant time comparison
+        self.mac(nonce).verify(&signature).is_ok()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Csrf {
+    signer: Rc<Signer>,
+    exempt: Rc<Vec<ResourceDef>>,
+    secure: bool,
+}
+
+impl Csrf {
+    pub fn new(secret: &str) -> Self {
+        Csrf {
+            signer: Rc::new(Signer {
+                secret: secret.as_bytes().to_vec(),
+            }),
+            exempt: Rc::new(Vec::new()),
+            secure: false,
+        }
+    }
+
+    /// Exempts all the routes under the path.
+    pub fn exempt(mut self, path: &str) -> Self {
+        Rc::make_mut(&mut self.exempt).push(ResourceDef::prefix(path));
+
+        self
+    }
+
+    /// Only sends the cookie over https.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+
+        self
+    }
+
+    fn is_protected(&self, req: &ServiceRequest) -> bool {
+        let is_state_changing = matches!(
+            *req.method(),
+            Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+        );
+
+        // The router matches the decoded path, `/%61pi/login` has to be checked as `/api/login`
+        let path = req.match_info().path();
+
+        is_state_changing
+            && path.starts_with("/api/")
+            && !req.headers().contains_key(header::AUTHORIZATION)
+            && !self
+                .exempt
+                .iter()
+                .any(|resource| resource.is_prefix_match(path).is_some())
+    }
+}
+
+/// The signed token of the cookie, if any.
+fn cookie_token(req: &ServiceRequest, signer: &Signer) -> Option<String> {
+    req.cookie(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| signer.verify(token))
+}
+
+fn check(req: &ServiceRequest, cookie_token: Option<&str>) -> Result<(), CsrfError> {
+    let header_token = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .ok_or(CsrfError::MissingToken)?;
+
+    let cookie_token = cookie_token.ok_or(CsrfError::InvalidToken)?;
+
+    if !bool::from(header_token.as_bytes().ct_eq(cookie_token.as_bytes())) {
+        return Err(CsrfError::InvalidToken);
+    }
+
+    Ok(())
+}
+
+impl<S> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+            csrf: self.clone(),
+        }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+    csrf: Csrf,
+}
+
+impl<S> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let existing_token = cookie_token(&req, &self.csrf.signer);
+
+        if self.csrf.is_protected(&req) {
+            if let Err(error) = check(&req, existing_token.as_deref()) {
+                let res = error.error_response();
+
+                return Box::pin(async move { Ok(req.into_response(res)) });
+            }
+        }
+
+        let token = match existing_token {
+            Some(token) => {
+                req.extensions_mut().insert(CsrfToken(token));
+
+                None
+            }
+            None => {
+                let token = self.csrf.signer.generate();
+
+                req.extensions_mut().insert(CsrfToken(token.clone()));
+
+                Some(token)
+            }
+        };
+
+        let secure = self.csrf.secure;
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+
+            // Only the visitors without a (valid) token get a new one
+            if let Some(token) = token {
+                let cookie = Cookie::build(CSRF_COOKIE, token)
+                    .path("/")
+                    .http_only(true)
+                    .same_site(SameSite::Strict)
+                    .secure(secure)
+                    .finish();
+
+                res.response_mut().add_cookie(&cookie)?;
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, StatusCode},
+        test, web, App, HttpResponse,
+    };
+
+    use super::{Csrf, Signer, CSRF_COOKIE, CSRF_HEADER};
+
+    #[test]
+    fn it_verifies_the_signed_tokens() {
+        let signer = Signer {
+            secret: b"secret".to_vec(),
+        };
+
+        let token = signer.generate();
+
+        assert!(signer.verify(token.as_str()));
+
+        let other_signer = Signer {
+            secret: b"other secret".to_vec(),
+        };
+
+        assert!(!other_signer.verify(token.as_str()));
+        assert!(!signer.verify(token.replacen('.', "x.", 1).as_str()));
+        assert!(!signer.verify("unsigned"));
+    }
+
+    #[actix_rt::test]
+    async fn it_requires_the_token_on_the_state_changing_api_routes() {
+        let app = test::init_service(
+            App::new()
+                .wrap(Csrf::new("secret").exempt("/api/public"))
+                .route("/login", web::get().to(HttpResponse::Ok))
+                .route("/api/login", web::post().to(HttpResponse::Ok))
+                .route("/api/public/hook", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/login").to_request()).await;
+
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == CSRF_COOKIE)
+            .expect("csrf cookie is missing")
+            .into_owned();
+
+        let post = || test::TestRequest::post().uri("/api/login");
+
+        let res = test::call_service(&app, post().to_request()).await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = test::call_service(
+            &app,
+            post()
+                .cookie(cookie.clone())
+                .insert_header((CSRF_HEADER, "forged"))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = test::call_service(
+            &app,
+            post()
+                .cookie(cookie.clone())
+                .insert_header((CSRF_HEADER, cookie.value()))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = test::call_service(
+            &app,
+            post()
+                .insert_header((header::AUTHORIZATION, "Bearer token"))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/public/hook")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn it_requires_the_token_on_the_percent_encoded_api_routes() {
+        let app = test::init_service(
+            App::new()
+                .wrap(Csrf::new("secret").exempt("/api/public"))
+                .route("/api/login", web::post().to(HttpResponse::Ok))
+                .route("/api/public/hook", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for uri in ["/%61pi/login", "/api/%6cogin"] {
+            let res =
+                test::call_service(&app, test::TestRequest::post().uri(uri).to_request()).await;
+
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/publi%63/hook")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
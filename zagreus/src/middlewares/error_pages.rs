@@ -0,0 +1,150 @@
+/// Renders the error pages of the views (see `views::html_error_response`) with the request,
+/// so that the `error.html` template gets its csrf token and csp nonce like any page.
+/// The errors are built without the request (`ResponseError` doesn't get it), they're kept in
+/// the response extensions until they come through this middleware.
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    Error,
+};
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+use tera::Tera;
+
+use crate::views::{render_error_page, ErrorPage};
+
+#[derive(Clone, Debug)]
+pub struct ErrorPages {
+    templates: &'static Tera,
+}
+
+impl ErrorPages {
+    pub fn new(templates: &'static Tera) -> Self {
+        ErrorPages { templates }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for ErrorPages
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = ErrorPagesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ErrorPagesMiddleware {
+            service: Rc::new(service),
+            templates: self.templates,
+        }))
+    }
+}
+
+pub struct ErrorPagesMiddleware<S> {
+    service: Rc<S>,
+    templates: &'static Tera,
+}
+
+impl<S> Service<ServiceRequest> for ErrorPagesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        let templates = self.templates;
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+
+            let error_page = res.response_mut().extensions_mut().remove::<ErrorPage>();
+
+            let error_page = match error_page {
+                Some(error_page) => error_page,
+                None => return Ok(res),
+            };
+
+            let page = render_error_page(templates, &error_page, Some(&res.request().extensions()));
+
+            // The plain text error is kept without the `error.html` template
+            let page = match page {
+                Some(page) => page,
+                None => return Ok(res),
+            };
+
+            let content_type = page.headers().get(header::CONTENT_TYPE).cloned();
+
+            // Only the body and its type are replaced, the headers set along the way
+            // (cookies...) are kept
+            let mut res = res.map_body(|_, _| page.into_body());
+
+            if let Some(content_type) = content_type {
+                res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::Service,
+        http::{header, StatusCode},
+        test, web, App, HttpMessage, HttpResponse,
+    };
+
+    use super::ErrorPages;
+    use crate::middlewares::security_headers::CspNonce;
+    use crate::test_utils::TEMPLATES;
+    use crate::views::ErrorPage;
+
+    async fn not_found() -> HttpResponse {
+        let mut res = HttpResponse::NotFound().body("rendered without the request");
+
+        res.extensions_mut().insert(ErrorPage {
+            status: 404,
+            code: "not_found",
+            message: String::from("not found"),
+            errors: None,
+        });
+
+        res
+    }
+
+    #[actix_rt::test]
+    async fn it_renders_the_error_pages_with_the_request() {
+        let app = test::init_service(
+            App::new()
+                .wrap(ErrorPages::new(&TEMPLATES))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(CspNonce(String::from("nonce")));
+
+                    srv.call(req)
+                })
+                .route("/", web::get().to(not_found)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/html;charset=utf-8"
+        );
+        assert_eq!(test::read_body(res).await, "not_found nonce");
+    }
+}
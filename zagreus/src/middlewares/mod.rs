@@ -0,0 +1,5 @@
+pub mod admin;
+pub mod csrf;
+pub mod error_pages;
+pub mod rate_limit;
+pub mod security_headers;
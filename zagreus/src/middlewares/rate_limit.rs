@@ -0,0 +1,385 @@
+/// Limits the number of requests each client (ip address) can send to a set of routes, see
+/// `crate::rate_limit`. A `429` with a `Retry-After` header is returned once the limit is reached.
+/// The ip address of the client is stored in the request extensions (`ClientIp`), it's taken
+/// from the `X-Forwarded-For` header when the request comes from a trusted proxy.
+use actix_web::{
+    dev::{forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, HeaderValue, Method, StatusCode},
+    Error, HttpMessage, ResponseError,
+};
+use anyhow::{anyhow, Result};
+use log::error;
+use std::{
+    future::{ready, Future, Ready},
+    net::{IpAddr, Ipv6Addr},
+    pin::Pin,
+    rc::Rc,
+    str::FromStr,
+    sync::Arc,
+};
+use thiserror::Error;
+
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::rate_limit::{Decision, Limit, RateLimitBackend};
+
+#[derive(Debug, Error)]
+pub enum RateLimitError {
+    #[error("too many requests, retry later")]
+    TooManyRequests,
+}
+
+impl ErrorDetails for RateLimitError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            RateLimitError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, "too_many_requests"),
+        }
+    }
+}
+
+json_response_error!(RateLimitError);
+
+/// The ip address of the client, available in the request extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+/// An ip address, or a network (`10.0.0.0/8`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl TrustedProxy {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (
+                u128::from(u32::from(network)),
+                u128::from(u32::from(*ip)),
+                32,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(*ip), 128),
+            _ => return false,
+        };
+
+        // Compares the first `prefix` bits
+        let shift = bits - self.prefix;
+
+        shift == 128 || network >> shift == ip >> shift
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (network, prefix) = match value.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (value, None),
+        };
+
+        let network = IpAddr::from_str(network.trim())
+            .map_err(|_| anyhow!("Invalid trusted proxy {}", value))?;
+
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| anyhow!("Invalid trusted proxy {}", value))?,
+            None => bits,
+        };
+
+        Ok(TrustedProxy { network, prefix })
+    }
+}
+
+/// The peer address, unless it's a trusted proxy: the `X-Forwarded-For` addresses are then
+/// walked from the closest one, and the first address that isn't a trusted proxy is the client.
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[TrustedProxy]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    let mut client_ip = req.peer_addr()?.ip();
+
+    let forwarded_ips = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| IpAddr::from_str(ip.trim()))
+        .collect::<Vec<_>>();
+
+    for forwarded_ip in forwarded_ips.into_iter().rev() {
+        if !is_trusted(&client_ip) {
+            break;
+        }
+
+        match forwarded_ip {
+            Ok(forwarded_ip) => client_ip = forwarded_ip,
+            // The chain can't be trusted past a malformed address
+            Err(_) => break,
+        }
+    }
+
+    Some(client_ip)
+}
+
+/// Identifies the buckets of a client. An IPv6 client usually owns a whole /64, so the
+/// addresses of the network share their buckets.
+fn client_key(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let network = Ipv6Addr::from(u128::from(*ip) & !(u128::MAX >> 64));
+
+            format!("{}/64", network)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LimitedRoute {
+    /// Identifies the buckets of the route.
+    name: String,
+    method: Method,
+    resource: ResourceDef,
+    limit: Limit,
+}
+
+#[derive(Clone)]
+pub struct RateLimit {
+    backend: Arc<dyn RateLimitBackend>,
+    routes: Arc<Vec<LimitedRoute>>,
+    trusted_proxies: Arc<Vec<TrustedProxy>>,
+}
+
+impl RateLimit {
+    pub fn new(backend: Arc<dyn RateLimitBackend>) -> Self {
+        RateLimit {
+            backend,
+            routes: Arc::new(Vec::new()),
+            trusted_proxies: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Limits a single route, the path can contain parameters (`/invitations/{challenge}`).
+    /// Ignored when the limit is `None`.
+    pub fn route(mut self, method: Method, path: &str, limit: Option<Limit>) -> Self {
+        if let Some(limit) = limit {
+            Arc::make_mut(&mut self.routes).push(LimitedRoute {
+                name: format!("{} {}", method, path),
+                method,
+                resource: ResourceDef::new(path),
+                limit,
+            });
+        }
+
+        self
+    }
+
+    /// Trusts the `X-Forwarded-For` header of the requests sent by the proxy.
+    pub fn trust_proxy(mut self, proxy: &str) -> Result<Self> {
+        Arc::make_mut(&mut self.trusted_proxies).push(proxy.parse()?);
+
+        Ok(self)
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            rate_limit: self.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    rate_limit: RateLimit,
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let client_ip = client_ip(&req, &self.rate_limit.trusted_proxies);
+
+        if let Some(client_ip) = client_ip {
+            req.extensions_mut().insert(ClientIp(client_ip));
+        }
+
+        let route = self
+            .rate_limit
+            .routes
+            .iter()
+            .find(|route| {
+                // The router matches the decoded path, `/api/l%6Fgin` is `/api/login`
+                route.method == req.method() && route.resource.is_match(req.match_info().path())
+            })
+            .map(|route| (route.name.clone(), route.limit));
+
+        let backend = self.rate_limit.backend.clone();
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if let (Some((name, limit)), Some(client_ip)) = (route, client_ip) {
+                match backend
+                    .take(name.as_str(), client_key(&client_ip).as_str(), &limit)
+                    .await
+                {
+                    Ok(Decision::Allowed) => {}
+                    Ok(Decision::Limited { retry_after }) => {
+                        let mut res = RateLimitError::TooManyRequests.error_response();
+
+                        res.headers_mut()
+                            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+
+                        return Ok(req.into_response(res));
+                    }
+                    // Lets the request through rather than locking everyone out
+                    Err(error) => error!("Couldn't rate limit {}: {}", name, error),
+                }
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, Method, StatusCode},
+        test, web, App, HttpRequest, HttpResponse,
+    };
+    use std::sync::Arc;
+
+    use super::{client_key, ClientIp, RateLimit, TrustedProxy};
+    use crate::rate_limit::{memory::MemoryBackend, Limit};
+
+    #[test]
+    fn it_matches_the_trusted_proxies() {
+        let proxy: TrustedProxy = "10.0.0.0/8".parse().unwrap();
+
+        assert!(proxy.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!proxy.contains(&"11.1.2.3".parse().unwrap()));
+        assert!(!proxy.contains(&"::1".parse().unwrap()));
+
+        let proxy: TrustedProxy = "::1".parse().unwrap();
+
+        assert!(proxy.contains(&"::1".parse().unwrap()));
+        assert!(!proxy.contains(&"::2".parse().unwrap()));
+
+        assert!("0.0.0.0/0"
+            .parse::<TrustedProxy>()
+            .unwrap()
+            .contains(&"1.2.3.4".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy".parse::<TrustedProxy>().is_err());
+    }
+
+    #[test]
+    fn it_groups_the_ipv6_clients_by_network() {
+        assert_eq!(client_key(&"1.2.3.4".parse().unwrap()), "1.2.3.4");
+        assert_eq!(
+            client_key(&"2001:db8:1:2:3:4:5:6".parse().unwrap()),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(
+            client_key(&"2001:db8:1:2:ffff::1".parse().unwrap()),
+            client_key(&"2001:db8:1:2::2".parse().unwrap())
+        );
+    }
+
+    async fn client_ip(req: HttpRequest) -> HttpResponse {
+        let client_ip = req.extensions().get::<ClientIp>().map(|ClientIp(ip)| *ip);
+
+        HttpResponse::Ok().body(client_ip.map(|ip| ip.to_string()).unwrap_or_default())
+    }
+
+    #[actix_rt::test]
+    async fn it_limits_the_requests_per_client() {
+        let rate_limit = RateLimit::new(Arc::new(MemoryBackend::default()))
+            .route(Method::POST, "/api/login", Limit::per_minute(1))
+            .trust_proxy("10.0.0.1")
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(rate_limit)
+                .route("/api/login", web::post().to(client_ip))
+                .route("/api/login", web::get().to(client_ip)),
+        )
+        .await;
+
+        let login = |peer_addr: &str, forwarded_for: &str| {
+            test::TestRequest::post()
+                .uri("/api/login")
+                .peer_addr(peer_addr.parse().unwrap())
+                .insert_header(("x-forwarded-for", forwarded_for))
+                .to_request()
+        };
+
+        let res = test::call_service(&app, login("1.1.1.1:4000", "")).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "1.1.1.1");
+
+        let res = test::call_service(&app, login("1.1.1.1:4000", "")).await;
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "60");
+
+        // Only the trusted proxy can forward the address of the client
+        let res = test::call_service(&app, login("10.0.0.1:4000", "6.6.6.6, 2.2.2.2")).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "2.2.2.2");
+
+        let res = test::call_service(&app, login("1.1.1.1:4000", "2.2.2.2")).await;
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/l%6Fgin")
+                .peer_addr("1.1.1.1:4000".parse().unwrap())
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/login")
+                .peer_addr("1.1.1.1:4000".parse().unwrap())
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,220 @@
+/// Adds the security headers (Content-Security-Policy, X-Frame-Options, HSTS, etc...) to the
+/// responses, without replacing the ones already set by a handler.
+/// Each request gets a random nonce, available in the request extensions and injected into the
+/// context of the rendered templates (`csp_nonce`), that replaces `{nonce}` in the policy so
+/// that the inline scripts can be allowed with `<script nonce="{{ csp_nonce }}">`.
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderName, HeaderValue, CONTENT_SECURITY_POLICY},
+        Error as HttpError,
+    },
+    Error, HttpMessage,
+};
+use rand::{distributions, Rng};
+use rand_core::OsRng;
+use std::{
+    convert::TryFrom,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+pub const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+const NONCE_LENGTH: usize = 24;
+
+/// The Content-Security-Policy nonce of the current request.
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+/// Shared by all the workers, hence the `Arc`s.
+#[derive(Clone, Debug, Default)]
+pub struct SecurityHeaders {
+    content_security_policy: Option<Arc<String>>,
+    headers: Arc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+impl SecurityHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the Content-Security-Policy, `{nonce}` is replaced by the nonce of each request.
+    /// Ignored when empty.
+    pub fn content_security_policy(mut self, policy: &str) -> Self {
+        if !policy.is_empty() {
+            self.content_security_policy = Some(Arc::new(policy.to_string()));
+        }
+
+        self
+    }
+
+    /// Adds a header with a fixed value, ignored when the value is empty.
+    pub fn header(mut self, name: &str, value: &str) -> Result<Self, HttpError> {
+        if !value.is_empty() {
+            let header = (HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+
+            Arc::make_mut(&mut self.headers).push(header);
+        }
+
+        Ok(self)
+    }
+}
+
+fn generate_nonce() -> String {
+    OsRng
+        .sample_iter(distributions::Alphanumeric)
+        .take(NONCE_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+impl<S> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = SecurityHeadersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+            security_headers: self.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    security_headers: SecurityHeaders,
+}
+
+impl<S> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let nonce = generate_nonce();
+
+        req.extensions_mut().insert(CspNonce(nonce.clone()));
+
+        let security_headers = self.security_headers.clone();
+
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+
+            let headers = res.headers_mut();
+
+            if let Some(policy) = security_headers.content_security_policy {
+                let policy = policy.replace(NONCE_PLACEHOLDER, nonce.as_str());
+
+                if let Ok(policy) = HeaderValue::try_from(policy) {
+                    if !headers.contains_key(CONTENT_SECURITY_POLICY) {
+                        headers.insert(CONTENT_SECURITY_POLICY, policy);
+                    }
+                }
+            }
+
+            for (name, value) in security_headers.headers.iter() {
+                if !headers.contains_key(name) {
+                    headers.insert(name.clone(), value.clone());
+                }
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test, web, App, HttpRequest, HttpResponse};
+
+    use super::{CspNonce, SecurityHeaders};
+
+    async fn page(req: HttpRequest) -> HttpResponse {
+        let nonce = req
+            .extensions()
+            .get::<CspNonce>()
+            .map(|CspNonce(nonce)| nonce.clone())
+            .unwrap_or_default();
+
+        HttpResponse::Ok().body(nonce)
+    }
+
+    #[actix_rt::test]
+    async fn it_adds_the_security_headers() {
+        let security_headers = SecurityHeaders::new()
+            .content_security_policy("script-src 'self' 'nonce-{nonce}'")
+            .header("x-frame-options", "DENY")
+            .unwrap()
+            .header("strict-transport-security", "")
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .wrap(security_headers)
+                .route("/login", web::get().to(page))
+                .route(
+                    "/embeddable",
+                    web::get().to(|| {
+                        HttpResponse::Ok()
+                            .insert_header((header::X_FRAME_OPTIONS, "SAMEORIGIN"))
+                            .finish()
+                    }),
+                ),
+        )
+        .await;
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/login").to_request()).await;
+
+        let policy = res
+            .headers()
+            .get(header::CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        assert_eq!(res.headers().get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert!(!res
+            .headers()
+            .contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        let nonce = test::read_body(res).await;
+
+        assert_eq!(nonce.len(), 24);
+        assert_eq!(
+            policy,
+            format!(
+                "script-src 'self' 'nonce-{}'",
+                std::str::from_utf8(&nonce).unwrap()
+            )
+        );
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/embeddable").to_request(),
+        )
+        .await;
+
+        assert_eq!(
+            res.headers().get(header::X_FRAME_OPTIONS).unwrap(),
+            "SAMEORIGIN"
+        );
+    }
+}
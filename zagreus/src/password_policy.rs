@@ -0,0 +1,306 @@
+/// The rules the new passwords must follow (invitations and password resets), configured by the
+/// `PASSWORD_*` variables. The policy is injected into the context of the rendered templates
+/// (`password_policy`) so that the frontend can display, and check, the same rules.
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::{borrow::Cow, collections::HashSet, fs};
+use validator::{ValidationError, ValidationErrors};
+
+/// Shorter substrings would forbid too many passwords.
+const MIN_FORBIDDEN_SUBSTRING_LENGTH: usize = 3;
+
+lazy_static! {
+    pub static ref PASSWORD_POLICY: PasswordPolicy =
+        PasswordPolicy::from_env().expect("password policy couldn't be loaded");
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordPolicy {
+    /// In characters, not bytes.
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// From 0 (anything goes) to 4, see `strength`.
+    pub min_strength: u8,
+    /// Lower cased, the local part of the email of the user is forbidden as well.
+    pub forbidden_substrings: Vec<String>,
+    /// Upper cased hex SHA-1 hashes.
+    #[serde(skip)]
+    breached_passwords: HashSet<String>,
+}
+
+fn sha1(password: &str) -> String {
+    format!("{:X}", Sha1::digest(password.as_bytes()))
+}
+
+/// One password per line, either in clear or as an upper cased hex SHA-1 hash optionally
+/// followed by a count (the format of the Have I Been Pwned downloads: `<hash>:<count>`).
+fn parse_breached_passwords(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let hash = line.split(':').next().unwrap_or_default();
+
+            if hash.len() == 40 && hash.chars().all(|char| char.is_ascii_hexdigit()) {
+                hash.to_uppercase()
+            } else {
+                sha1(line)
+            }
+        })
+        .collect()
+}
+
+/// The zxcvbn estimate of the strength, where the user inputs (e.g. the parts of their email)
+/// count as words of its dictionaries. Scored from 0 (less than 10^3 guesses) to 4 (10^10
+/// guesses or more).
+pub fn strength(password: &str, user_inputs: &[&str]) -> u8 {
+    zxcvbn::zxcvbn(password, user_inputs)
+        .map(|entropy| entropy.score())
+        // Only blank passwords are rejected
+        .unwrap_or_default()
+}
+
+/// No password could be both long and short enough otherwise.
+fn check_lengths(min_length: usize, max_length: usize) -> Result<()> {
+    if min_length > max_length {
+        bail!(
+            "PASSWORD_MIN_LENGTH ({}) is greater than PASSWORD_MAX_LENGTH ({})",
+            min_length,
+            max_length
+        );
+    }
+
+    Ok(())
+}
+
+fn error(code: &'static str) -> ValidationError {
+    ValidationError::new(code)
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Result<Self> {
+        use zagreus_config::env::PASSWORD;
+
+        let breached_passwords = match PASSWORD::BREACHED_LIST_PATH() {
+            Some(path) => parse_breached_passwords(
+                fs::read_to_string(path.as_str())
+                    .with_context(|| format!("Couldn't read breached passwords {}", path))?
+                    .as_str(),
+            ),
+            None => HashSet::new(),
+        };
+
+        let (min_length, max_length) = (PASSWORD::MIN_LENGTH(), PASSWORD::MAX_LENGTH());
+
+        check_lengths(min_length, max_length)?;
+
+        Ok(PasswordPolicy {
+            min_length,
+            max_length,
+            require_lowercase: PASSWORD::REQUIRE_LOWERCASE(),
+            require_uppercase: PASSWORD::REQUIRE_UPPERCASE(),
+            require_digit: PASSWORD::REQUIRE_DIGIT(),
+            require_symbol: PASSWORD::REQUIRE_SYMBOL(),
+            min_strength: PASSWORD::MIN_STRENGTH(),
+            forbidden_substrings: PASSWORD::FORBIDDEN_SUBSTRINGS()
+                .unwrap_or_default()
+                .split(',')
+                .map(|substring| substring.trim().to_lowercase())
+                .filter(|substring| !substring.is_empty())
+                .collect(),
+            breached_passwords,
+        })
+    }
+
+    /// All the rules the password of the user breaks.
+    pub fn check(&self, password: &str, email: &str) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            let mut error = error("password_too_short");
+
+            error.add_param(Cow::from("min"), &self.min_length);
+
+            errors.push(error);
+        }
+
+        if length > self.max_length {
+            let mut error = error("password_too_long");
+
+            error.add_param(Cow::from("max"), &self.max_length);
+
+            errors.push(error);
+        }
+
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            errors.push(error("must_contain_lower_cased_chars"));
+        }
+
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            errors.push(error("must_contain_upper_cased_chars"));
+        }
+
+        if self.require_digit && !password.chars().any(char::is_numeric) {
+            errors.push(error("must_contain_numbers"));
+        }
+
+        if self.require_symbol && !password.chars().any(|char| !char.is_alphanumeric()) {
+            errors.push(error("must_contain_symbols"));
+        }
+
+        let lower_cased_password = password.to_lowercase();
+
+        let email_local_part = email.split('@').next().unwrap_or_default().to_lowercase();
+
+        let contains_forbidden_substring = self
+            .forbidden_substrings
+            .iter()
+            .chain(Some(&email_local_part))
+            .filter(|substring| substring.chars().count() >= MIN_FORBIDDEN_SUBSTRING_LENGTH)
+            .any(|substring| lower_cased_password.contains(substring.as_str()));
+
+        if contains_forbidden_substring {
+            errors.push(error("password_contains_forbidden_substring"));
+        }
+
+        let strength = strength(password, &[email_local_part.as_str(), email]);
+
+        if strength < self.min_strength {
+            let mut error = error("password_too_weak");
+
+            error.add_param(Cow::from("strength"), &strength);
+            error.add_param(Cow::from("min"), &self.min_strength);
+
+            errors.push(error);
+        }
+
+        if self.breached_passwords.contains(&sha1(password)) {
+            errors.push(error("password_breached"));
+        }
+
+        errors
+    }
+
+    /// The broken rules as the validation errors of the `password` field.
+    pub fn validate(&self, password: &str, email: &str) -> Result<(), ValidationErrors> {
+        let errors = self.check(password, email);
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let mut validation_errors = ValidationErrors::new();
+
+        for error in errors {
+            validation_errors.add("password", error);
+        }
+
+        Err(validation_errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_lengths, parse_breached_passwords, strength, PasswordPolicy};
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 64,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+            min_strength: 3,
+            forbidden_substrings: vec!["zagreus".to_string()],
+            breached_passwords: parse_breached_passwords(
+                "Passw0rd!\n\n7C4A8D09CA3762AF61E59520943DC26494F8941B:24230577\n",
+            ),
+        }
+    }
+
+    fn codes(policy: &PasswordPolicy, password: &str, email: &str) -> Vec<String> {
+        policy
+            .check(password, email)
+            .into_iter()
+            .map(|error| error.code.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_validates_password() {
+        let policy = policy();
+
+        assert!(policy
+            .validate("Val1d_passw0rd", "user@example.com")
+            .is_ok());
+        assert_eq!(
+            codes(&policy, "1nVal1d", "user@example.com"),
+            vec!["password_too_short", "password_too_weak"]
+        );
+        assert_eq!(
+            codes(&policy, "boringpassword", "user@example.com"),
+            vec![
+                "must_contain_upper_cased_chars",
+                "must_contain_numbers",
+                "password_too_weak"
+            ]
+        );
+        // Counted in characters, not bytes
+        assert_eq!(
+            codes(&policy, "Ééé1", "user@example.com"),
+            vec!["password_too_short", "password_too_weak"]
+        );
+    }
+
+    #[test]
+    fn it_forbids_the_personal_and_breached_passwords() {
+        let policy = policy();
+
+        assert_eq!(
+            codes(&policy, "Jane.Doe42", "jane.doe@example.com"),
+            vec!["password_contains_forbidden_substring", "password_too_weak"]
+        );
+        assert_eq!(
+            codes(&policy, "MyZagreus2021", "user@example.com"),
+            vec!["password_contains_forbidden_substring"]
+        );
+        assert_eq!(
+            codes(&policy, "Passw0rd!", "user@example.com"),
+            vec!["password_too_weak", "password_breached"]
+        );
+        // The SHA-1 of `123456`
+        assert!(
+            codes(&policy, "123456", "user@example.com").contains(&"password_breached".to_string())
+        );
+    }
+
+    #[test]
+    fn it_estimates_the_strength() {
+        assert_eq!(strength("", &[]), 0);
+        assert_eq!(strength("aaaaaaaa", &[]), 0);
+        // Dictionary words barely count, whatever the classes of characters used
+        assert_eq!(strength("Password1", &[]), 0);
+        assert_eq!(strength("abcdefgh1", &[]), 1);
+        assert_eq!(strength("Val1d_passw0rd", &[]), 3);
+        assert_eq!(strength("Correct-Horse-9", &[]), 4);
+        // So do the user inputs
+        assert_eq!(strength("jane.doe1984!", &[]), 4);
+        assert_eq!(strength("jane.doe1984!", &["jane.doe"]), 2);
+    }
+
+    #[test]
+    fn it_checks_the_lengths() {
+        assert!(check_lengths(8, 128).is_ok());
+        assert!(check_lengths(8, 8).is_ok());
+        assert!(check_lengths(129, 128).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{key, Decision, Limit, RateLimitBackend};
+
+/// Past this number of buckets, the full ones are dropped (a missing bucket is full).
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    full_at: Instant,
+}
+
+/// Keeps the buckets in the memory of the instance, each instance limits the requests it
+/// receives on its own.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryBackend {
+    async fn take(&self, route: &str, client: &str, limit: &Limit) -> Result<Decision> {
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key(route, client)).or_insert(Bucket {
+            tokens: f64::from(limit.capacity),
+            refilled_at: now,
+            full_at: now,
+        });
+
+        let mut tokens = limit.refill(bucket.tokens, now - bucket.refilled_at);
+
+        let decision = if tokens >= 1.0 {
+            tokens -= 1.0;
+
+            Decision::Allowed
+        } else {
+            Decision::Limited {
+                retry_after: limit.retry_after(tokens),
+            }
+        };
+
+        bucket.tokens = tokens;
+        bucket.refilled_at = now;
+        bucket.full_at = now
+            + Duration::from_secs_f64(
+                (f64::from(limit.capacity) - tokens) / limit.refill_per_second(),
+            );
+
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, Limit, MemoryBackend, RateLimitBackend};
+
+    #[actix_rt::test]
+    async fn it_limits_each_client() {
+        let backend = MemoryBackend::default();
+
+        let limit = Limit::per_minute(2).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                backend.take("login", "1.1.1.1", &limit).await.unwrap(),
+                Decision::Allowed
+            );
+        }
+
+        assert_eq!(
+            backend.take("login", "1.1.1.1", &limit).await.unwrap(),
+            Decision::Limited { retry_after: 30 }
+        );
+        assert_eq!(
+            backend.take("login", "2.2.2.2", &limit).await.unwrap(),
+            Decision::Allowed
+        );
+        assert_eq!(
+            backend.take("invitation", "1.1.1.1", &limit).await.unwrap(),
+            Decision::Allowed
+        );
+    }
+}
@@ -0,0 +1,89 @@
+/// Token bucket rate limiting (see `middlewares::rate_limit` for the routes it applies to).
+/// Every key gets a bucket holding up to `capacity` tokens, refilled continuously over `period`,
+/// each request takes a token out of it and is rejected when the bucket is empty.
+/// The buckets are kept either in memory (`memory::MemoryBackend`, a single instance) or in
+/// Postgres (`postgres::PostgresBackend`, shared by all the instances).
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::{sync::Arc, time::Duration};
+use zagreus_domain::db::PgPool;
+
+pub mod memory;
+pub mod postgres;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl Limit {
+    /// `None` when no request is allowed, which disables the limit.
+    pub fn per_minute(requests: u32) -> Option<Limit> {
+        if requests == 0 {
+            return None;
+        }
+
+        Some(Limit {
+            capacity: requests,
+            period: Duration::from_secs(60),
+        })
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.capacity) / self.period.as_secs_f64()
+    }
+
+    /// The tokens of a bucket once refilled for the elapsed time.
+    fn refill(&self, tokens: f64, elapsed: Duration) -> f64 {
+        (tokens + elapsed.as_secs_f64() * self.refill_per_second()).min(f64::from(self.capacity))
+    }
+
+    /// The number of seconds until a bucket holding `tokens` gets a whole token back.
+    fn retry_after(&self, tokens: f64) -> u64 {
+        ((1.0 - tokens) / self.refill_per_second()).ceil().max(1.0) as u64
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: u64 },
+}
+
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// Takes a token out of the bucket of the client for the route.
+    async fn take(&self, route: &str, client: &str, limit: &Limit) -> Result<Decision>;
+}
+
+fn key(route: &str, client: &str) -> String {
+    format!("{}|{}", route, client)
+}
+
+/// The backend of `RATE_LIMIT_BACKEND`.
+pub fn from_env(pool: &PgPool) -> Result<Arc<dyn RateLimitBackend>> {
+    match zagreus_config::env::RATE_LIMIT::BACKEND().as_str() {
+        "memory" => Ok(Arc::new(memory::MemoryBackend::default())),
+        "postgres" => Ok(Arc::new(postgres::PostgresBackend::new(pool.clone()))),
+        backend => Err(anyhow!("Unknown rate limit backend {}", backend)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Limit;
+
+    #[test]
+    fn it_refills_the_buckets_over_time() {
+        let limit = Limit::per_minute(6).unwrap();
+
+        assert_eq!(limit.refill(0.0, Duration::from_secs(10)), 1.0);
+        assert_eq!(limit.refill(5.5, Duration::from_secs(10)), 6.0);
+        assert_eq!(limit.retry_after(0.0), 10);
+        assert_eq!(limit.retry_after(0.5), 5);
+        assert_eq!(Limit::per_minute(0), None);
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use zagreus_domain::{db::PgPool, models::rate_limit_bucket::RateLimitBucket};
+
+use super::{key, Decision, Limit, RateLimitBackend};
+
+/// Number of requests between two deletions of the stale buckets of a route.
+const CLEANUP_INTERVAL: u64 = 1000;
+
+/// Keeps the buckets in the `rate_limit_buckets` table, shared by all the instances.
+#[derive(Debug)]
+pub struct PostgresBackend {
+    pool: PgPool,
+    requests: AtomicU64,
+}
+
+impl PostgresBackend {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresBackend {
+            pool,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// A bucket that hasn't been refilled for a whole period is full, it can be forgotten.
+    async fn delete_stale(&self, route: &str, limit: &Limit) -> Result<()> {
+        let period = chrono::Duration::from_std(limit.period)?;
+
+        RateLimitBucket::delete_stale(
+            &self.pool,
+            key(route, "").as_str(),
+            &(Utc::now().naive_utc() - period),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for PostgresBackend {
+    async fn take(&self, route: &str, client: &str, limit: &Limit) -> Result<Decision> {
+        if self.requests.fetch_add(1, Ordering::Relaxed) % CLEANUP_INTERVAL == CLEANUP_INTERVAL - 1
+        {
+            self.delete_stale(route, limit).await?;
+        }
+
+        let bucket = RateLimitBucket::take(
+            &self.pool,
+            key(route, client).as_str(),
+            f64::from(limit.capacity),
+            limit.refill_per_second(),
+        )
+        .await?;
+
+        if bucket.allowed {
+            return Ok(Decision::Allowed);
+        }
+
+        Ok(Decision::Limited {
+            retry_after: limit.retry_after(bucket.tokens),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Decision, Limit, PostgresBackend, RateLimitBackend};
+
+    #[actix_rt::test]
+    async fn it_shares_the_buckets_through_the_database() {
+        dotenv::dotenv().ok();
+
+        let pool = zagreus_domain::db::connect().await.unwrap();
+
+        let client = Uuid::new_v4().to_string();
+
+        let limit = Limit::per_minute(2).unwrap();
+
+        // Two instances
+        let backend = PostgresBackend::new(pool.clone());
+
+        let other_backend = PostgresBackend::new(pool);
+
+        assert_eq!(
+            backend.take("login", &client, &limit).await.unwrap(),
+            Decision::Allowed
+        );
+        assert_eq!(
+            other_backend.take("login", &client, &limit).await.unwrap(),
+            Decision::Allowed
+        );
+
+        match backend.take("login", &client, &limit).await.unwrap() {
+            Decision::Limited { retry_after } => assert!((1..=30).contains(&retry_after)),
+            Decision::Allowed => panic!("the bucket should be empty"),
+        }
+    }
+}
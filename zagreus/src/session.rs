@@ -0,0 +1,353 @@
+/// Authenticates the requests made on behalf of a user, using the access tokens issued by Hydra.
+/// The token is read from the `Authorization: Bearer <token>` header, and is introspected
+/// through the Hydra admin api.
+/// The admin requests are authenticated with a token granted the `ADMIN_SCOPE` scope, either a
+/// client credentials token, or the token of a user granted the `ADMIN_PERMISSION` permission.
+use actix_web::{
+    dev::Payload, http::header, http::StatusCode, web, FromRequest, HttpRequest, Result,
+};
+use ory_hydra_client::models::OAuth2TokenIntrospection;
+use std::{future::Future, pin::Pin};
+use thiserror::Error;
+use uuid::Uuid;
+use zagreus_domain::{
+    models::user::User,
+    repositories::{RoleRepository, UserRepository},
+};
+
+use crate::errors::{json_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("access token is missing")]
+    MissingToken,
+    #[error("access token is not active")]
+    InactiveToken,
+    #[error("access token introspection failed")]
+    IntrospectionFailed,
+    #[error("access token subject is not a valid uuid")]
+    WrongSubject,
+    #[error("user not found")]
+    UserNotFound,
+    #[error("user is disabled")]
+    UserDisabled,
+    #[error("user request error")]
+    UserError,
+    #[error("access token doesn't grant admin access")]
+    NotAdmin,
+}
+
+impl ErrorDetails for SessionError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            SessionError::MissingToken => (StatusCode::UNAUTHORIZED, "missing_access_token"),
+            SessionError::InactiveToken => (StatusCode::UNAUTHORIZED, "inactive_access_token"),
+            SessionError::IntrospectionFailed => {
+                (StatusCode::BAD_GATEWAY, "access_token_introspection_failed")
+            }
+            SessionError::WrongSubject => (StatusCode::UNAUTHORIZED, "wrong_access_token_subject"),
+            SessionError::UserNotFound => (StatusCode::UNAUTHORIZED, "user_not_found"),
+            SessionError::UserDisabled => (StatusCode::FORBIDDEN, "user_disabled"),
+            SessionError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+            SessionError::NotAdmin => (StatusCode::FORBIDDEN, "not_admin"),
+        }
+    }
+}
+
+json_response_error!(SessionError);
+
+/// The user authenticated by the access token.
+#[derive(Debug)]
+pub struct Session {
+    pub user: User,
+}
+
+fn access_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .map(|bearer| bearer.trim().to_string())
+}
+
+async fn introspect(
+    hydra: &dyn HydraAdmin,
+    access_token: &str,
+) -> Result<OAuth2TokenIntrospection, SessionError> {
+    let introspection = hydra
+        .introspect_token(access_token)
+        .await
+        .map_err(|_| SessionError::IntrospectionFailed)?;
+
+    if !introspection.active {
+        return Err(SessionError::InactiveToken);
+    }
+
+    Ok(introspection)
+}
+
+async fn get_user(users: &dyn UserRepository, subject: Option<&str>) -> Result<User, SessionError> {
+    let subject = subject.ok_or(SessionError::WrongSubject)?;
+
+    let user_id = Uuid::parse_str(subject).map_err(|_| SessionError::WrongSubject)?;
+
+    let user = users
+        .get_by_id(&user_id)
+        .await
+        .map_err(|_| SessionError::UserError)?;
+
+    let user = user.ok_or(SessionError::UserNotFound)?;
+
+    if user.is_disabled() {
+        return Err(SessionError::UserDisabled);
+    }
+
+    Ok(user)
+}
+
+async fn authenticate(
+    users: &dyn UserRepository,
+    hydra: &dyn HydraAdmin,
+    access_token: &str,
+) -> Result<Session, SessionError> {
+    let introspection = introspect(hydra, access_token).await?;
+
+    let user = get_user(users, introspection.sub.as_deref()).await?;
+
+    Ok(Session { user })
+}
+
+/// A client credentials token has the client as subject.
+fn is_client_credentials(introspection: &OAuth2TokenIntrospection) -> bool {
+    matches!(
+        (&introspection.sub, &introspection.client_id),
+        (Some(subject), Some(client_id)) if subject == client_id
+    )
+}
+
+async fn authenticate_admin(
+    users: &dyn UserRepository,
+    roles: &dyn RoleRepository,
+    hydra: &dyn HydraAdmin,
+    access_token: &str,
+) -> Result<AdminSession, SessionError> {
+    let introspection = introspect(hydra, access_token).await?;
+
+    let admin_scope = zagreus_config::env::ADMIN::SCOPE();
+
+    // A user token must carry the scope as well, so that any client the user logs into
+    // doesn't get an admin token
+    let has_admin_scope = introspection
+        .scope
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .any(|scope| scope == admin_scope);
+
+    if !has_admin_scope {
+        return Err(SessionError::NotAdmin);
+    }
+
+    if is_client_credentials(&introspection) {
+        return Ok(AdminSession {
+            subject: introspection.sub.unwrap_or_default(),
+        });
+    }
+
+    let user = get_user(users, introspection.sub.as_deref()).await?;
+
+    let admin_permission = zagreus_config::env::ADMIN::PERMISSION();
+
+    // Admin permissions are never scoped to a client
+    let permissions = roles
+        .get_permissions_by_user_id(&user.id, None)
+        .await
+        .map_err(|_| SessionError::UserError)?;
+
+    if !permissions
+        .iter()
+        .any(|permission| permission.name == admin_permission)
+    {
+        return Err(SessionError::NotAdmin);
+    }
+
+    Ok(AdminSession {
+        subject: user.id.to_string(),
+    })
+}
+
+impl FromRequest for Session {
+    type Config = ();
+    type Error = SessionError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let access_token = access_token(req);
+
+        let users = req.app_data::<web::Data<dyn UserRepository>>().cloned();
+
+        let hydra = req.app_data::<web::Data<dyn HydraAdmin>>().cloned();
+
+        Box::pin(async move {
+            let access_token = access_token.ok_or(SessionError::MissingToken)?;
+
+            let users = users.ok_or(SessionError::UserError)?;
+
+            let hydra = hydra.ok_or(SessionError::IntrospectionFailed)?;
+
+            authenticate(&**users, &**hydra, access_token.as_str()).await
+        })
+    }
+}
+
+/// An admin, either a client (authenticated with client credentials) or a user.
+#[derive(Clone, Debug)]
+pub struct AdminSession {
+    /// The client id or the user id.
+    pub subject: String,
+}
+
+impl FromRequest for AdminSession {
+    type Config = ();
+    type Error = SessionError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Already authenticated by the `RequireAdmin` middleware
+        if let Some(admin) = req.extensions().get::<AdminSession>() {
+            let admin = admin.clone();
+
+            return Box::pin(async move { Ok(admin) });
+        }
+
+        let access_token = access_token(req);
+
+        let users = req.app_data::<web::Data<dyn UserRepository>>().cloned();
+
+        let roles = req.app_data::<web::Data<dyn RoleRepository>>().cloned();
+
+        let hydra = req.app_data::<web::Data<dyn HydraAdmin>>().cloned();
+
+        Box::pin(async move {
+            let access_token = access_token.ok_or(SessionError::MissingToken)?;
+
+            let users = users.ok_or(SessionError::UserError)?;
+
+            let roles = roles.ok_or(SessionError::UserError)?;
+
+            let hydra = hydra.ok_or(SessionError::IntrospectionFailed)?;
+
+            authenticate_admin(&**users, &**roles, &**hydra, access_token.as_str()).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test, web::Data, FromRequest};
+    use ory_hydra_client::models::OAuth2TokenIntrospection;
+    use std::sync::Arc;
+    use zagreus_domain::repositories::{memory::MemoryRepository, RoleRepository, UserRepository};
+
+    use super::{is_client_credentials, AdminSession, SessionError};
+    use crate::hydra::{fake::FakeHydraAdmin, HydraAdmin};
+    use crate::test_utils::create_user;
+
+    fn introspection(sub: &str, client_id: &str) -> OAuth2TokenIntrospection {
+        OAuth2TokenIntrospection {
+            sub: Some(sub.to_string()),
+            client_id: Some(client_id.to_string()),
+            ..OAuth2TokenIntrospection::new(true)
+        }
+    }
+
+    #[test]
+    fn it_detects_client_credentials_tokens() {
+        assert!(is_client_credentials(&introspection("backend", "backend")));
+
+        assert!(!is_client_credentials(&introspection(
+            "8d6f4b5c-7c5e-4c8e-9a5e-0f3b8a3e2d1c",
+            "backend"
+        )));
+
+        assert!(!is_client_credentials(&OAuth2TokenIntrospection::new(true)));
+    }
+
+    #[actix_rt::test]
+    async fn it_authenticates_the_admin_clients_and_users() {
+        let repository = MemoryRepository::default();
+
+        let admin_id = create_user(&repository, "admin@example.com").await;
+        let user_id = create_user(&repository, "user@example.com").await;
+
+        let role_id = repository.insert_role("admin", &["zagreus:admin"]).unwrap();
+
+        repository.assign_role(&admin_id, &role_id, None).unwrap();
+
+        let fake_hydra = FakeHydraAdmin::default();
+
+        fake_hydra.add_token("admin", "backend", "backend", &["zagreus:admin"]);
+        fake_hydra.add_token("not-admin", "backend", "backend", &["openid"]);
+        fake_hydra.add_token(
+            "user-without-admin-scope",
+            "8d6f4b5c-7c5e-4c8e-9a5e-0f3b8a3e2d1c",
+            "frontend",
+            &["openid"],
+        );
+        fake_hydra.add_token(
+            "admin-user",
+            admin_id.to_string().as_str(),
+            "frontend",
+            &["openid", "zagreus:admin"],
+        );
+        fake_hydra.add_token(
+            "user-without-admin-permission",
+            user_id.to_string().as_str(),
+            "frontend",
+            &["openid", "zagreus:admin"],
+        );
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let roles: Data<dyn RoleRepository> = Data::from(Arc::new(repository) as Arc<_>);
+        let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(fake_hydra) as Arc<_>);
+
+        let extract = |access_token: &str| {
+            let req = test::TestRequest::get()
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", access_token)))
+                .app_data(users.clone())
+                .app_data(roles.clone())
+                .app_data(hydra.clone())
+                .to_http_request();
+
+            async move { AdminSession::extract(&req).await }
+        };
+
+        assert_eq!(extract("admin").await.unwrap().subject, "backend");
+
+        assert_eq!(
+            extract("admin-user").await.unwrap().subject,
+            admin_id.to_string()
+        );
+
+        assert!(matches!(
+            extract("user-without-admin-permission").await,
+            Err(SessionError::NotAdmin)
+        ));
+
+        assert!(matches!(
+            extract("not-admin").await,
+            Err(SessionError::NotAdmin)
+        ));
+
+        assert!(matches!(
+            extract("user-without-admin-scope").await,
+            Err(SessionError::NotAdmin)
+        ));
+
+        assert!(matches!(
+            extract("unknown").await,
+            Err(SessionError::InactiveToken)
+        ));
+    }
+}
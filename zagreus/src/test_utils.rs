@@ -0,0 +1,80 @@
+/// Helpers shared by the tests: the handlers are tested against a `MemoryRepository`, the few
+/// tests of the Postgres implementations connect to the database of `DATABASE_URL`.
+use actix_web::{
+    dev::{Service, ServiceRequest},
+    HttpMessage,
+};
+use chrono::Utc;
+use serde_json::Value;
+use tera::Tera;
+use uuid::Uuid;
+use zagreus_domain::{
+    db::PgPool,
+    models::{client::Client, user::UserProfile},
+    repositories::UserRepository,
+};
+
+use crate::session::AdminSession;
+
+lazy_static! {
+    /// The error page of the views, rendered by `middlewares::error_pages`.
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = Tera::default();
+
+        tera.add_raw_template(
+            "error.html",
+            "{{ code }}{% if csp_nonce %} {{ csp_nonce }}{% endif %}",
+        )
+        .expect("error template couldn't be parsed");
+
+        tera
+    };
+}
+
+pub async fn connect() -> PgPool {
+    dotenv::dotenv().ok();
+
+    zagreus_domain::db::connect().await.unwrap()
+}
+
+/// An empty profile.
+pub fn profile() -> UserProfile {
+    UserProfile {
+        name: None,
+        locale: None,
+        timezone: None,
+        metadata: Value::Object(Default::default()),
+    }
+}
+
+/// Creates a user with an empty profile, returns its id.
+pub async fn create_user(users: &dyn UserRepository, email: &str) -> Uuid {
+    users
+        .create(email, "password", &Utc::now().naive_utc(), &profile())
+        .await
+        .unwrap()
+}
+
+pub fn client(id: &str, redirect_uris: &[&str]) -> Client {
+    Client {
+        id: id.to_string(),
+        name: id.to_string(),
+        redirect_uris: redirect_uris.iter().map(ToString::to_string).collect(),
+        skip_consent: false,
+        created_at: Utc::now().naive_utc(),
+        updated_at: Utc::now().naive_utc(),
+    }
+}
+
+/// Authenticates every request as the `backend` admin client, as the `RequireAdmin` middleware
+/// would (to be passed to `App::wrap_fn`).
+pub fn as_admin<S>(req: ServiceRequest, srv: &S) -> S::Future
+where
+    S: Service<ServiceRequest>,
+{
+    req.extensions_mut().insert(AdminSession {
+        subject: String::from("backend"),
+    });
+
+    srv.call(req)
+}
@@ -0,0 +1,135 @@
+/// Time-based one-time passwords (RFC 6238) as generated by the authenticator apps,
+/// using the widely supported defaults: HMAC-SHA1, 6 digits and a 30 seconds period.
+use base32::Alphabet;
+use hmac::{Hmac, Mac, NewMac};
+use rand::{distributions, Rng, RngCore};
+use rand_core::OsRng;
+use sha1::Sha1;
+use url::Url;
+
+const ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+const DIGITS: u32 = 6;
+
+const PERIOD: u64 = 30;
+
+/// Number of periods accepted before and after the current one to tolerate clock drifts.
+const SKEW: u64 = 1;
+
+const SECRET_LENGTH: usize = 20;
+
+const RECOVERY_CODES_COUNT: usize = 10;
+
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Generates a new random base32 encoded secret.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+
+    OsRng.fill_bytes(&mut secret);
+
+    base32::encode(ALPHABET, &secret)
+}
+
+/// Returns the `otpauth://` uri that can be rendered as a QR code and scanned by the authenticator apps.
+pub fn provisioning_uri(secret: &str, account_name: &str) -> String {
+    let issuer = zagreus_config::env::TOTP::ISSUER();
+
+    let mut uri = Url::parse("otpauth://totp/").expect("otpauth base uri is valid");
+
+    uri.set_path(format!("/{}:{}", issuer, account_name).as_str());
+
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer.as_str())
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", DIGITS.to_string().as_str())
+        .append_pair("period", PERIOD.to_string().as_str());
+
+    uri.to_string()
+}
+
+fn generate_code(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts keys of any size");
+
+    mac.update(&counter.to_be_bytes());
+
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Checks a code against the secret at the given unix timestamp.
+pub fn verify(secret: &str, code: &str, timestamp: u64) -> bool {
+    let key = match base32::decode(ALPHABET, secret) {
+        Some(key) => key,
+        None => return false,
+    };
+
+    let counter = timestamp / PERIOD;
+
+    (counter.saturating_sub(SKEW)..=counter + SKEW)
+        .any(|counter| generate_code(&key, counter) == code)
+}
+
+/// Generates one-time recovery codes that can be used when the authenticator is lost.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            OsRng
+                .sample_iter(distributions::Alphanumeric)
+                .take(RECOVERY_CODE_LENGTH)
+                .map(char::from)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_code, generate_recovery_codes, verify, ALPHABET};
+
+    // Test vectors from the RFC 6238 appendix B (SHA1), truncated to 6 digits
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn it_generates_rfc_codes() {
+        assert_eq!(generate_code(RFC_KEY, 59 / 30), "287082");
+        assert_eq!(generate_code(RFC_KEY, 1111111109 / 30), "081804");
+        assert_eq!(generate_code(RFC_KEY, 1234567890 / 30), "005924");
+        assert_eq!(generate_code(RFC_KEY, 20000000000 / 30), "353130");
+    }
+
+    #[test]
+    fn it_verifies_codes_with_skew() {
+        let secret = base32::encode(ALPHABET, RFC_KEY);
+
+        assert!(verify(&secret, "081804", 1111111109));
+        assert!(verify(&secret, "081804", 1111111109 + 30));
+        assert!(!verify(&secret, "081804", 1111111109 + 90));
+        assert!(!verify(&secret, "000000", 1111111109));
+        assert!(!verify("not base32!", "081804", 1111111109));
+    }
+
+    #[test]
+    fn it_generates_recovery_codes() {
+        let recovery_codes = generate_recovery_codes();
+
+        assert_eq!(recovery_codes.len(), 10);
+        assert!(recovery_codes.iter().all(|code| code.len() == 10));
+    }
+}
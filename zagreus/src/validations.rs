@@ -1,58 +1,48 @@
-use validator::ValidationError;
+use actix_web::{http::StatusCode, HttpResponse};
+use thiserror::Error;
+use validator::{ValidationError, ValidationErrors};
 
-pub fn validate_terms_accepted(terms_accepted: &bool) -> Result<(), ValidationError> {
-    if !terms_accepted {
-        return Err(ValidationError::new("terms_not_accepted"));
-    }
+use crate::errors::{ErrorDetails, ErrorResponse};
 
-    Ok(())
+#[derive(Debug, Error)]
+pub enum PayloadError {
+    #[error("payload is invalid")]
+    InvalidPayload,
 }
 
-pub fn validate_password(password: &str) -> Result<(), ValidationError> {
-    let password_chars = password.chars();
-
-    if password.len() < 8 {
-        return Err(ValidationError::new("password_too_short"));
-    }
-
-    if !password_chars.clone().any(char::is_lowercase) {
-        return Err(ValidationError::new("must_contain_lower_cased_chars"));
-    }
-
-    if !password_chars.clone().any(char::is_uppercase) {
-        return Err(ValidationError::new("must_contain_upper_cased_chars"));
+impl ErrorDetails for PayloadError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            PayloadError::InvalidPayload => (StatusCode::BAD_REQUEST, "invalid_payload"),
+        }
     }
+}
 
-    if !password_chars.clone().any(char::is_numeric) {
-        return Err(ValidationError::new("must_contain_numbers"));
+pub fn validate_terms_accepted(terms_accepted: &bool) -> Result<(), ValidationError> {
+    if !terms_accepted {
+        return Err(ValidationError::new("terms_not_accepted"));
     }
 
     Ok(())
 }
 
+/// A 400 like the other errors, listing the validation errors per field.
+pub fn validation_errors_response(validation_errors: &ValidationErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        errors: Some(validation_errors.clone()),
+        ..ErrorResponse::new(&PayloadError::InvalidPayload)
+    })
+}
+
 /// This macro will automatically validate anything
 /// (that implements the `validator::Validate` trait)
 /// and return a 400 error if an error occured.
 macro_rules! validate {
     ($s:ident) => {
         if let Err(validation_errors) = $s.validate() {
-            let mut response = ::actix_web::HttpResponse::with_body(
-                ::actix_web::http::StatusCode::BAD_REQUEST,
-                ::actix_web::body::AnyBody::from_slice(
-                    ::serde_json::to_string(validation_errors.errors())
-                        .unwrap()
-                        .as_bytes(),
-                ),
-            );
-
-            let headers = response.headers_mut();
-
-            headers.append(
-                ::actix_web::http::header::CONTENT_TYPE,
-                ::actix_web::http::HeaderValue::from_static("application/json"),
-            );
-
-            return Ok(response);
+            return Ok(crate::validations::validation_errors_response(
+                &validation_errors,
+            ));
         };
     };
 }
@@ -61,10 +51,11 @@ pub(crate) use validate;
 
 #[cfg(test)]
 mod tests {
-    use validator::ValidationError;
+    use actix_web::{body::AnyBody, http::StatusCode};
+    use serde_json::{json, Value};
+    use validator::{ValidationError, ValidationErrors};
 
-    use super::validate_password;
-    use super::validate_terms_accepted;
+    use super::{validate_terms_accepted, validation_errors_response};
 
     #[test]
     fn it_validates_terms_accepted() {
@@ -76,23 +67,30 @@ mod tests {
     }
 
     #[test]
-    fn it_validates_password() {
-        assert_eq!(validate_password("Val1d_passw0rd"), Ok(()));
-        assert_eq!(
-            validate_password("1nVal1d"),
-            Err(ValidationError::new("password_too_short"))
-        );
-        assert_eq!(
-            validate_password("L00DPASSW0RD"),
-            Err(ValidationError::new("must_contain_lower_cased_chars"))
-        );
-        assert_eq!(
-            validate_password("s1lentpassw0rd"),
-            Err(ValidationError::new("must_contain_upper_cased_chars"))
-        );
+    fn it_returns_the_validation_errors_like_the_other_errors() {
+        let mut validation_errors = ValidationErrors::new();
+
+        validation_errors.add("terms_accepted", ValidationError::new("terms_not_accepted"));
+
+        let res = validation_errors_response(&validation_errors);
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let body: Value = match res.body() {
+            AnyBody::Bytes(bytes) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("unexpected body"),
+        };
+
         assert_eq!(
-            validate_password("Boringpassword"),
-            Err(ValidationError::new("must_contain_numbers"))
+            body,
+            json!({
+                "status": 400,
+                "code": "invalid_payload",
+                "message": "payload is invalid",
+                "errors": {
+                    "terms_accepted": [{ "code": "terms_not_accepted", "message": null, "params": {} }]
+                }
+            })
         );
     }
 }
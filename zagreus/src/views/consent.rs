@@ -0,0 +1,68 @@
+use actix_web::{get, http::StatusCode, web, HttpRequest, Responder, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::Validate;
+
+use super::HtmlTemplate;
+use crate::errors::{html_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+use crate::validations::validate;
+
+#[derive(Debug, Serialize)]
+struct ConsentTemplate {
+    consent_challenge: String,
+    client_name: String,
+    requested_scopes: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+enum ConsentError {
+    #[error("wrong consent_challenge argument")]
+    WrongChallenge,
+}
+
+impl ErrorDetails for ConsentError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            ConsentError::WrongChallenge => (StatusCode::BAD_REQUEST, "wrong_consent_challenge"),
+        }
+    }
+}
+
+html_response_error!(ConsentError);
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ConsentPayload {
+    #[validate(length(min = 1))]
+    consent_challenge: String,
+}
+
+/// Lets the user grant (a subset of) the scopes requested by a client, or reject the request.
+#[get("/consent")]
+pub async fn consent(
+    req: HttpRequest,
+    payload: web::Query<ConsentPayload>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<impl Responder> {
+    validate!(payload);
+
+    let consent_request = hydra
+        .get_consent_request(payload.consent_challenge.as_str())
+        .await
+        .map_err(|_| ConsentError::WrongChallenge)?;
+
+    let client_name = consent_request
+        .client
+        .and_then(|client| client.client_name.or(client.client_id))
+        .unwrap_or_default();
+
+    Ok(HtmlTemplate::new(
+        "consent.html",
+        ConsentTemplate {
+            consent_challenge: payload.into_inner().consent_challenge,
+            client_name,
+            requested_scopes: consent_request.requested_scope.unwrap_or_default(),
+        },
+    )
+    .respond_to(&req))
+}
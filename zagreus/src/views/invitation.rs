@@ -1,10 +1,11 @@
-use actix_web::{get, web, HttpRequest, Responder, ResponseError, Result};
+use actix_web::{get, http::StatusCode, web, HttpRequest, Responder, Result};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use validator::Validate;
-use zagreus_domain::{db::PgPool, models::invitation::Invitation};
+use zagreus_domain::{models::invitation::Invitation, repositories::InvitationRepository};
 
 use super::HtmlTemplate;
+use crate::errors::{html_response_error, ErrorDetails};
 use crate::validations::validate;
 
 #[derive(Debug, Serialize)]
@@ -19,9 +20,41 @@ enum InvitationError {
     NotFound,
     #[error("invitation already used")]
     AlreadyUsed,
+    #[error("invitation has expired")]
+    Expired,
+    #[error("invitation has been revoked")]
+    Revoked,
 }
 
-impl ResponseError for InvitationError {}
+impl ErrorDetails for InvitationError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            InvitationError::NotFound => (StatusCode::NOT_FOUND, "invitation_not_found"),
+            InvitationError::AlreadyUsed => (StatusCode::GONE, "invitation_already_used"),
+            InvitationError::Expired => (StatusCode::GONE, "invitation_expired"),
+            InvitationError::Revoked => (StatusCode::GONE, "invitation_revoked"),
+        }
+    }
+}
+
+html_response_error!(InvitationError);
+
+/// Only a pending invitation can be completed.
+fn check_pending(record: &Invitation) -> Result<(), InvitationError> {
+    if record.used_at.is_some() {
+        return Err(InvitationError::AlreadyUsed);
+    }
+
+    if record.is_revoked() {
+        return Err(InvitationError::Revoked);
+    }
+
+    if record.is_expired() {
+        return Err(InvitationError::Expired);
+    }
+
+    Ok(())
+}
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct InvitationPayload {
@@ -33,19 +66,18 @@ pub struct InvitationPayload {
 pub async fn invitation(
     req: HttpRequest,
     payload: web::Path<InvitationPayload>,
-    pool: web::Data<PgPool>,
+    invitations: web::Data<dyn InvitationRepository>,
 ) -> Result<impl Responder> {
     validate!(payload);
 
-    let invitation = Invitation::get_by_code(&pool, payload.challenge.as_str())
+    let invitation = invitations
+        .get_by_code(payload.challenge.as_str())
         .await
         .map_err(|_| InvitationError::NotFound)?;
 
     let invitation = invitation.ok_or(InvitationError::NotFound)?;
 
-    if invitation.used_at.is_some() {
-        return Err(InvitationError::AlreadyUsed.into());
-    }
+    check_pending(&invitation)?;
 
     Ok(HtmlTemplate::new(
         "invitation.html",
@@ -56,3 +88,52 @@ pub async fn invitation(
     )
     .respond_to(&req))
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+    use zagreus_domain::repositories::{memory::MemoryRepository, InvitationRepository};
+
+    use super::invitation;
+    use crate::middlewares::error_pages::ErrorPages;
+    use crate::test_utils::TEMPLATES;
+
+    #[actix_rt::test]
+    async fn it_rejects_an_expired_invitation() {
+        let repository = MemoryRepository::default();
+
+        InvitationRepository::create(
+            &repository,
+            "alice@example.com",
+            "code",
+            "client",
+            "https://example.com",
+            &(Utc::now() - Duration::minutes(1)).naive_utc(),
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let invitations: Data<dyn InvitationRepository> =
+            Data::from(Arc::new(repository) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(ErrorPages::new(&TEMPLATES))
+                .app_data(invitations)
+                .service(invitation),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/invitations/code")
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::GONE);
+        assert_eq!(test::read_body(res).await, "invitation_expired");
+    }
+}
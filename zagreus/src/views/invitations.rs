@@ -1,23 +1,34 @@
-use actix_web::{get, web, Responder, ResponseError, Result};
+use actix_web::{get, http::StatusCode, web, Responder, Result};
 use serde::Serialize;
 use thiserror::Error;
-use zagreus_domain::{db::PgPool, models::invitation::Invitation};
+use zagreus_domain::{models::invitation::Invitation, repositories::InvitationRepository};
 
 use super::HtmlTemplate;
 
+use crate::errors::{html_response_error, ErrorDetails};
+use crate::session::AdminSession;
+
 #[derive(Debug, Serialize)]
 struct RenderedInvitation {
+    id: String,
     email: String,
-    path: String,
+    expired: bool,
+    used: bool,
+    revoked: bool,
 }
 
 impl From<Invitation> for RenderedInvitation {
     fn from(invitation: Invitation) -> Self {
-        let path = format!("/invitations/{challenge}", challenge = invitation.code);
+        let expired = invitation.is_expired();
+
+        let revoked = invitation.is_revoked();
 
         Self {
+            id: invitation.id.to_string(),
             email: invitation.email,
-            path,
+            expired,
+            used: invitation.used_at.is_some(),
+            revoked,
         }
     }
 }
@@ -33,11 +44,25 @@ enum InvitationsError {
     NotFound,
 }
 
-impl ResponseError for InvitationsError {}
+impl ErrorDetails for InvitationsError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            InvitationsError::NotFound => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "invitations_not_found")
+            }
+        }
+    }
+}
+
+html_response_error!(InvitationsError);
 
 #[get("/invitations")]
-pub async fn invitations(pool: web::Data<PgPool>) -> Result<impl Responder> {
-    let invitations = Invitation::get_all(&pool)
+pub async fn invitations(
+    _admin: AdminSession,
+    invitations: web::Data<dyn InvitationRepository>,
+) -> Result<impl Responder> {
+    let invitations = invitations
+        .get_all()
         .await
         .map_err(|_| InvitationsError::NotFound)?
         .into_iter()
@@ -1,24 +1,34 @@
 use actix_web::{
-    get, http::header, web, HttpRequest, HttpResponse, Responder, ResponseError, Result,
+    get,
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, Responder, Result,
 };
 use oauth2::CsrfToken;
-use ory_hydra_client::{
-    apis::admin_api::{accept_login_request, get_login_request},
-    models::AcceptLoginRequest,
-};
-use rand::{distributions, thread_rng, Rng};
+use ory_hydra_client::models::AcceptLoginRequest;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
+use uuid::Uuid;
 use validator::Validate;
+use zagreus_domain::repositories::{LoginFailureRepository, UserRepository};
 
 use super::HtmlTemplate;
-use crate::hydra_configuration::{CLIENT, CONFIGURATION};
+use crate::codes;
+use crate::errors::{html_response_error, ErrorDetails};
+use crate::hydra::HydraAdmin;
+use crate::hydra_configuration::CLIENT;
+use crate::lockout;
 use crate::validations::validate;
 
 #[derive(Debug, Serialize)]
 struct LoginTemplate {
     login_challenge: String,
+    account_locked: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LoginTotpTemplate {
+    totp_challenge: String,
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -30,9 +40,25 @@ enum LoginError {
     WrongRequestUrl,
     #[error("login request returned a wrong redirect_uri")]
     WrongRedirectUri,
+    #[error("login request returned a wrong subject")]
+    WrongSubject,
+    #[error("user request error")]
+    UserError,
 }
 
-impl ResponseError for LoginError {}
+impl ErrorDetails for LoginError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            LoginError::WrongChallenge => (StatusCode::BAD_REQUEST, "wrong_login_challenge"),
+            LoginError::WrongRequestUrl => (StatusCode::BAD_GATEWAY, "wrong_request_url"),
+            LoginError::WrongRedirectUri => (StatusCode::BAD_GATEWAY, "wrong_redirect_uri"),
+            LoginError::WrongSubject => (StatusCode::BAD_GATEWAY, "wrong_login_subject"),
+            LoginError::UserError => (StatusCode::INTERNAL_SERVER_ERROR, "user_error"),
+        }
+    }
+}
+
+html_response_error!(LoginError);
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct LoginPayload {
@@ -40,18 +66,58 @@ pub struct LoginPayload {
     login_challenge: Option<String>,
 }
 
+/// Whether the user of a Hydra session (identified by the subject) can reuse it.
+#[derive(Debug, PartialEq)]
+enum SessionReuse {
+    Allowed,
+    /// The user has been deleted or disabled.
+    Denied,
+    /// The user is locked after too many failed attempts, the login form tells them.
+    Locked,
+}
+
+async fn session_reuse(
+    users: &dyn UserRepository,
+    login_failures: &dyn LoginFailureRepository,
+    subject: &str,
+) -> Result<SessionReuse, LoginError> {
+    let user_id = Uuid::parse_str(subject).map_err(|_| LoginError::WrongSubject)?;
+
+    let user = users
+        .get_by_id(&user_id)
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    let user = match user {
+        Some(user) if !user.is_disabled() => user,
+        _ => return Ok(SessionReuse::Denied),
+    };
+
+    let account_locked = lockout::is_account_locked(login_failures, user.email.as_str())
+        .await
+        .map_err(|_| LoginError::UserError)?;
+
+    if account_locked {
+        return Ok(SessionReuse::Locked);
+    }
+
+    Ok(SessionReuse::Allowed)
+}
+
 #[get("/login")]
-pub async fn login(req: HttpRequest, payload: web::Query<LoginPayload>) -> Result<HttpResponse> {
+pub async fn login(
+    req: HttpRequest,
+    payload: web::Query<LoginPayload>,
+    users: web::Data<dyn UserRepository>,
+    login_failures: web::Data<dyn LoginFailureRepository>,
+    hydra: web::Data<dyn HydraAdmin>,
+) -> Result<HttpResponse> {
     validate!(payload);
 
     let login_challenge = match payload.into_inner().login_challenge {
         Some(login_challenge) => login_challenge,
         None => {
-            let state: String = thread_rng()
-                .sample_iter(distributions::Alphanumeric)
-                .take(12)
-                .map(char::from)
-                .collect();
+            let state = codes::generate();
 
             let (redirect_to, _) = CLIENT.authorize_url(|| CsrfToken::new(state)).url();
 
@@ -61,7 +127,8 @@ pub async fn login(req: HttpRequest, payload: web::Query<LoginPayload>) -> Resul
         }
     };
 
-    let login_request = get_login_request(&CONFIGURATION, login_challenge.as_ref())
+    let login_request = hydra
+        .get_login_request(login_challenge.as_ref())
         .await
         .map_err(|_| LoginError::WrongChallenge)?;
 
@@ -74,16 +141,23 @@ pub async fn login(req: HttpRequest, payload: web::Query<LoginPayload>) -> Resul
 
     let (_, redirect_uri) = redirect_uri.ok_or(LoginError::WrongRedirectUri)?;
 
-    if login_request.skip {
-        let completed_request = accept_login_request(
-            &CONFIGURATION,
-            login_challenge.as_ref(),
-            Some(AcceptLoginRequest {
-                remember: Some(true),
-                ..AcceptLoginRequest::new(login_request.subject)
-            }),
-        )
-        .await;
+    // Deleted, disabled or locked users can't reuse their Hydra session, they get the login form
+    let session_reuse = if login_request.skip {
+        session_reuse(&**users, &**login_failures, login_request.subject.as_str()).await?
+    } else {
+        SessionReuse::Denied
+    };
+
+    if session_reuse == SessionReuse::Allowed {
+        let completed_request = hydra
+            .accept_login_request(
+                login_challenge.as_ref(),
+                AcceptLoginRequest {
+                    remember: Some(true),
+                    ..AcceptLoginRequest::new(login_request.subject)
+                },
+            )
+            .await;
 
         let redirect_to = match completed_request {
             Ok(completed_request) => completed_request.redirect_to,
@@ -95,5 +169,168 @@ pub async fn login(req: HttpRequest, payload: web::Query<LoginPayload>) -> Resul
             .finish());
     }
 
-    Ok(HtmlTemplate::new("login.html", LoginTemplate { login_challenge }).respond_to(&req))
+    Ok(HtmlTemplate::new(
+        "login.html",
+        LoginTemplate {
+            login_challenge,
+            account_locked: session_reuse == SessionReuse::Locked,
+        },
+    )
+    .respond_to(&req))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct LoginTotpPayload {
+    #[validate(length(min = 1))]
+    totp_challenge: String,
+}
+
+/// Second login step, displayed to the users with totp enabled once their password is verified.
+#[get("/login/totp")]
+pub async fn login_totp(
+    req: HttpRequest,
+    payload: web::Query<LoginTotpPayload>,
+) -> Result<HttpResponse> {
+    validate!(payload);
+
+    Ok(HtmlTemplate::new(
+        "login_totp.html",
+        LoginTotpTemplate {
+            totp_challenge: payload.into_inner().totp_challenge,
+        },
+    )
+    .respond_to(&req))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::{header, StatusCode},
+        test,
+        web::Data,
+        App,
+    };
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+    use zagreus_domain::{
+        models::login_failure::LoginFailureKind,
+        repositories::{memory::MemoryRepository, LoginFailureRepository, UserRepository},
+    };
+
+    use super::{login, session_reuse, SessionReuse};
+    use crate::hydra::{
+        fake::{FakeHydraAdmin, HydraCall},
+        HydraAdmin,
+    };
+    use crate::test_utils::create_user;
+
+    #[actix_rt::test]
+    async fn it_accepts_the_skipped_login_requests() {
+        let repository = MemoryRepository::default();
+
+        let user_id = create_user(&repository, "alice@example.com").await;
+
+        let fake_hydra = FakeHydraAdmin::default();
+
+        let subject = user_id.to_string();
+
+        fake_hydra.add_login_request(
+            "challenge",
+            true,
+            subject.as_str(),
+            "http://hydra.test/oauth2/auth?redirect_uri=https%3A%2F%2Fexample.com",
+        );
+
+        let users: Data<dyn UserRepository> = Data::from(Arc::new(repository.clone()) as Arc<_>);
+        let login_failures: Data<dyn LoginFailureRepository> =
+            Data::from(Arc::new(repository) as Arc<_>);
+        let hydra: Data<dyn HydraAdmin> = Data::from(Arc::new(fake_hydra.clone()) as Arc<_>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(users)
+                .app_data(login_failures)
+                .app_data(hydra)
+                .service(login),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/login?login_challenge=challenge")
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            "http://hydra.test/oauth2/auth?login_verifier=challenge"
+        );
+
+        assert!(matches!(
+            fake_hydra.calls().as_slice(),
+            [HydraCall::GetLoginRequest(_), HydraCall::AcceptLoginRequest(_, accept_login_request)]
+                if accept_login_request.subject == subject
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn it_denies_the_session_reuse_to_the_deleted_and_disabled_users() {
+        let repository = MemoryRepository::default();
+
+        let user_id = create_user(&repository, "alice@example.com").await;
+
+        let subject = user_id.to_string();
+
+        let active = session_reuse(&repository, &repository, subject.as_str())
+            .await
+            .unwrap();
+
+        UserRepository::update_disabled_at(&repository, &user_id, Some(&Utc::now().naive_utc()))
+            .await
+            .unwrap();
+
+        let disabled = session_reuse(&repository, &repository, subject.as_str())
+            .await
+            .unwrap();
+
+        UserRepository::delete(&repository, &user_id).await.unwrap();
+
+        let deleted = session_reuse(&repository, &repository, subject.as_str())
+            .await
+            .unwrap();
+
+        assert_eq!(active, SessionReuse::Allowed);
+        assert_eq!(disabled, SessionReuse::Denied);
+        assert_eq!(deleted, SessionReuse::Denied);
+    }
+
+    #[actix_rt::test]
+    async fn it_tells_the_locked_users_instead_of_reusing_their_session() {
+        let repository = MemoryRepository::default();
+
+        let user_id = create_user(&repository, "alice@example.com").await;
+
+        let now = Utc::now().naive_utc();
+
+        repository
+            .increment(LoginFailureKind::Email, "alice@example.com", &now)
+            .await
+            .unwrap();
+
+        repository
+            .update_locked_until(
+                LoginFailureKind::Email,
+                "alice@example.com",
+                &(now + Duration::minutes(15)),
+            )
+            .await
+            .unwrap();
+
+        let locked = session_reuse(&repository, &repository, user_id.to_string().as_str())
+            .await
+            .unwrap();
+
+        assert_eq!(locked, SessionReuse::Locked);
+    }
 }
@@ -1,13 +1,18 @@
-use actix_web::{http::StatusCode, HttpRequest, HttpResponse, Responder, ResponseError};
+use actix_web::{dev::Extensions, http::StatusCode, HttpRequest, HttpResponse, Responder};
 use serde::Serialize;
 use std::path::Path;
 use tera::{Context, Error, ErrorKind, Tera};
-use thiserror::Error;
 
+use crate::errors::{ErrorDetails, ErrorResponse};
+use crate::middlewares::{csrf::CsrfToken, security_headers::CspNonce};
+use crate::password_policy::PASSWORD_POLICY;
+
+pub mod consent;
 pub mod home;
 pub mod invitation;
 pub mod invitations;
 pub mod login;
+pub mod password_reset;
 
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
@@ -31,15 +36,13 @@ lazy_static! {
     };
 }
 
-#[derive(Error, Debug)]
-#[error("template couldn't be rendered")]
-struct TemplateError;
-
-impl ResponseError for TemplateError {}
+/// Template used to render the errors of the views, see `crate::errors`.
+const ERROR_TEMPLATE: &str = "error.html";
 
 pub struct HtmlTemplate<'a, T> {
     filepath: &'a str,
     template: T,
+    status: StatusCode,
 }
 
 impl<'a, T> HtmlTemplate<'a, T>
@@ -47,21 +50,40 @@ where
     T: Serialize,
 {
     fn new(filepath: &'a str, template: T) -> Self {
-        HtmlTemplate { filepath, template }
+        HtmlTemplate {
+            filepath,
+            template,
+            status: StatusCode::OK,
+        }
     }
-}
 
-impl<'a, T> Responder for HtmlTemplate<'a, T>
-where
-    T: Serialize,
-{
-    fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
-        let context = match Context::from_serialize(self.template).map_err(|_| TemplateError) {
+    fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+
+        self
+    }
+
+    /// Renders the template, with the password policy, and the csrf token and the csp nonce of
+    /// the request if any.
+    fn render(self, templates: &Tera, extensions: Option<&Extensions>) -> HttpResponse {
+        let mut context = match Context::from_serialize(self.template) {
             Ok(context) => context,
             Err(_) => return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
         };
 
-        let html_string = match TEMPLATES.render(self.filepath, &context) {
+        context.insert("password_policy", &*PASSWORD_POLICY);
+
+        if let Some(extensions) = extensions {
+            if let Some(CsrfToken(csrf_token)) = extensions.get() {
+                context.insert("csrf_token", csrf_token);
+            }
+
+            if let Some(CspNonce(csp_nonce)) = extensions.get() {
+                context.insert("csp_nonce", csp_nonce);
+            }
+        }
+
+        let html_string = match templates.render(self.filepath, &context) {
             Ok(html_string) => html_string,
             Err(Error {
                 kind: ErrorKind::TemplateNotFound(_),
@@ -70,8 +92,58 @@ where
             Err(_) => return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
         };
 
-        HttpResponse::Ok()
+        HttpResponse::build(self.status)
             .content_type("text/html;charset=utf-8")
             .body(html_string)
     }
 }
+
+/// An error of a view, its page is rendered again with the request once it goes through
+/// `middlewares::error_pages` (the `ResponseError` implementations don't get the request).
+pub type ErrorPage = ErrorResponse;
+
+/// Renders an error page with the `error.html` template, with the csrf token and the csp nonce
+/// of the request if any. Returns `None` when the template doesn't exist.
+pub fn render_error_page(
+    templates: &Tera,
+    error_page: &ErrorPage,
+    extensions: Option<&Extensions>,
+) -> Option<HttpResponse> {
+    if !templates
+        .get_template_names()
+        .any(|name| name == ERROR_TEMPLATE)
+    {
+        return None;
+    }
+
+    let res = HtmlTemplate::new(ERROR_TEMPLATE, error_page)
+        .with_status(
+            StatusCode::from_u16(error_page.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .render(templates, extensions);
+
+    Some(res)
+}
+
+/// Responds to an error of a view with a plain text message, the `error.html` page replaces it
+/// once the response goes through `middlewares::error_pages`.
+pub fn html_error_response<E: ErrorDetails>(error: &E) -> HttpResponse {
+    let (status, _) = error.details();
+
+    let mut res = HttpResponse::build(status)
+        .content_type("text/plain;charset=utf-8")
+        .body(error.to_string());
+
+    res.extensions_mut().insert(ErrorResponse::new(error));
+
+    res
+}
+
+impl<'a, T> Responder for HtmlTemplate<'a, T>
+where
+    T: Serialize,
+{
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        self.render(&TEMPLATES, Some(&req.extensions()))
+    }
+}
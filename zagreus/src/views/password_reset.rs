@@ -0,0 +1,87 @@
+use actix_web::{get, http::StatusCode, web, HttpRequest, Responder, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::Validate;
+use zagreus_domain::repositories::{PasswordResetRepository, UserRepository};
+
+use super::HtmlTemplate;
+use crate::errors::{html_response_error, ErrorDetails};
+use crate::validations::validate;
+
+#[derive(Debug, Serialize)]
+struct PasswordResetTemplate {
+    email: String,
+    password_reset_challenge: String,
+}
+
+#[derive(Debug, Error)]
+enum PasswordResetError {
+    #[error("password reset couldn't be found")]
+    NotFound,
+    #[error("password reset already used")]
+    AlreadyUsed,
+    #[error("password reset has expired")]
+    Expired,
+    #[error("user couldn't be found")]
+    UserNotFound,
+}
+
+impl ErrorDetails for PasswordResetError {
+    fn details(&self) -> (StatusCode, &'static str) {
+        match self {
+            PasswordResetError::NotFound => (StatusCode::NOT_FOUND, "password_reset_not_found"),
+            PasswordResetError::AlreadyUsed => (StatusCode::GONE, "password_reset_already_used"),
+            PasswordResetError::Expired => (StatusCode::GONE, "password_reset_expired"),
+            PasswordResetError::UserNotFound => (StatusCode::NOT_FOUND, "user_not_found"),
+        }
+    }
+}
+
+html_response_error!(PasswordResetError);
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetPayload {
+    #[validate(length(min = 1))]
+    challenge: String,
+}
+
+#[get("/password-resets/{challenge}")]
+pub async fn password_reset(
+    req: HttpRequest,
+    payload: web::Path<PasswordResetPayload>,
+    users: web::Data<dyn UserRepository>,
+    password_resets: web::Data<dyn PasswordResetRepository>,
+) -> Result<impl Responder> {
+    validate!(payload);
+
+    let password_reset = password_resets
+        .get_by_code(payload.challenge.as_str())
+        .await
+        .map_err(|_| PasswordResetError::NotFound)?;
+
+    let password_reset = password_reset.ok_or(PasswordResetError::NotFound)?;
+
+    if password_reset.used_at.is_some() {
+        return Err(PasswordResetError::AlreadyUsed.into());
+    }
+
+    if password_reset.is_expired() {
+        return Err(PasswordResetError::Expired.into());
+    }
+
+    let user = users
+        .get_by_id(&password_reset.user_id)
+        .await
+        .map_err(|_| PasswordResetError::UserNotFound)?;
+
+    let user = user.ok_or(PasswordResetError::UserNotFound)?;
+
+    Ok(HtmlTemplate::new(
+        "password_reset.html",
+        PasswordResetTemplate {
+            email: user.email,
+            password_reset_challenge: payload.into_inner().challenge,
+        },
+    )
+    .respond_to(&req))
+}
@@ -0,0 +1,288 @@
+/// WebAuthn (passkeys) ceremonies.
+/// The ceremonies' states and the registered passkeys are persisted as json strings,
+/// this module takes care of the (de)serialization so that the handlers only deal with strings.
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use thiserror::Error;
+use url::Url;
+use webauthn_rs::prelude::{
+    AuthenticationResult, Base64UrlSafeData, CreationChallengeResponse, Passkey,
+    PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse, Uuid as WebauthnUuid,
+};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+#[derive(Error, Debug)]
+pub enum WebauthnError {
+    #[error("webauthn state couldn't be serialized")]
+    StateNotSerialized,
+    #[error("webauthn state couldn't be deserialized")]
+    StateNotDeserialized,
+    #[error("webauthn ceremony couldn't be started")]
+    CeremonyNotStarted,
+    #[error("webauthn ceremony failed")]
+    CeremonyFailed,
+}
+
+/// Builds the relying party from the `URL` (the origin) and the `WEBAUTHN_*` variables.
+pub fn from_env() -> Result<Webauthn> {
+    let origin = Url::parse(zagreus_config::env::URL())?;
+
+    let rp_id = match zagreus_config::env::WEBAUTHN::RP_ID() {
+        Some(rp_id) => rp_id,
+        None => origin
+            .host_str()
+            .ok_or_else(|| anyhow!("URL has no host"))?
+            .to_string(),
+    };
+
+    let rp_name = zagreus_config::env::WEBAUTHN::RP_NAME();
+
+    let webauthn = WebauthnBuilder::new(rp_id.as_str(), &origin)?
+        .rp_name(rp_name.as_str())
+        .build()?;
+
+    Ok(webauthn)
+}
+
+pub fn serialize_passkey(passkey: &Passkey) -> Result<String, WebauthnError> {
+    serde_json::to_string(passkey).map_err(|_| WebauthnError::StateNotSerialized)
+}
+
+pub fn deserialize_passkey(passkey: &str) -> Result<Passkey, WebauthnError> {
+    serde_json::from_str(passkey).map_err(|_| WebauthnError::StateNotDeserialized)
+}
+
+/// Starts the registration of a new passkey for the user, the already registered
+/// passkeys are excluded so that an authenticator can't be registered twice.
+pub fn start_registration(
+    webauthn: &Webauthn,
+    user_id: &uuid::Uuid,
+    email: &str,
+    passkeys: &[Passkey],
+) -> Result<(CreationChallengeResponse, String), WebauthnError> {
+    let exclude_credentials = passkeys
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+
+    let (challenge, state) = webauthn
+        .start_passkey_registration(
+            WebauthnUuid::from_bytes(*user_id.as_bytes()),
+            email,
+            email,
+            Some(exclude_credentials),
+        )
+        .map_err(|_| WebauthnError::CeremonyNotStarted)?;
+
+    let state = serde_json::to_string(&state).map_err(|_| WebauthnError::StateNotSerialized)?;
+
+    Ok((challenge, state))
+}
+
+pub fn finish_registration(
+    webauthn: &Webauthn,
+    credential: &RegisterPublicKeyCredential,
+    state: &str,
+) -> Result<Passkey, WebauthnError> {
+    let state: PasskeyRegistration =
+        serde_json::from_str(state).map_err(|_| WebauthnError::StateNotDeserialized)?;
+
+    webauthn
+        .finish_passkey_registration(credential, &state)
+        .map_err(|_| WebauthnError::CeremonyFailed)
+}
+
+pub fn start_authentication(
+    webauthn: &Webauthn,
+    passkeys: &[Passkey],
+) -> Result<(RequestChallengeResponse, String), WebauthnError> {
+    let (challenge, state) = webauthn
+        .start_passkey_authentication(passkeys)
+        .map_err(|_| WebauthnError::CeremonyNotStarted)?;
+
+    let state = serde_json::to_string(&state).map_err(|_| WebauthnError::StateNotSerialized)?;
+
+    Ok((challenge, state))
+}
+
+/// A challenge for an email without passkeys (or without account), shaped like the real ones so
+/// that the response doesn't tell whether the account exists. It allows a single credential whose
+/// id is derived from the email: the same email always gets the same id.
+pub fn start_decoy_authentication(
+    webauthn: &Webauthn,
+    email: &str,
+    key: &[u8],
+) -> Result<RequestChallengeResponse, WebauthnError> {
+    let (challenge, _) = webauthn
+        .start_passkey_authentication(&[])
+        .map_err(|_| WebauthnError::CeremonyNotStarted)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+
+    mac.update(email.trim().to_lowercase().as_bytes());
+
+    let credential_id = Base64UrlSafeData::from(mac.finalize().into_bytes().to_vec());
+
+    // The allowed credentials' type isn't exported, they're added through their json form
+    let mut challenge =
+        serde_json::to_value(challenge).map_err(|_| WebauthnError::CeremonyNotStarted)?;
+
+    challenge["publicKey"]["allowCredentials"] = serde_json::json!([{
+        "type": "public-key",
+        "id": credential_id,
+    }]);
+
+    serde_json::from_value(challenge).map_err(|_| WebauthnError::CeremonyNotStarted)
+}
+
+pub fn finish_authentication(
+    webauthn: &Webauthn,
+    credential: &PublicKeyCredential,
+    state: &str,
+) -> Result<AuthenticationResult, WebauthnError> {
+    let state: PasskeyAuthentication =
+        serde_json::from_str(state).map_err(|_| WebauthnError::StateNotDeserialized)?;
+
+    webauthn
+        .finish_passkey_authentication(credential, &state)
+        .map_err(|_| WebauthnError::CeremonyFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+    use url::Url;
+    use uuid::Uuid;
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+    use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+    use super::{
+        deserialize_passkey, finish_authentication, finish_registration, serialize_passkey,
+        start_authentication, start_decoy_authentication, start_registration,
+    };
+
+    const ORIGIN: &str = "https://zagreus.localhost";
+
+    fn webauthn() -> Webauthn {
+        WebauthnBuilder::new("zagreus.localhost", &Url::parse(ORIGIN).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_registers_and_authenticates_with_a_passkey() {
+        let webauthn = webauthn();
+
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (challenge, state) =
+            start_registration(&webauthn, &Uuid::new_v4(), "user@example.com", &[]).unwrap();
+
+        let credential = authenticator
+            .do_registration(Url::parse(ORIGIN).unwrap(), challenge)
+            .unwrap();
+
+        let passkey = finish_registration(&webauthn, &credential, &state).unwrap();
+
+        // Passkeys go through the database as strings
+        let passkey = deserialize_passkey(&serialize_passkey(&passkey).unwrap()).unwrap();
+
+        let (challenge, state) =
+            start_authentication(&webauthn, slice::from_ref(&passkey)).unwrap();
+
+        let credential = authenticator
+            .do_authentication(Url::parse(ORIGIN).unwrap(), challenge)
+            .unwrap();
+
+        let result = finish_authentication(&webauthn, &credential, &state).unwrap();
+
+        assert_eq!(result.cred_id(), passkey.cred_id());
+    }
+
+    #[test]
+    fn it_rejects_an_assertion_for_another_challenge() {
+        let webauthn = webauthn();
+
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (challenge, state) =
+            start_registration(&webauthn, &Uuid::new_v4(), "user@example.com", &[]).unwrap();
+
+        let credential = authenticator
+            .do_registration(Url::parse(ORIGIN).unwrap(), challenge)
+            .unwrap();
+
+        let passkey = finish_registration(&webauthn, &credential, &state).unwrap();
+
+        let (challenge, _) = start_authentication(&webauthn, slice::from_ref(&passkey)).unwrap();
+
+        let (_, other_state) = start_authentication(&webauthn, &[passkey]).unwrap();
+
+        let credential = authenticator
+            .do_authentication(Url::parse(ORIGIN).unwrap(), challenge)
+            .unwrap();
+
+        assert!(finish_authentication(&webauthn, &credential, &other_state).is_err());
+    }
+
+    #[test]
+    fn it_starts_a_decoy_authentication_shaped_like_a_real_one() {
+        let webauthn = webauthn();
+
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (challenge, state) =
+            start_registration(&webauthn, &Uuid::new_v4(), "user@example.com", &[]).unwrap();
+
+        let credential = authenticator
+            .do_registration(Url::parse(ORIGIN).unwrap(), challenge)
+            .unwrap();
+
+        let passkey = finish_registration(&webauthn, &credential, &state).unwrap();
+
+        let (challenge, _) = start_authentication(&webauthn, &[passkey]).unwrap();
+
+        let decoy = |email: &str| {
+            let challenge = start_decoy_authentication(&webauthn, email, b"secret").unwrap();
+
+            serde_json::to_value(challenge).unwrap()
+        };
+
+        let challenge = serde_json::to_value(challenge).unwrap();
+
+        let unknown = decoy("unknown@example.com");
+
+        let keys = |value: &serde_json::Value| {
+            value["publicKey"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(&unknown), keys(&challenge));
+        assert_eq!(
+            unknown["publicKey"]["allowCredentials"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            unknown["publicKey"]["allowCredentials"],
+            decoy("Unknown@example.com ")["publicKey"]["allowCredentials"]
+        );
+        assert_ne!(
+            unknown["publicKey"]["allowCredentials"],
+            decoy("other@example.com")["publicKey"]["allowCredentials"]
+        );
+        assert_ne!(
+            unknown["publicKey"]["challenge"],
+            decoy("unknown@example.com")["publicKey"]["challenge"]
+        );
+    }
+}